@@ -0,0 +1,125 @@
+//! Compare le fonctionnement des portes avec un thread par porte
+//! et avec l'ordonnanceur, pour des péages de plus en plus grands.
+//!
+//! Pour chaque taille de péage, on mesure le temps de construction,
+//! le temps nécessaire pour servir un même nombre de véhicules par porte,
+//! et le temps d'affichage du péage.
+//! Chaque mesure est faite dans un nouveau processus, afin que les threads
+//! d'un péage mesuré auparavant ne faussent ni les temps ni le nombre de threads.
+//!
+//! cargo bench --bench gates
+
+use std::env;
+use std::process::Command;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use rand::Rng;
+use rsy40::gate::WaitingVehicle;
+use rsy40::scheduler::ExecutionModel;
+use rsy40::toll::Toll;
+use rsy40::vehicle::Vehicle;
+
+/// Nombre de véhicules envoyés à chaque porte
+const VEHICLES_PER_GATE: usize = 20;
+/// Variable d'environnement donnant au processus enfant la mesure à faire,
+/// sous la forme `<exécution>:<nombre de portes>`
+const CASE_VARIABLE: &str = "RSY40_BENCH_CASE";
+/// Facteur d'accélération : un service d'une quinzaine de secondes dure
+/// environ 2 ms en temps réel
+const ACCELERATION: u32 = 7200;
+
+struct Measure {
+    build: Duration,
+    drain: Duration,
+    display: Duration,
+    /// Nombre de threads lancés par le péage
+    threads: usize,
+}
+
+/// Nombre de threads du processus (Linux uniquement, 0 ailleurs)
+fn nb_threads() -> usize {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| status.lines()
+            .find_map(|line| line.strip_prefix("Threads:"))
+            .and_then(|n| n.trim().parse().ok()))
+        .unwrap_or(0)
+}
+
+fn run(model: ExecutionModel, nb_gates: usize) -> Measure {
+    let mut rng = rand::thread_rng();
+    let threads_before = nb_threads();
+    let start = Instant::now();
+    let toll = Toll::builder()
+        .nb_gates(nb_gates)
+        .acceleration_factor(ACCELERATION)
+        .execution(model)
+        .build()
+        .unwrap();
+    let build = start.elapsed();
+
+    // les véhicules sont placés directement dans les files pour ne mesurer
+    // que le service des portes, et non le choix de la porte
+    let vehicles: Vec<Vehicle> = (0..nb_gates * VEHICLES_PER_GATE)
+        .map(|_| rng.gen::<Vehicle>())
+        .collect();
+    let start = Instant::now();
+    for (i, vehicle) in vehicles.into_iter().enumerate() {
+        toll.gates[i % nb_gates].push(WaitingVehicle::new(vehicle, toll.clock.clone()));
+    }
+    while toll.gates.iter().any(|gate| !gate.idle()) {
+        sleep(Duration::from_micros(200));
+    }
+    let drain = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..100 {
+        let _ = toll.to_string();
+    }
+    let display = start.elapsed() / 100;
+
+    Measure { build, drain, display, threads: nb_threads().saturating_sub(threads_before) }
+}
+
+fn model_name(model: ExecutionModel) -> &'static str {
+    match model {
+        ExecutionModel::ThreadPerGate => "thread_per_gate",
+        ExecutionModel::Scheduler => "scheduler",
+    }
+}
+
+/// Fait la mesure demandée par le processus parent et en écrit la ligne
+fn run_case(case: &str) {
+    let (model, nb_gates) = case.split_once(':').expect("invalid bench case");
+    let model = match model {
+        "thread_per_gate" => ExecutionModel::ThreadPerGate,
+        _ => ExecutionModel::Scheduler,
+    };
+    let nb_gates: usize = nb_gates.parse().expect("invalid number of gates");
+    let measure = run(model, nb_gates);
+    println!(
+        "{:>6} | {:<14} | {:>8} | {:>12.2?} | {:>12.2?} | {:>12.2?}",
+        nb_gates, format!("{:?}", model), measure.threads, measure.build, measure.drain, measure.display,
+    );
+}
+
+fn main() {
+    if let Ok(case) = env::var(CASE_VARIABLE) {
+        run_case(&case);
+        return;
+    }
+    let exe = env::current_exe().expect("cannot find the bench executable");
+    println!(
+        "{:>6} | {:<14} | {:>8} | {:>12} | {:>12} | {:>12}",
+        "portes", "exécution", "threads", "construction", "service", "affichage",
+    );
+    for nb_gates in [10, 100, 1000] {
+        for model in [ExecutionModel::ThreadPerGate, ExecutionModel::Scheduler] {
+            let status = Command::new(&exe)
+                .env(CASE_VARIABLE, format!("{}:{}", model_name(model), nb_gates))
+                .status()
+                .expect("cannot start the bench process");
+            assert!(status.success(), "bench of {:?} with {} gates failed", model, nb_gates);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Zone d'approche du péage.
+//!
+//! Les véhicules arrivent sur les voies de l'autoroute et choisissent leur porte
+//! à un point de décision situé en amont des cabines. A ce moment, le conducteur
+//! ne voit que les portes proches de sa voie et ne distingue pas la longueur
+//! des files trop longues : il peut donc choisir une file qui n'est pas la plus
+//! courte. Il lui faut ensuite le temps de ralentir jusqu'à la fin de la file,
+//! pendant lequel les files évoluent.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::prelude::*;
+use crate::error::Error;
+use crate::gate::{Gate, WaitingVehicle};
+use crate::spillback::Upstream;
+use crate::toll::{choose_gate, choose_gate_by};
+use crate::toll_clock::TollClock;
+
+/// Géométrie de la zone d'approche et perception des conducteurs
+#[derive(Debug, Clone)]
+pub struct ApproachConfig {
+    /// Nombre de voies de l'autoroute en amont du péage
+    pub nb_lanes: usize,
+    /// Vitesse des véhicules au point de décision, en km/h.
+    /// Les véhicules décélèrent ensuite uniformément jusqu'à la fin de la file.
+    pub speed: f32,
+    /// Distance entre le point de décision et les cabines, en mètres
+    pub decision_distance: f32,
+    /// Longueur de file au-delà de laquelle le conducteur ne distingue plus
+    /// les files : toutes les files plus longues lui paraissent de même longueur
+    pub visibility: f32,
+    /// Nombre de portes visibles de part et d'autre de la position du véhicule
+    pub lateral_visibility: f32,
+    /// Longueur moyenne occupée par un véhicule dans une file, en mètres
+    pub vehicle_length: f32,
+}
+
+impl Default for ApproachConfig {
+    fn default() -> Self {
+        Self {
+            nb_lanes: 3,
+            speed: 50.0,
+            decision_distance: 150.0,
+            visibility: 60.0,
+            lateral_visibility: 3.0,
+            vehicle_length: 7.0,
+        }
+    }
+}
+
+impl ApproachConfig {
+    /// Nombre de véhicules que le conducteur croit voir dans la file de la porte
+    fn perceived_len(&self, gate: &Gate) -> usize {
+        let visible = (self.visibility / self.vehicle_length).ceil() as usize;
+        gate.nb_cars().min(visible)
+    }
+
+    /// Temps nécessaire pour aller du point de décision à la fin de la file
+    /// de la porte, en décélérant uniformément jusqu'à l'arrêt
+    fn travel_time(&self, gate: &Gate) -> Duration {
+        let tail = gate.nb_cars() as f32 * self.vehicle_length;
+        let distance = (self.decision_distance - tail).max(0.0);
+        let speed = (self.speed / 3.6).max(f32::EPSILON);
+        Duration::from_secs_f32(2.0 * distance / speed)
+    }
+}
+
+/// Véhicule roulant du point de décision vers la file de la porte choisie
+struct Approaching {
+    /// Instant (réel) d'arrivée à la fin de la file
+    due: Instant,
+    /// Numéro d'ordre départageant les véhicules arrivant au même instant
+    seq: u64,
+    /// Numéro de la porte choisie
+    gate: usize,
+    vehicle: WaitingVehicle,
+}
+
+impl PartialEq for Approaching {
+    fn eq(&self, other: &Self) -> bool {
+        (self.due, self.seq) == (other.due, other.seq)
+    }
+}
+
+impl Eq for Approaching {}
+
+impl PartialOrd for Approaching {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Approaching {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.due, self.seq).cmp(&(other.due, other.seq))
+    }
+}
+
+/// Zone d'approche du péage
+#[derive(Debug)]
+pub struct Approach {
+    pub config: ApproachConfig,
+    clock: TollClock,
+    /// Sender vers le thread de la zone d'approche
+    sender: Sender<(Instant, usize, WaitingVehicle)>,
+    /// Nombre de véhicules entre le point de décision et leur file
+    in_approach: Arc<AtomicUsize>,
+}
+
+impl Approach {
+    /// Crée la zone d'approche et lance son thread, qui fait entrer
+    /// les véhicules dans la file choisie à la fin de leur approche
+    pub fn new(
+        config: ApproachConfig,
+        gates: Vec<Gate>,
+        upstream: Option<Arc<Upstream>>,
+        clock: TollClock,
+    ) -> Self {
+        let (sender, receiver) = channel();
+        let in_approach = Arc::new(AtomicUsize::new(0));
+        launch_approach_thread(gates, upstream, receiver, in_approach.clone(), clock.clone());
+        Self { config, clock, sender, in_approach }
+    }
+
+    /// Fait arriver le véhicule au point de décision : il choisit sa porte
+    /// parmi celles qu'il voit, d'après la longueur des files qu'il perçoit,
+    /// puis roule jusqu'à la fin de la file de cette porte.
+    /// Renvoie une erreur si aucune porte ouverte n'accepte le véhicule.
+    pub fn arrive<R: Rng + ?Sized>(
+        &self, rng: &mut R, gates: &[Gate], vehicle: WaitingVehicle,
+    ) -> Result<(), Error> {
+        let lane = rng.gen_range(0..self.config.nb_lanes.max(1));
+        let position = (lane as f32 + 0.5) / self.config.nb_lanes.max(1) as f32 * gates.len() as f32;
+        let visible: Vec<Gate> = gates.iter()
+            .filter(|gate| (gate.id as f32 + 0.5 - position).abs() <= self.config.lateral_visibility)
+            .filter(|gate| gate.accepts(&vehicle.vehicle))
+            .cloned()
+            .collect();
+        let gate = choose_gate_by(&visible, &vehicle.vehicle, |gate| self.config.perceived_len(gate))
+            .or_else(|| choose_gate(gates, &vehicle.vehicle))
+            .ok_or(Error::NoGate(vehicle.vehicle.id))?;
+        let due = Instant::now() + self.clock.real_duration(self.config.travel_time(gate));
+        self.in_approach.fetch_add(1, AtomicOrdering::Relaxed);
+        self.sender.send((due, gate.id, vehicle)).unwrap();
+        Ok(())
+    }
+
+    /// Nombre de véhicules entre le point de décision et leur file
+    pub fn len(&self) -> usize {
+        self.in_approach.load(AtomicOrdering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Lance le thread qui fait entrer les véhicules dans la file de leur porte
+/// à la fin de leur approche. Si la porte choisie n'accepte plus le véhicule,
+/// celui-ci se rabat sur une autre porte. Si aucune porte ne l'accepte plus
+/// (toutes fermées ou en panne), il attend au bout de la zone d'approche
+/// et réessaie chaque seconde de simulation.
+///
+/// Un véhicule entrant dans une file plus longue que celle qu'il aurait choisie
+/// en connaissant exactement toutes les files est compté comme une erreur
+/// de choix de voie.
+fn launch_approach_thread(
+    gates: Vec<Gate>,
+    upstream: Option<Arc<Upstream>>,
+    arrivals: Receiver<(Instant, usize, WaitingVehicle)>,
+    in_approach: Arc<AtomicUsize>,
+    clock: TollClock,
+) {
+    thread::spawn(move || {
+        let mut approaching = BinaryHeap::new();
+        let mut seq = 0;
+        loop {
+            let timeout = match approaching.peek() {
+                Some(Reverse(Approaching { due, .. })) => due.saturating_duration_since(Instant::now()),
+                None => Duration::from_secs(3600),
+            };
+            match arrivals.recv_timeout(timeout) {
+                Ok((due, gate, vehicle)) => {
+                    approaching.push(Reverse(Approaching { due, seq, gate, vehicle }));
+                    seq += 1;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            while approaching.peek().is_some_and(|Reverse(v)| v.due <= Instant::now()) {
+                let Reverse(Approaching { gate, mut vehicle, .. }) = approaching.pop().unwrap();
+                in_approach.fetch_sub(1, AtomicOrdering::Relaxed);
+                let gate = match gates.iter().find(|g| g.id == gate) {
+                    Some(g) if g.accepts(&vehicle.vehicle) => Some(g),
+                    _ => choose_gate(&gates, &vehicle.vehicle),
+                };
+                let best = choose_gate(&gates, &vehicle.vehicle).map(|g| g.nb_cars());
+                vehicle.lane_choice_error = gate
+                    .zip(best)
+                    .is_some_and(|(gate, best)| gate.nb_cars() > best);
+                match (&upstream, gate) {
+                    (Some(upstream), _) => upstream.admit_to(&gates, gate, vehicle),
+                    (None, Some(gate)) => gate.push(vehicle),
+                    (None, None) => {
+                        // aucune porte choisie : une porte sera cherchée au prochain essai
+                        let due = Instant::now() + clock.real_duration(Duration::from_secs(1));
+                        in_approach.fetch_add(1, AtomicOrdering::Relaxed);
+                        approaching.push(Reverse(Approaching { due, seq, gate: usize::MAX, vehicle }));
+                        seq += 1;
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use super::*;
+
+    /// Porte dont la file compte le nombre de véhicules donné
+    fn gate_with(nb_cars: usize) -> Gate {
+        let mut rng = StdRng::seed_from_u64(0);
+        let gate = Gate::new(0);
+        for _ in 0..nb_cars {
+            gate.push(WaitingVehicle::new(rng.gen(), TollClock::default()));
+        }
+        gate
+    }
+
+    #[test]
+    fn long_queues_look_the_same() {
+        let config = ApproachConfig { visibility: 60.0, vehicle_length: 7.0, ..Default::default() };
+        // 60 m de visibilité : au plus 9 véhicules distingués
+        assert_eq!(config.perceived_len(&gate_with(0)), 0);
+        assert_eq!(config.perceived_len(&gate_with(5)), 5);
+        assert_eq!(config.perceived_len(&gate_with(9)), 9);
+        assert_eq!(config.perceived_len(&gate_with(20)), 9);
+    }
+
+    #[test]
+    fn travel_time_shrinks_as_the_queue_grows() {
+        let config = ApproachConfig {
+            speed: 54.0,
+            decision_distance: 150.0,
+            vehicle_length: 7.0,
+            ..Default::default()
+        };
+        // 150 m en décélérant uniformément depuis 15 m/s
+        assert_eq!(config.travel_time(&gate_with(0)), Duration::from_secs(20));
+        let time = config.travel_time(&gate_with(10)).as_secs_f32();
+        assert!((time - 2.0 * 80.0 / 15.0).abs() < 1e-3, "{}", time);
+        // la file remonte jusqu'au point de décision
+        assert_eq!(config.travel_time(&gate_with(30)), Duration::ZERO);
+    }
+}
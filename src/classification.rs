@@ -0,0 +1,242 @@
+//! Classification automatique des véhicules par capteurs.
+//!
+//! Chaque capteur donne une classe pour le véhicule qui passe, avec un taux
+//! d'erreur qui dépend de la classe réelle. Chaque capteur a autorité sur les
+//! classes qu'il sait distinguer, et la classe retenue est celle qui est facturée.
+
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+
+/// Type de capteur de classification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorKind {
+    /// Détecteur de hauteur, qui distingue les véhicules légers des autres
+    HeightDetector,
+    /// Compteur d'essieux, qui distingue les poids lourds entre eux
+    AxleCounter,
+    /// Barrière lumineuse, qui mesure la silhouette du véhicule
+    LightCurtain,
+}
+
+impl SensorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SensorKind::HeightDetector => "height_detector",
+            SensorKind::AxleCounter => "axle_counter",
+            SensorKind::LightCurtain => "light_curtain",
+        }
+    }
+}
+
+/// Capteur de classification et ses erreurs
+#[derive(Debug, Clone)]
+pub struct Sensor {
+    pub kind: SensorKind,
+    /// Matrice de confusion : `confusion[vraie][détectée]` est la probabilité
+    /// qu'un véhicule de la classe `vraie` soit vu dans la classe `détectée`.
+    /// Chaque ligne est normalisée au moment du tirage.
+    pub confusion: [[f64; 5]; 5],
+}
+
+/// Matrice de confusion d'un capteur parfait
+fn identity() -> [[f64; 5]; 5] {
+    let mut matrix = [[0.0; 5]; 5];
+    for (i, row) in matrix.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    matrix
+}
+
+/// Ajoute à la matrice une confusion symétrique entre deux classes
+fn confuse(matrix: &mut [[f64; 5]; 5], a: usize, b: usize, error: f64) {
+    matrix[a][a] -= error;
+    matrix[a][b] += error;
+    matrix[b][b] -= error;
+    matrix[b][a] += error;
+}
+
+impl Sensor {
+    /// Détecteur de hauteur confondant véhicules légers et intermédiaires
+    /// avec la probabilité donnée
+    pub fn height_detector(error: f64) -> Self {
+        let mut confusion = identity();
+        confuse(&mut confusion, 0, 1, error);
+        Self { kind: SensorKind::HeightDetector, confusion }
+    }
+
+    /// Compteur d'essieux confondant les poids lourds 2 et 4 essieux,
+    /// ainsi que les véhicules intermédiaires tractant une remorque
+    /// et les poids lourds 2 essieux
+    pub fn axle_counter(error: f64) -> Self {
+        let mut confusion = identity();
+        confuse(&mut confusion, 2, 3, error);
+        confuse(&mut confusion, 1, 2, error / 2.0);
+        Self { kind: SensorKind::AxleCounter, confusion }
+    }
+
+    /// Barrière lumineuse confondant motos et véhicules légers
+    pub fn light_curtain(error: f64) -> Self {
+        let mut confusion = identity();
+        confuse(&mut confusion, 4, 0, error);
+        Self { kind: SensorKind::LightCurtain, confusion }
+    }
+
+    /// Classe vue par le capteur pour un véhicule de la classe donnée
+    pub fn read<R: Rng + ?Sized>(&self, rng: &mut R, true_class: usize) -> usize {
+        let row = self.confusion[true_class].map(|p| p.max(0.0));
+        match WeightedIndex::new(row) {
+            Ok(dist) => dist.sample(rng),
+            Err(_) => true_class,
+        }
+    }
+}
+
+/// Ensemble des capteurs de classification d'une porte
+#[derive(Debug, Clone)]
+pub struct ClassificationConfig {
+    pub sensors: Vec<Sensor>,
+}
+
+impl Default for ClassificationConfig {
+    fn default() -> Self {
+        Self {
+            sensors: vec![
+                Sensor::height_detector(0.02),
+                Sensor::axle_counter(0.01),
+                Sensor::light_curtain(0.01),
+            ],
+        }
+    }
+}
+
+/// Résultat de la classification d'un véhicule
+#[derive(Debug, Clone)]
+pub struct Classification {
+    /// Classe retenue
+    pub detected: usize,
+    /// Classe vue par chacun des capteurs
+    pub readings: Vec<(SensorKind, usize)>,
+}
+
+impl Classification {
+    /// Classification d'un véhicule dont la classe est connue sans erreur
+    pub fn exact(class: usize) -> Self {
+        Self { detected: class, readings: Vec::new() }
+    }
+
+    /// Renvoie les lectures des capteurs au format `capteur=classe;capteur=classe`
+    pub fn readings_to_string(&self) -> String {
+        self.readings.iter()
+            .map(|(kind, class)| format!("{}={}", kind.name(), class))
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+}
+
+impl ClassificationConfig {
+    /// Classifie un véhicule de la classe donnée.
+    /// La classe retenue est obtenue ainsi :
+    /// - si la barrière lumineuse voit une moto, le véhicule est une moto ;
+    /// - sinon, si le détecteur de hauteur voit un véhicule léger,
+    ///   le véhicule est un véhicule léger ;
+    /// - sinon, le compteur d'essieux départage les véhicules intermédiaires
+    ///   et les poids lourds.
+    ///
+    /// Un capteur absent est ignoré ; sans aucun capteur, la classe réelle est retenue.
+    pub fn classify<R: Rng + ?Sized>(&self, rng: &mut R, true_class: usize) -> Classification {
+        let readings: Vec<(SensorKind, usize)> = self.sensors.iter()
+            .map(|sensor| (sensor.kind, sensor.read(rng, true_class)))
+            .collect();
+        let reading = |kind: SensorKind| readings.iter()
+            .find(|(k, _)| *k == kind)
+            .map(|&(_, class)| class);
+        let curtain = reading(SensorKind::LightCurtain);
+        let detected = match (curtain, reading(SensorKind::HeightDetector), reading(SensorKind::AxleCounter)) {
+            (Some(4), _, _) => 4,
+            (_, Some(0), _) => 0,
+            (_, _, Some(axles)) if (1..=3).contains(&axles) => axles,
+            (_, Some(height), _) => height,
+            (_, None, Some(axles)) => axles,
+            (_, None, None) => curtain.unwrap_or(true_class),
+        };
+        Classification { detected, readings }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use super::*;
+
+    fn perfect() -> ClassificationConfig {
+        ClassificationConfig {
+            sensors: vec![Sensor::height_detector(0.0), Sensor::axle_counter(0.0), Sensor::light_curtain(0.0)],
+        }
+    }
+
+    /// Capteur voyant toujours les véhicules de la classe `from` dans la classe `to`
+    fn misreading(kind: SensorKind, from: usize, to: usize) -> Sensor {
+        let mut confusion = identity();
+        confusion[from] = [0.0; 5];
+        confusion[from][to] = 1.0;
+        Sensor { kind, confusion }
+    }
+
+    #[test]
+    fn perfect_sensors_find_the_true_class() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for class in 0..5 {
+            let classification = perfect().classify(&mut rng, class);
+            assert_eq!(classification.detected, class);
+            assert_eq!(classification.readings.len(), 3);
+            assert!(classification.readings.iter().all(|&(_, reading)| reading == class));
+        }
+    }
+
+    #[test]
+    fn without_sensors_the_true_class_is_kept() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let classification = ClassificationConfig { sensors: Vec::new() }.classify(&mut rng, 3);
+        assert_eq!(classification.detected, 3);
+        assert!(classification.readings.is_empty());
+        assert_eq!(classification.readings_to_string(), "");
+    }
+
+    #[test]
+    fn light_curtain_seeing_a_motorcycle_prevails() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut config = perfect();
+        config.sensors[2] = misreading(SensorKind::LightCurtain, 0, 4);
+        let classification = config.classify(&mut rng, 0);
+        assert_eq!(classification.detected, 4);
+        assert_eq!(
+            classification.readings_to_string(),
+            "height_detector=0;axle_counter=0;light_curtain=4",
+        );
+    }
+
+    #[test]
+    fn axle_counter_decides_between_medium_vehicles_and_trucks() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut config = perfect();
+        config.sensors[1] = misreading(SensorKind::AxleCounter, 2, 3);
+        assert_eq!(config.classify(&mut rng, 2).detected, 3);
+        config.sensors[1] = misreading(SensorKind::AxleCounter, 1, 2);
+        assert_eq!(config.classify(&mut rng, 1).detected, 2);
+        // le compteur d'essieux ne revient pas sur un véhicule léger vu par le détecteur de hauteur
+        config.sensors[1] = misreading(SensorKind::AxleCounter, 0, 2);
+        assert_eq!(config.classify(&mut rng, 0).detected, 0);
+    }
+
+    #[test]
+    fn degenerate_confusion_row_reads_the_true_class() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut sensor = Sensor::axle_counter(0.0);
+        sensor.confusion[2] = [0.0; 5];
+        assert_eq!(sensor.read(&mut rng, 2), 2);
+        sensor.confusion[2] = [-1.0; 5];
+        assert_eq!(sensor.read(&mut rng, 2), 2);
+        let config = ClassificationConfig { sensors: vec![sensor] };
+        assert_eq!(config.classify(&mut rng, 2).detected, 2);
+    }
+}
@@ -0,0 +1,92 @@
+//! Péage desservant les deux sens de circulation.
+//!
+//! Chaque porte est affectée à un sens. Les voies réversibles, situées au centre
+//! du péage, changent de sens suivant un horaire pour suivre le sens de pointe :
+//! une voie à basculer cesse d'accepter des véhicules, puis change de sens
+//! une fois vide.
+
+use std::thread;
+use std::time::Duration;
+use crate::gate::Gate;
+use crate::logger::{LogMessage, LogSender};
+use crate::toll_clock::{SimpleTime, TollClock};
+
+/// Sens de circulation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[allow(unused)]
+pub enum Direction {
+    /// Sens 1
+    #[default]
+    Outbound = 0,
+    /// Sens 2
+    Inbound,
+}
+
+impl Direction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Outbound => "outbound",
+            Direction::Inbound => "inbound",
+        }
+    }
+}
+
+/// Changement de sens d'une voie réversible,
+/// destiné à être enregistré en base de données
+#[derive(Debug, Clone)]
+pub struct ReversalRecord {
+    pub gate: usize,
+    /// Heure à laquelle la voie a cessé d'accepter des véhicules
+    pub requested: SimpleTime,
+    /// Heure à laquelle la voie, vide, a changé de sens
+    pub done: SimpleTime,
+    /// Nouveau sens de la voie
+    pub direction: Direction,
+}
+
+/// Lance le thread qui fait changer de sens les voies réversibles
+/// suivant l'horaire donné (sens des voies réversibles pour chaque heure).
+/// Chaque minute de simulation, une voie qui n'est pas dans le sens prévu
+/// cesse d'accepter des véhicules ; elle change de sens dès qu'elle est vide.
+pub fn launch_reversal_thread(
+    gates: Vec<Gate>,
+    schedule: [Direction; 24],
+    clock: TollClock,
+    log_sender: Option<LogSender>,
+) {
+    if gates.is_empty() {
+        return;
+    }
+    thread::spawn(move || {
+        // heure à laquelle chaque voie a cessé d'accepter des véhicules
+        let mut requests: Vec<Option<SimpleTime>> = gates.iter().map(|_| None).collect();
+        loop {
+            thread::sleep(clock.real_duration(Duration::from_secs(60)));
+            let now = clock.now();
+            let target = schedule[now.hour as usize];
+            for (gate, request) in gates.iter().zip(requests.iter_mut()) {
+                if gate.direction() == target {
+                    // l'horaire est revenu au sens actuel avant que la voie soit vide
+                    gate.set_switching(false);
+                    *request = None;
+                    continue;
+                }
+                gate.set_switching(true);
+                let requested = request.get_or_insert(now.clone()).clone();
+                if gate.idle() {
+                    gate.set_direction(target);
+                    gate.set_switching(false);
+                    *request = None;
+                    if let Some(ref sender) = log_sender {
+                        let _ = sender.send(LogMessage::Reversal(ReversalRecord {
+                            gate: gate.id,
+                            requested,
+                            done: now.clone(),
+                            direction: target,
+                        }));
+                    }
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,170 @@
+//! Lois de probabilité paramétrables pour les temps de service aux portes
+
+use std::fs::read_to_string;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use rand::Rng;
+use rand::prelude::*;
+use rand_distr::{Gamma, LogNormal, Normal, Weibull};
+
+/// Plus long temps de service, en secondes, qu'une loi peut donner :
+/// les temps tirés au-delà sont ramenés à cette valeur
+pub const MAX_SERVICE_TIME: f64 = 24.0 * 3600.0;
+
+/// Loi suivie par un temps de service, exprimé en secondes
+#[derive(Debug, Clone)]
+#[allow(unused)]
+pub enum ServiceDistribution {
+    /// Loi normale tronquée par une valeur minimale
+    Normal { mean: f64, std_dev: f64, min: f64 },
+    /// Loi log-normale : le logarithme du temps suit une loi normale
+    /// d'espérance `mu` et d'écart-type `sigma`
+    LogNormal { mu: f64, sigma: f64 },
+    /// Loi gamma de paramètre de forme `shape` et d'échelle `scale`
+    Gamma { shape: f64, scale: f64 },
+    /// Loi de Weibull d'échelle `scale` et de forme `shape`
+    Weibull { scale: f64, shape: f64 },
+    /// Temps constant
+    Deterministic(Duration),
+    /// Temps tirés au hasard parmi des temps observés
+    Empirical(Vec<f64>),
+}
+
+impl ServiceDistribution {
+    /// Crée une loi empirique à partir d'un fichier CSV de temps observés.
+    /// Les temps (en secondes) sont lus dans la colonne `column` (numérotée à
+    /// partir de 0) ; les lignes dont cette colonne n'est pas un nombre positif,
+    /// comme une ligne d'en-tête, sont ignorées.
+    /// Les séparateurs acceptés sont la virgule et le point-virgule.
+    ///
+    /// ```no_run
+    /// # use rsy40::distribution::ServiceDistribution;
+    /// let dist = ServiceDistribution::from_csv("observations/gate_3.csv", 1)?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[allow(unused)]
+    pub fn from_csv<P: AsRef<Path>>(path: P, column: usize) -> io::Result<Self> {
+        let content = read_to_string(path)?;
+        let times: Vec<f64> = content.lines()
+            .filter_map(|line| line.split([',', ';']).nth(column))
+            .filter_map(|field| field.trim().parse::<f64>().ok())
+            .filter(|&t| t.is_finite() && t >= 0.0)
+            .collect();
+        if times.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no service time found in column {}", column),
+            ));
+        }
+        Ok(ServiceDistribution::Empirical(times))
+    }
+
+    /// Renvoie vrai si les paramètres de la loi permettent d'en tirer des temps,
+    /// dont l'ordre de grandeur ne dépasse pas `MAX_SERVICE_TIME`
+    pub fn is_valid(&self) -> bool {
+        let positive = |x: f64| x.is_finite() && x > 0.0;
+        let time = |x: f64| (0.0..=MAX_SERVICE_TIME).contains(&x);
+        match self {
+            ServiceDistribution::Normal { mean, std_dev, min } =>
+                *mean <= MAX_SERVICE_TIME && mean.is_finite()
+                    && time(*std_dev) && *min <= MAX_SERVICE_TIME && !min.is_nan(),
+            ServiceDistribution::LogNormal { mu, sigma } =>
+                *mu <= MAX_SERVICE_TIME.ln() && mu.is_finite() && sigma.is_finite() && *sigma >= 0.0,
+            ServiceDistribution::Gamma { shape, scale } =>
+                positive(*shape) && positive(*scale) && time(shape * scale),
+            ServiceDistribution::Weibull { scale, shape } =>
+                positive(*shape) && positive(*scale) && time(*scale),
+            ServiceDistribution::Deterministic(duration) => time(duration.as_secs_f64()),
+            ServiceDistribution::Empirical(times) =>
+                !times.is_empty() && times.iter().all(|&t| time(t)),
+        }
+    }
+
+    /// Tire un temps de service au hasard suivant cette loi, limité
+    /// à `MAX_SERVICE_TIME`. Les paramètres de la loi doivent être valides.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
+        let seconds = match self {
+            ServiceDistribution::Normal { mean, std_dev, min } =>
+                Normal::new(*mean, *std_dev).unwrap().sample(rng).max(*min),
+            ServiceDistribution::LogNormal { mu, sigma } =>
+                LogNormal::new(*mu, *sigma).unwrap().sample(rng),
+            ServiceDistribution::Gamma { shape, scale } =>
+                Gamma::new(*shape, *scale).unwrap().sample(rng),
+            ServiceDistribution::Weibull { scale, shape } =>
+                Weibull::new(*scale, *shape).unwrap().sample(rng),
+            ServiceDistribution::Deterministic(duration) => return *duration,
+            ServiceDistribution::Empirical(times) => *times.choose(rng).unwrap(),
+        };
+        Duration::from_secs_f64(seconds.clamp(0.0, MAX_SERVICE_TIME))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use crate::gate::LaneType;
+    use crate::toll::Toll;
+    use crate::vehicle::PaymentMean;
+    use super::*;
+
+    fn temp_csv(name: &str, content: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("rsy40-{}-{}.csv", name, std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn empirical_distribution_reads_a_csv_column() {
+        let path = temp_csv("times", "gate;time\n3;12.5\n3,-4\n4;abc\n5,30\n\n6;7\n");
+        let Ok(ServiceDistribution::Empirical(times)) = ServiceDistribution::from_csv(&path, 1) else {
+            panic!("the times should be read");
+        };
+        assert_eq!(times, vec![12.5, 30.0, 7.0]);
+
+        let dist = ServiceDistribution::Empirical(times.clone());
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            assert!(times.contains(&dist.sample(&mut rng).as_secs_f64()));
+        }
+
+        let error = ServiceDistribution::from_csv(&path, 2).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+        let error = ServiceDistribution::from_csv(&path, 1).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn gate_distribution_overrides_the_default_one() {
+        let seconds = |secs| ServiceDistribution::Deterministic(Duration::from_secs(secs));
+        let toll = Toll::builder()
+            .nb_gates(2)
+            .lane_type(1, LaneType::Mixed)
+            .payment_time(PaymentMean::Cash, seconds(10))
+            .gate_payment_time(1, PaymentMean::Cash, seconds(3))
+            .build()
+            .unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let cash = |gate: usize| toll.gates[gate].payment_times[PaymentMean::Cash as usize].clone();
+        assert_eq!(cash(0).unwrap().sample(&mut rng), Duration::from_secs(10));
+        assert_eq!(cash(1).unwrap().sample(&mut rng), Duration::from_secs(3));
+        // sans loi donnée, le temps de paiement suit la loi de la classe du véhicule
+        assert!(toll.gates[0].payment_times[PaymentMean::Toll as usize].is_none());
+    }
+
+    #[test]
+    fn sampled_times_are_bounded() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let dist = ServiceDistribution::Normal { mean: -5.0, std_dev: 0.0, min: -10.0 };
+        assert!(dist.is_valid());
+        assert_eq!(dist.sample(&mut rng), Duration::ZERO);
+        let dist = ServiceDistribution::LogNormal { mu: 9.0, sigma: 5.0 };
+        assert!(dist.is_valid());
+        for _ in 0..100 {
+            assert!(dist.sample(&mut rng).as_secs_f64() <= MAX_SERVICE_TIME);
+        }
+        assert!(!ServiceDistribution::Empirical(vec![1.0, f64::NAN]).is_valid());
+        assert!(!ServiceDistribution::Weibull { scale: 1e300, shape: 1.0 }.is_valid());
+    }
+}
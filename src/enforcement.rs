@@ -0,0 +1,281 @@
+//! Fraude au péage et contrôle par caméras et capteurs d'essieux
+
+use rand::distributions::Bernoulli;
+use rand::prelude::*;
+use crate::gate::LaneType;
+use crate::tariff::Tariff;
+use crate::vehicle::{PaymentMean, Vehicle};
+
+/// Comportement frauduleux d'un véhicule au péage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Evasion {
+    /// Le véhicule passe sous la barrière en suivant de près le véhicule précédent
+    Tailgating,
+    /// Le véhicule emprunte une voie réservée au télépéage sans avoir de badge
+    BadgeFraud,
+    /// Le conducteur déclare une classe de véhicule inférieure à la sienne
+    Underclassing,
+}
+
+impl Evasion {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Evasion::Tailgating => "tailgating",
+            Evasion::BadgeFraud => "badge_fraud",
+            Evasion::Underclassing => "underclassing",
+        }
+    }
+}
+
+/// Paramètres de la fraude et des contrôles.
+/// Les tables par type de voie sont indexées par `LaneType as usize`,
+/// puis par classe de véhicule.
+#[derive(Debug, Clone)]
+pub struct EnforcementConfig {
+    /// Probabilité qu'un véhicule passe en collant le véhicule précédent
+    pub tailgating: [[f64; 5]; 3],
+    /// Probabilité qu'un véhicule sans badge choisisse une voie de télépéage
+    pub badge_fraud: [f64; 5],
+    /// Probabilité qu'un conducteur déclare une classe inférieure
+    pub underclassing: [[f64; 5]; 3],
+    /// Probabilité qu'une caméra détecte un passage collé ou une fraude au badge
+    pub camera_accuracy: f64,
+    /// Probabilité que les capteurs d'essieux détectent une fausse déclaration de classe
+    pub axle_sensor_accuracy: f64,
+    /// Montant de l'amende, en euros, qui s'ajoute au péage dû
+    pub fine: f32,
+    /// Proportion des amendes effectivement recouvrées
+    pub fine_recovery_rate: f64,
+}
+
+impl Default for EnforcementConfig {
+    fn default() -> Self {
+        Self {
+            tailgating: [
+                [0.002, 0.001, 0.0005, 0.0005, 0.004],
+                [0.004, 0.002, 0.001, 0.001, 0.008],
+                [0.002, 0.001, 0.0005, 0.0005, 0.004],
+            ],
+            badge_fraud: [0.003, 0.002, 0.001, 0.001, 0.005],
+            underclassing: [
+                [0.0, 0.01, 0.02, 0.02, 0.0],
+                [0.0; 5],
+                [0.0, 0.01, 0.02, 0.02, 0.0],
+            ],
+            camera_accuracy: 0.9,
+            axle_sensor_accuracy: 0.95,
+            fine: 90.0,
+            fine_recovery_rate: 0.6,
+        }
+    }
+}
+
+/// Résultat du passage d'un véhicule à une porte du point de vue du paiement
+#[derive(Debug, Clone)]
+pub struct Assessment {
+    /// Fraude commise, None si le véhicule a payé normalement
+    pub evasion: Option<Evasion>,
+    /// Classe attribuée au véhicule par la classification automatique
+    /// (égale à sa vraie classe en l'absence de capteurs)
+    pub detected_class: usize,
+    /// Classe facturée au véhicule
+    pub charged_class: usize,
+    /// Montant payé à la porte
+    pub paid: f32,
+    /// Montant dû mais non payé à la porte
+    pub unpaid: f32,
+    /// Vrai si la fraude a été détectée
+    pub detected: bool,
+    /// Montant recouvré par l'amende (péage dû compris)
+    pub fine_recovered: f32,
+}
+
+fn happens<R: Rng + ?Sized>(rng: &mut R, p: f64) -> bool {
+    Bernoulli::new(p.clamp(0.0, 1.0)).unwrap().sample(rng)
+}
+
+impl EnforcementConfig {
+    /// Renvoie vrai si toutes les probabilités sont comprises entre 0 et 1
+    /// et si l'amende n'est pas négative
+    pub fn is_valid(&self) -> bool {
+        let probability = |p: &f64| (0.0..=1.0).contains(p);
+        self.tailgating.iter().flatten().all(probability)
+            && self.badge_fraud.iter().all(probability)
+            && self.underclassing.iter().flatten().all(probability)
+            && [self.camera_accuracy, self.axle_sensor_accuracy, self.fine_recovery_rate].iter().all(probability)
+            && self.fine.is_finite() && self.fine >= 0.0
+    }
+
+    /// Décide à l'arrivée du véhicule s'il compte frauder le télépéage,
+    /// ce qui lui permet d'emprunter les voies réservées au télépéage
+    pub fn badge_fraud<R: Rng + ?Sized>(&self, rng: &mut R, vehicle: &Vehicle) -> Option<Evasion> {
+        let eligible = vehicle.payment_mean == PaymentMean::Cash
+            && !vehicle.category.is_priority();
+        match eligible && happens(rng, self.badge_fraud[vehicle.type_num()]) {
+            true => Some(Evasion::BadgeFraud),
+            false => None,
+        }
+    }
+
+    /// Décide du comportement du véhicule à la porte, de ce qu'il paie
+    /// et de la détection d'une éventuelle fraude.
+    /// Un véhicule honnête paie le prix de la classe détectée.
+    pub fn assess<R: Rng + ?Sized>(
+        &self, rng: &mut R, vehicle: &Vehicle, lane: LaneType, tariff: &Tariff,
+        detected_class: usize,
+    ) -> Assessment {
+        let class = vehicle.type_num();
+        let due = tariff.price(vehicle);
+        let evasion = match vehicle.evasion {
+            _ if vehicle.category.is_priority() => None,
+            // la fraude au badge n'est possible que dans une voie réservée au télépéage :
+            // ailleurs, le fraudeur se comporte comme les autres véhicules
+            Some(evasion) if evasion != Evasion::BadgeFraud || lane == LaneType::Electronic =>
+                Some(evasion),
+            _ if happens(rng, self.tailgating[lane as usize][class]) =>
+                Some(Evasion::Tailgating),
+            _ if happens(rng, self.underclassing[lane as usize][class]) =>
+                Some(Evasion::Underclassing),
+            _ => None,
+        };
+        let (charged_class, paid) = match evasion {
+            None => (detected_class, tariff.price_for_class(vehicle, detected_class)),
+            Some(Evasion::Tailgating) | Some(Evasion::BadgeFraud) => (class, 0.0),
+            Some(Evasion::Underclassing) => {
+                // les poids lourds se déclarent dans la classe juste en-dessous,
+                // les autres véhicules en véhicule léger
+                let declared = match class {
+                    2 | 3 => class - 1,
+                    _ => 0,
+                };
+                (declared, tariff.price_for_class(vehicle, declared).min(due))
+            }
+        };
+        let detected = match evasion {
+            None => false,
+            Some(Evasion::Underclassing) => happens(rng, self.axle_sensor_accuracy),
+            Some(_) => happens(rng, self.camera_accuracy),
+        };
+        let unpaid = match evasion {
+            Some(_) => due - paid,
+            None => 0.0,
+        };
+        let fine_recovered = match detected && happens(rng, self.fine_recovery_rate) {
+            true => self.fine + unpaid,
+            false => 0.0,
+        };
+        Assessment {
+            evasion, detected_class, charged_class, paid, unpaid, detected, fine_recovered,
+        }
+    }
+}
+
+/// Passage d'un véhicule sans fraude : le véhicule paie la classe détectée
+pub fn honest_assessment(vehicle: &Vehicle, tariff: &Tariff, detected_class: usize) -> Assessment {
+    Assessment {
+        evasion: None,
+        detected_class,
+        charged_class: detected_class,
+        paid: tariff.price_for_class(vehicle, detected_class),
+        unpaid: 0.0,
+        detected: false,
+        fine_recovered: 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use super::*;
+
+    /// Configuration sans fraude spontanée, dont les contrôles réussissent toujours
+    fn strict() -> EnforcementConfig {
+        EnforcementConfig {
+            tailgating: [[0.0; 5]; 3],
+            badge_fraud: [0.0; 5],
+            underclassing: [[0.0; 5]; 3],
+            camera_accuracy: 1.0,
+            axle_sensor_accuracy: 1.0,
+            fine: 90.0,
+            fine_recovery_rate: 1.0,
+        }
+    }
+
+    /// Véhicule ordinaire de la classe donnée, ayant parcouru 100 km
+    fn vehicle(rng: &mut StdRng, class: usize, priority: bool) -> Vehicle {
+        let mut vehicle: Vehicle = rng.gen();
+        while vehicle.type_num() != class || vehicle.category.is_priority() != priority {
+            vehicle = rng.gen();
+        }
+        vehicle.nb_kilometres = 100.0;
+        vehicle.evasion = None;
+        vehicle
+    }
+
+    #[test]
+    fn badge_fraud_only_happens_in_electronic_lanes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let tariff = Tariff::default();
+        let mut fraudster = vehicle(&mut rng, 0, false);
+        fraudster.evasion = Some(Evasion::BadgeFraud);
+        let due = tariff.price(&fraudster);
+
+        let mixed = strict().assess(&mut rng, &fraudster, LaneType::Mixed, &tariff, 0);
+        assert_eq!(mixed.evasion, None);
+        assert_eq!((mixed.paid, mixed.unpaid, mixed.detected), (due, 0.0, false));
+
+        let electronic = strict().assess(&mut rng, &fraudster, LaneType::Electronic, &tariff, 0);
+        assert_eq!(electronic.evasion, Some(Evasion::BadgeFraud));
+        assert_eq!((electronic.paid, electronic.unpaid), (0.0, due));
+    }
+
+    #[test]
+    fn underclassed_trucks_declare_the_class_below() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let tariff = Tariff::default();
+        let mut config = strict();
+        config.underclassing[LaneType::Mixed as usize] = [1.0; 5];
+        let truck = vehicle(&mut rng, 2, false);
+        let assessment = config.assess(&mut rng, &truck, LaneType::Mixed, &tariff, 2);
+        assert_eq!(assessment.evasion, Some(Evasion::Underclassing));
+        assert_eq!((assessment.detected_class, assessment.charged_class), (2, 1));
+        assert_eq!(assessment.paid, tariff.price_for_class(&truck, 1));
+        assert_eq!(assessment.unpaid, tariff.price(&truck) - assessment.paid);
+    }
+
+    #[test]
+    fn detected_fraud_recovers_the_fine_and_the_toll() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let tariff = Tariff::default();
+        let mut config = strict();
+        config.tailgating = [[1.0; 5]; 3];
+        let car = vehicle(&mut rng, 0, false);
+        let due = tariff.price(&car);
+        let recovered = config.assess(&mut rng, &car, LaneType::Mixed, &tariff, 0);
+        assert_eq!(recovered.evasion, Some(Evasion::Tailgating));
+        assert_eq!((recovered.paid, recovered.unpaid, recovered.detected), (0.0, due, true));
+        assert_eq!(recovered.fine_recovered, 90.0 + due);
+
+        config.fine_recovery_rate = 0.0;
+        let lost = config.assess(&mut rng, &car, LaneType::Mixed, &tariff, 0);
+        assert!(lost.detected);
+        assert_eq!(lost.fine_recovered, 0.0);
+    }
+
+    #[test]
+    fn priority_vehicles_are_exempt() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let tariff = Tariff::default();
+        let mut config = strict();
+        config.tailgating = [[1.0; 5]; 3];
+        config.badge_fraud = [1.0; 5];
+        let mut ambulance = vehicle(&mut rng, 0, true);
+        ambulance.payment_mean = PaymentMean::Cash;
+        assert_eq!(config.badge_fraud(&mut rng, &ambulance), None);
+        ambulance.evasion = Some(Evasion::Tailgating);
+        let assessment = config.assess(&mut rng, &ambulance, LaneType::Mixed, &tariff, 0);
+        assert_eq!(assessment.evasion, None);
+        assert_eq!((assessment.paid, assessment.unpaid, assessment.fine_recovered), (0.0, 0.0, 0.0));
+        assert!(!assessment.detected);
+    }
+}
@@ -0,0 +1,231 @@
+//! Erreurs de la simulation.
+//!
+//! Les erreurs qui ne peuvent pas être traitées par le simulateur lui-même
+//! (base de données impossible à ouvrir, enregistrements impossibles à écrire,
+//! véhicule sans porte, run introuvable) sont renvoyées à la boucle principale, qui décide
+//! d'arrêter ou non la simulation.
+
+use std::fmt::{Display, Formatter};
+use crate::direction::Direction;
+use crate::logger::DatabaseError;
+use crate::sink::SinkError;
+use crate::vehicle::PaymentMean;
+
+/// Erreur renvoyée par le simulateur
+#[derive(Debug)]
+pub enum Error {
+    /// La base de données n'a pas pu être ouverte
+    Database(DatabaseError),
+    /// Le thread d'enregistrement n'a pas pu écrire des enregistrements.
+    /// Les enregistrements concernés sont perdus, les suivants sont écrits normalement.
+    Sink(SinkError),
+    /// Le thread d'enregistrement s'est arrêté : plus rien n'est enregistré
+    LoggerStopped,
+    /// Aucune porte ouverte n'accepte le véhicule dont le numéro est donné,
+    /// qui n'a pas été ajouté au péage
+    NoGate(u64),
+    /// Le run dont le numéro est donné (None pour le dernier run)
+    /// n'est pas enregistré dans la base de données
+    NoRun(Option<u64>),
+    /// La configuration du péage (ou du réseau) est invalide.
+    /// Contient tous les problèmes relevés.
+    Config(Vec<ConfigError>),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Database(e) => write!(f, "cannot open database: {}", e),
+            Error::Sink(e) => write!(f, "cannot write records: {}", e),
+            Error::LoggerStopped => write!(f, "the logger thread has stopped"),
+            Error::NoGate(vehicle) => write!(f, "no open gate accepts vehicle {}", vehicle),
+            Error::NoRun(Some(run)) => write!(f, "run {} is not in the database", run),
+            Error::NoRun(None) => write!(f, "the database contains no run"),
+            Error::Config(problems) => {
+                write!(f, "invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Database(e) => Some(e),
+            Error::Sink(e) => Some(e),
+            Error::LoggerStopped | Error::NoGate(_) | Error::NoRun(_) | Error::Config(_) => None,
+        }
+    }
+}
+
+impl From<DatabaseError> for Error {
+    fn from(e: DatabaseError) -> Self {
+        Error::Database(e)
+    }
+}
+
+impl From<SinkError> for Error {
+    fn from(e: SinkError) -> Self {
+        Error::Sink(e)
+    }
+}
+
+/// Problème relevé dans la configuration d'un péage ou d'un réseau
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// Le péage n'a aucune porte
+    NoGates,
+    /// Le facteur d'accélération est nul
+    ZeroAcceleration,
+    /// L'heure de départ de la simulation n'existe pas
+    InvalidStartTime,
+    /// Un paramètre concerne une porte qui n'existe pas
+    UnknownGate { setting: &'static str, gate: usize, nb_gates: usize },
+    /// Des véhicules arrivent dans ce sens aux heures données, mais aucune
+    /// voie acceptant tous les véhicules (voie mixte) ne dessert ce sens
+    NoMixedLane { direction: Direction, hours: Vec<usize> },
+    /// Le taux d'arrivée donné pour ce sens et cette heure est négatif ou n'est pas fini
+    InvalidRate { direction: Direction, hour: usize, rate: f64 },
+    /// Le planning d'ouverture ouvre plus de portes qu'il n'y en a à ouvrir
+    /// (hors voies de covoiturage)
+    ScheduleTooLarge { hour: usize, open: usize, nb_staffed: usize },
+    /// Un paramètre de la politique d'ouverture des portes est invalide
+    Staffing(&'static str),
+    /// Les paramètres de la loi du temps de paiement de la porte donnée
+    /// (None pour la loi de toutes les portes) sont invalides
+    InvalidDistribution { gate: Option<usize>, mean: PaymentMean },
+    /// Le modèle de panne de la porte donnée (None pour le modèle de toutes
+    /// les portes) ne donne aucun poids aux types de panne, ou sa probabilité
+    /// de changer de voie n'est pas comprise entre 0 et 1
+    InvalidFailureModel(Option<usize>),
+    /// Une probabilité des fraudes et des contrôles n'est pas comprise entre 0 et 1,
+    /// ou l'amende est négative
+    InvalidEnforcement,
+    /// Un taux de lecture ou de paiement du péage en flux libre
+    /// n'est pas compris entre 0 et 1
+    InvalidFreeFlow,
+    /// Les paramètres de la loi du temps de délivrance des tickets sont invalides
+    InvalidIssueTime,
+    /// Des voies réversibles ont un planning mais aucune voie n'est réversible
+    NoReversibleLane,
+    /// Le péage est à la fois en flux libre et une gare d'entrée
+    ConflictingModes,
+    /// Un paramètre qui doit être strictement positif ne l'est pas
+    NotPositive(&'static str),
+    /// Les enregistrements demandés n'ont aucune destination,
+    /// ou des destinations données seraient ignorées
+    Logging(&'static str),
+    /// Le réseau n'a aucun itinéraire de poids strictement positif
+    NoRoute,
+    /// Le taux d'entrée des véhicules dans le réseau donné pour cette heure
+    /// est négatif ou n'est pas fini
+    InvalidNetworkRate { hour: usize, rate: f64 },
+    /// L'itinéraire dont l'indice est donné ne passe par aucun péage
+    EmptyRoute(usize),
+    /// L'itinéraire dont l'indice est donné passe par un péage qui n'existe pas
+    UnknownPlaza { route: usize, plaza: usize },
+    /// Un itinéraire emprunte un tronçon qui n'existe pas
+    MissingSegment { from: usize, to: usize },
+    /// Problème relevé dans la configuration d'un péage du réseau
+    Plaza(usize, Box<ConfigError>),
+}
+
+/// Ecrit les portes concernées par un paramètre
+fn gates(gate: &Option<usize>) -> String {
+    match gate {
+        Some(gate) => format!("gate {}", gate),
+        None => "all gates".to_string(),
+    }
+}
+
+/// Ecrit une liste d'heures en regroupant les heures consécutives (`0-6, 20-23`)
+fn hour_ranges(hours: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &hour in hours {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == hour => *end = hour,
+            _ => ranges.push((hour, hour)),
+        }
+    }
+    ranges.iter()
+        .map(|&(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::NoGates => write!(f, "the plaza has no gate"),
+            ConfigError::ZeroAcceleration => write!(f, "the acceleration factor must not be 0"),
+            ConfigError::InvalidStartTime => write!(f, "the start time is not a valid time of day"),
+            ConfigError::UnknownGate { setting, gate, nb_gates } => write!(
+                f, "{} refers to gate {}, but the plaza only has gates 0 to {}",
+                setting, gate, nb_gates.saturating_sub(1),
+            ),
+            ConfigError::NoMixedLane { direction, hours } => write!(
+                f, "vehicles arrive {} at hours {}, but no mixed lane serves this direction \
+                (cash-paying vehicles could not pass)",
+                direction.name(), hour_ranges(hours),
+            ),
+            ConfigError::InvalidRate { direction, hour, rate } => write!(
+                f, "arrival rate {} for direction {} at hour {} is not a non-negative number",
+                rate, direction.name(), hour,
+            ),
+            ConfigError::ScheduleTooLarge { hour, open, nb_staffed } => write!(
+                f, "the staffing schedule opens {} gates at hour {}, but only {} gates can be staffed",
+                open, hour, nb_staffed,
+            ),
+            ConfigError::Staffing(problem) => write!(f, "staffing policy: {}", problem),
+            ConfigError::Logging(problem) => write!(f, "logging: {}", problem),
+            ConfigError::InvalidDistribution { gate, mean } => write!(
+                f, "the {} payment time distribution of {} has invalid parameters",
+                match mean {
+                    PaymentMean::Cash => "cash",
+                    PaymentMean::Toll => "electronic toll",
+                },
+                gates(gate),
+            ),
+            ConfigError::InvalidFailureModel(gate) => write!(
+                f, "the failure model of {} gives no weight to any outage \
+                or has a reroute probability outside [0, 1]", gates(gate),
+            ),
+            ConfigError::InvalidEnforcement => write!(
+                f, "the enforcement probabilities must lie in [0, 1] and the fine must not be negative",
+            ),
+            ConfigError::InvalidFreeFlow => write!(f, "the free-flow read and payment rates must lie in [0, 1]"),
+            ConfigError::InvalidIssueTime => write!(f, "the ticket issue time distribution has invalid parameters"),
+            ConfigError::NoReversibleLane => write!(f, "a reversal schedule is given without any reversible lane"),
+            ConfigError::ConflictingModes => write!(f, "a plaza cannot be both free-flow and an entry plaza"),
+            ConfigError::NotPositive(setting) => write!(f, "{} must be positive", setting),
+            ConfigError::NoRoute => write!(f, "the network needs at least one route with a positive weight"),
+            ConfigError::InvalidNetworkRate { hour, rate } => write!(
+                f, "network arrival rate {} at hour {} is not a non-negative number", rate, hour,
+            ),
+            ConfigError::EmptyRoute(route) => write!(f, "route {} goes through no plaza", route),
+            ConfigError::UnknownPlaza { route, plaza } => write!(f, "route {} goes through unknown plaza {}", route, plaza),
+            ConfigError::MissingSegment { from, to } => write!(f, "no segment from plaza {} to plaza {}", from, to),
+            ConfigError::Plaza(plaza, problem) => write!(f, "plaza {}: {}", plaza, problem),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hour_ranges_group_consecutive_hours() {
+        assert_eq!(hour_ranges(&[]), "");
+        assert_eq!(hour_ranges(&[5]), "5");
+        assert_eq!(hour_ranges(&[0, 1, 2, 3, 4, 5, 6, 20, 21, 22, 23]), "0-6, 20-23");
+        assert_eq!(hour_ranges(&[1, 3, 4, 8]), "1, 3-4, 8");
+    }
+}
@@ -0,0 +1,155 @@
+//! Journal complet des événements de la simulation.
+//!
+//! En plus des enregistrements de fin de passage, chaque étape du parcours
+//! d'un véhicule (arrivée, choix de la file, arrivée à la porte, paiement,
+//! départ) et chaque changement d'état d'une porte (ouverture, fermeture,
+//! changement de sens, panne, réparation) peut être enregistré, avec son heure
+//! simulée, dans la table `event`.
+
+use crate::logger::{LogMessage, LogSender};
+use crate::toll_clock::{SimpleTime, TollClock};
+
+/// Type d'événement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// Le véhicule arrive au péage
+    Arrival,
+    /// Le véhicule rejoint la file d'une porte
+    LaneChosen,
+    /// Le véhicule arrive à la porte et commence à payer
+    ServiceStart,
+    /// Le véhicule a payé (ou fraudé) et franchit la barrière
+    Payment,
+    /// Le véhicule quitte le péage
+    Departure,
+    /// Une voie réversible change de sens
+    LaneSwitch,
+    /// Une porte est ouverte
+    GateOpen,
+    /// Une porte est fermée
+    GateClose,
+    /// Une porte tombe en panne
+    Failure,
+    /// Une porte est réparée
+    Repair,
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Arrival => "arrival",
+            EventKind::LaneChosen => "lane_chosen",
+            EventKind::ServiceStart => "service_start",
+            EventKind::Payment => "payment",
+            EventKind::Departure => "departure",
+            EventKind::LaneSwitch => "lane_switch",
+            EventKind::GateOpen => "gate_open",
+            EventKind::GateClose => "gate_close",
+            EventKind::Failure => "failure",
+            EventKind::Repair => "repair",
+        }
+    }
+}
+
+/// Evénement de la simulation, destiné à être enregistré en base de données
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub time: SimpleTime,
+    pub kind: EventKind,
+    /// Numéro de la porte concernée, None si l'événement ne concerne aucune porte
+    pub gate: Option<usize>,
+    /// Numéro du véhicule concerné, None si l'événement ne concerne aucun véhicule
+    pub vehicle: Option<u64>,
+    /// Précisions sur l'événement, lisibles par un humain
+    pub detail: Option<String>,
+}
+
+/// Envoie les événements au thread d'enregistrement en db
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    sender: LogSender,
+    /// Horloge donnant l'heure des événements qui n'en ont pas
+    clock: TollClock,
+}
+
+impl EventLog {
+    pub fn new(sender: LogSender, clock: TollClock) -> Self {
+        Self { sender, clock }
+    }
+
+    /// Enregistre un événement survenu à l'heure donnée
+    pub fn emit_at(
+        &self,
+        time: SimpleTime,
+        kind: EventKind,
+        gate: Option<usize>,
+        vehicle: Option<u64>,
+        detail: Option<String>,
+    ) {
+        // le thread d'enregistrement ne s'arrête jamais avant la simulation
+        let _ = self.sender.send(LogMessage::Event(Event { time, kind, gate, vehicle, detail }));
+    }
+
+    /// Enregistre un événement survenant maintenant
+    pub fn emit(&self, kind: EventKind, gate: Option<usize>, vehicle: Option<u64>, detail: Option<String>) {
+        self.emit_at(self.clock.now(), kind, gate, vehicle, detail);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use crate::gate::{Gate, WaitingVehicle};
+    use crate::logger::{LoggerConfig, TollDatabase};
+    use crate::sink::MemorySink;
+    use crate::vehicle::Vehicle;
+    use super::*;
+
+    fn events(memory: &MemorySink) -> Vec<Event> {
+        memory.messages().into_iter()
+            .filter_map(|(_, message)| match message {
+                LogMessage::Event(event) => Some(event),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn passage_is_logged_step_by_step() {
+        let memory = MemorySink::new();
+        let db = TollDatabase::with_sink(Box::new(memory.clone()), LoggerConfig::default());
+        let mut gate = Gate::new(3);
+        gate.events = Some(EventLog::new(db.sender.clone(), TollClock::default()));
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut vehicle: Vehicle = rng.gen();
+        vehicle.identify();
+        let id = vehicle.id;
+
+        gate.push(WaitingVehicle::new(vehicle, TollClock::default()));
+        let next = gate.take_next().unwrap();
+        let departed = gate.end_service(gate.begin_service(&mut rng, next));
+        let paid = departed.assessment.paid;
+        gate.depart(departed);
+        gate.events.as_ref().unwrap().emit(EventKind::GateClose, Some(3), None, None);
+        db.flush();
+
+        let events = events(&memory);
+        assert_eq!(
+            events.iter().map(|e| e.kind).collect::<Vec<_>>(),
+            vec![
+                EventKind::LaneChosen,
+                EventKind::ServiceStart,
+                EventKind::Payment,
+                EventKind::Departure,
+                EventKind::GateClose,
+            ],
+        );
+        assert!(events.iter().all(|e| e.gate == Some(3)));
+        assert!(events[..4].iter().all(|e| e.vehicle == Some(id)));
+        assert_eq!(events[4].vehicle, None);
+        let detail = events[2].detail.as_deref().unwrap();
+        assert!(detail.starts_with(&format!("paid={};unpaid=0;evasion=none", paid)), "{}", detail);
+        assert!(events.windows(2).all(|pair| pair[0].time.as_secs() <= pair[1].time.as_secs()));
+    }
+}
@@ -0,0 +1,223 @@
+//! Pannes aléatoires des portes du péage et temps de réparation
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::thread;
+use std::time::Duration;
+use rand::distributions::{Bernoulli, WeightedIndex};
+use rand::prelude::*;
+use rand_distr::{Exp, Normal};
+use crate::gate::Gate;
+use crate::logger::{LogMessage, LogSender};
+use crate::toll::choose_gate;
+use crate::toll_clock::{SimpleTime, TollClock};
+use crate::vehicle::PaymentMean;
+
+/// Type de panne d'une porte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outage {
+    /// La barrière reste bloquée : plus aucun véhicule ne peut passer
+    StuckBarrier,
+    /// Le terminal de paiement est hors service : seul le télépéage fonctionne
+    TerminalDown,
+    /// Le lecteur de badge est hors service : seul le paiement au terminal fonctionne
+    BadgeReaderDown,
+}
+
+impl Outage {
+    /// Renvoie vrai si un véhicule utilisant ce moyen de paiement
+    /// peut tout de même passer la porte pendant la panne
+    pub fn accepts(&self, mean: &PaymentMean) -> bool {
+        match self {
+            Outage::StuckBarrier => false,
+            Outage::TerminalDown => matches!(mean, PaymentMean::Toll),
+            Outage::BadgeReaderDown => matches!(mean, PaymentMean::Cash),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outage::StuckBarrier => "stuck_barrier",
+            Outage::TerminalDown => "terminal_down",
+            Outage::BadgeReaderDown => "badge_reader_down",
+        }
+    }
+}
+
+/// Modèle de panne d'une porte.
+/// Les durées sont exprimées en temps de la simulation.
+#[derive(Debug, Clone)]
+pub struct FailureModel {
+    /// Temps moyen de bon fonctionnement entre deux pannes
+    /// (le temps avant la panne suit une loi exponentielle)
+    pub mean_time_to_failure: Duration,
+    /// Durée moyenne d'une réparation
+    pub mean_repair_time: Duration,
+    /// Ecart-type de la durée de réparation (loi normale tronquée à 1 minute)
+    pub repair_time_sd: Duration,
+    /// Pondération des types de panne, dans l'ordre :
+    /// barrière bloquée, terminal de paiement, lecteur de badge
+    pub outage_weights: [u32; 3],
+    /// Probabilité qu'un véhicule bloqué par la panne change de voie
+    /// plutôt que d'attendre la réparation
+    pub reroute_probability: f64,
+}
+
+impl Default for FailureModel {
+    fn default() -> Self {
+        Self {
+            mean_time_to_failure: Duration::from_secs(8 * 3600),
+            mean_repair_time: Duration::from_secs(15 * 60),
+            repair_time_sd: Duration::from_secs(5 * 60),
+            outage_weights: [2, 5, 3],
+            reroute_probability: 0.8,
+        }
+    }
+}
+
+impl FailureModel {
+    /// Renvoie vrai si le modèle permet de tirer le type des pannes
+    /// et si la probabilité de changer de voie est comprise entre 0 et 1
+    pub fn is_valid(&self) -> bool {
+        self.outage_weights.iter().any(|&weight| weight > 0)
+            && (0.0..=1.0).contains(&self.reroute_probability)
+    }
+
+    fn time_to_failure<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
+        let lambda = 1.0 / self.mean_time_to_failure.as_secs_f64().max(1.0);
+        Duration::from_secs_f64(Exp::new(lambda).unwrap().sample(rng))
+    }
+
+    fn repair_time<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
+        let dist = Normal::new(
+            self.mean_repair_time.as_secs_f64(),
+            self.repair_time_sd.as_secs_f64(),
+        ).unwrap();
+        Duration::from_secs_f64(dist.sample(rng).max(60.0))
+    }
+
+    fn outage<R: Rng + ?Sized>(&self, rng: &mut R) -> Outage {
+        static OUTAGES: [Outage; 3] =
+            [Outage::StuckBarrier, Outage::TerminalDown, Outage::BadgeReaderDown];
+        OUTAGES[WeightedIndex::new(self.outage_weights).unwrap().sample(rng)]
+    }
+}
+
+/// Panne terminée, destinée à être enregistrée en base de données
+#[derive(Debug, Clone)]
+pub struct OutageRecord {
+    pub gate: usize,
+    pub outage: Outage,
+    pub start: SimpleTime,
+    pub end: SimpleTime,
+    /// Nombre de véhicules ayant changé de voie à cause de la panne
+    pub rerouted: usize,
+}
+
+/// Prochain changement d'état d'une porte
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Event {
+    /// instant de l'événement, en secondes de simulation
+    time: u64,
+    gate: usize,
+}
+
+/// Lance le thread qui provoque les pannes et les réparations des portes.
+/// `models` associe à chaque porte (par sa position dans `gates`)
+/// son modèle de panne, ou None si la porte ne tombe jamais en panne.
+///
+/// Les pannes sont tirées à partir de la graine donnée.
+/// Si aucune porte n'a de modèle de panne, aucun thread n'est lancé.
+pub fn launch_failure_thread(
+    gates: Vec<Gate>,
+    models: Vec<Option<FailureModel>>,
+    clock: TollClock,
+    log_sender: Option<LogSender>,
+    seed: u64,
+) {
+    if models.iter().all(Option::is_none) {
+        return;
+    }
+    thread::spawn(move || {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let start = clock.now().as_secs();
+        let mut events = BinaryHeap::new();
+        for (gate, model) in models.iter().enumerate() {
+            if let Some(model) = model {
+                let time = start + model.time_to_failure(&mut rng).as_secs();
+                events.push(Reverse(Event { time, gate }));
+            }
+        }
+        // début et nombre de véhicules déviés des pannes en cours
+        let mut ongoing: Vec<Option<(SimpleTime, usize)>> = vec![None; gates.len()];
+        while let Some(Reverse(event)) = events.pop() {
+            let wait = event.time.saturating_sub(clock.now().as_secs());
+            thread::sleep(clock.real_duration(Duration::from_secs(wait)));
+            let now = clock.now();
+            let gate = &gates[event.gate];
+            let model = models[event.gate].as_ref().unwrap();
+            let next = match ongoing[event.gate].take() {
+                None => {
+                    let outage = model.outage(&mut rng);
+                    gate.set_outage(Some(outage));
+                    let rerouted = reroute_blocked(&gates, event.gate, model, &mut rng);
+                    ongoing[event.gate] = Some((now.clone(), rerouted));
+                    model.repair_time(&mut rng)
+                }
+                Some((start, rerouted)) => {
+                    let outage = gate.outage().unwrap();
+                    gate.set_outage(None);
+                    if let Some(ref sender) = log_sender {
+                        let _ = sender.send(LogMessage::Outage(OutageRecord {
+                            gate: gate.id,
+                            outage,
+                            start,
+                            end: now.clone(),
+                            rerouted,
+                        }));
+                    }
+                    model.time_to_failure(&mut rng)
+                }
+            };
+            events.push(Reverse(Event {
+                time: now.as_secs() + next.as_secs(),
+                gate: event.gate,
+            }));
+        }
+    });
+}
+
+/// Fait changer de voie les véhicules de la porte en panne qui ne peuvent plus
+/// y passer, avec la probabilité donnée par le modèle de panne.
+/// Les autres attendent la fin de la réparation.
+/// Renvoie le nombre de véhicules ayant changé de voie.
+fn reroute_blocked<R: Rng + ?Sized>(
+    gates: &[Gate], failed: usize, model: &FailureModel, rng: &mut R,
+) -> usize {
+    let outage = gates[failed].outage().unwrap();
+    let reroute = Bernoulli::new(model.reroute_probability.clamp(0.0, 1.0)).unwrap();
+    // la file est vidée puis le verrou relâché,
+    // car choisir une autre porte nécessite de consulter toutes les files
+    let waiting: Vec<_> = gates[failed].queue.lock().unwrap().drain(..).collect();
+    let mut staying = Vec::with_capacity(waiting.len());
+    let mut rerouted = 0;
+    for v in waiting {
+        if outage.accepts(&v.vehicle.payment_mean) || !reroute.sample(rng) {
+            staying.push(v);
+            continue;
+        }
+        match choose_gate(gates, &v.vehicle) {
+            Some(gate) if gate.id != gates[failed].id => {
+                gate.push(v);
+                rerouted += 1;
+            }
+            _ => staying.push(v),
+        }
+    }
+    // les véhicules restés gardent leur place devant ceux arrivés entre-temps
+    let mut queue = gates[failed].queue.lock().unwrap();
+    for v in staying.into_iter().rev() {
+        queue.push_front(v);
+    }
+    rerouted
+}
@@ -0,0 +1,313 @@
+//! Péage en flux libre : des portiques lisent les badges et les plaques
+//! d'immatriculation sans que les véhicules aient à s'arrêter.
+
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use rand::distributions::Bernoulli;
+use rand::prelude::*;
+use crate::enforcement::Assessment;
+use crate::tariff::Tariff;
+use crate::toll_clock::SimpleTime;
+use crate::vehicle::{PaymentMean, Vehicle};
+
+/// Paramètres du péage en flux libre
+#[derive(Debug, Clone)]
+pub struct FreeFlowConfig {
+    /// Probabilité que le badge d'un véhicule équipé soit lu par le portique
+    pub badge_read_rate: f64,
+    /// Probabilité que la plaque d'immatriculation soit lue
+    /// lorsque le badge n'a pas été lu
+    pub plate_read_rate: f64,
+    /// Probabilité qu'une plaque lue soit mal lue,
+    /// rendant le recouvrement impossible
+    pub misread_rate: f64,
+    /// Délai laissé aux véhicules sans badge pour payer après leur passage
+    pub post_payment_window: Duration,
+    /// Proportion des véhicules sans badge qui paient dans le délai imparti
+    pub post_payment_rate: f64,
+    /// Temps de passage sous le portique
+    pub crossing_time: Duration,
+    pub tariff: Tariff,
+}
+
+impl Default for FreeFlowConfig {
+    fn default() -> Self {
+        Self {
+            badge_read_rate: 0.995,
+            plate_read_rate: 0.97,
+            misread_rate: 0.01,
+            post_payment_window: Duration::from_secs(72 * 3600),
+            post_payment_rate: 0.9,
+            crossing_time: Duration::from_secs(2),
+            tariff: Tariff::default(),
+        }
+    }
+}
+
+/// Issue du passage d'un véhicule sous le portique
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassageOutcome {
+    /// Véhicule prioritaire, qui ne paie pas
+    Exempt,
+    /// Badge lu, le paiement est prélevé
+    Badge,
+    /// Badge non lu mais plaque lue et rattachée à l'abonnement du véhicule
+    PlateMatched,
+    /// Véhicule sans badge ayant payé dans le délai imparti
+    PostPaid,
+    /// Véhicule sans badge n'ayant pas payé dans le délai imparti
+    PostPaymentMissed,
+    /// Plaque mal lue : le passage ne peut pas être facturé
+    Misread,
+    /// Ni badge ni plaque lus
+    Unread,
+}
+
+impl PassageOutcome {
+    /// Renvoie vrai si le montant dû est encaissé
+    pub fn collected(&self) -> bool {
+        matches!(self, PassageOutcome::Badge | PassageOutcome::PlateMatched | PassageOutcome::PostPaid)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PassageOutcome::Exempt => "exempt",
+            PassageOutcome::Badge => "badge",
+            PassageOutcome::PlateMatched => "plate_matched",
+            PassageOutcome::PostPaid => "post_paid",
+            PassageOutcome::PostPaymentMissed => "post_payment_missed",
+            PassageOutcome::Misread => "misread",
+            PassageOutcome::Unread => "unread",
+        }
+    }
+}
+
+/// Passage d'un véhicule sous le portique,
+/// destiné à être enregistré en base de données
+#[derive(Debug, Clone)]
+pub struct FreeFlowPassage {
+    pub time: SimpleTime,
+    pub vehicle_type: usize,
+    pub outcome: PassageOutcome,
+    /// Montant dû, en euros
+    pub due: f32,
+    /// Montant encaissé, en euros
+    pub collected: f32,
+    /// Date limite de paiement pour les véhicules sans badge
+    pub payment_deadline: Option<SimpleTime>,
+}
+
+/// Statistiques cumulées d'un mode de péage
+#[derive(Debug, Clone, Default)]
+pub struct RevenueStats {
+    /// Nombre de véhicules arrivés au péage
+    pub arrived: u64,
+    /// Nombre de véhicules ayant franchi le péage
+    pub passed: u64,
+    /// Montant dû par les véhicules ayant franchi le péage
+    pub due: f64,
+    /// Montant encaissé
+    pub collected: f64,
+}
+
+impl RevenueStats {
+    /// Compte le passage d'un véhicule ayant franchi une barrière :
+    /// il devait ce qu'il a payé et ce qu'il n'a pas payé
+    pub fn add_passage(&mut self, assessment: &Assessment) {
+        self.passed += 1;
+        self.due += (assessment.paid + assessment.unpaid) as f64;
+        self.collected += assessment.paid as f64;
+    }
+
+    /// Recettes perdues : montant dû mais non encaissé
+    pub fn leakage(&self) -> f64 {
+        self.due - self.collected
+    }
+}
+
+/// Portique de péage en flux libre
+#[derive(Debug, Clone)]
+pub struct FreeFlowPlaza {
+    pub config: FreeFlowConfig,
+    pub stats: RevenueStats,
+    /// Nombre de passages pour chaque issue, dans l'ordre de `PassageOutcome`
+    pub outcomes: [u64; 7],
+}
+
+impl FreeFlowConfig {
+    /// Renvoie vrai si tous les taux sont des probabilités comprises entre 0 et 1
+    pub fn is_valid(&self) -> bool {
+        [self.badge_read_rate, self.plate_read_rate, self.misread_rate, self.post_payment_rate].iter()
+            .all(|p| (0.0..=1.0).contains(p))
+    }
+}
+
+impl FreeFlowPlaza {
+    pub fn new(config: FreeFlowConfig) -> Self {
+        Self { config, stats: RevenueStats::default(), outcomes: [0; 7] }
+    }
+
+    /// Fait passer le véhicule sous le portique à l'heure donnée
+    pub fn pass<R: Rng + ?Sized>(
+        &mut self, rng: &mut R, vehicle: &Vehicle, time: SimpleTime,
+    ) -> FreeFlowPassage {
+        let config = &self.config;
+        let happens = |rng: &mut R, p: f64| Bernoulli::new(p.clamp(0.0, 1.0)).unwrap().sample(rng);
+        let badged = matches!(vehicle.payment_mean, PaymentMean::Toll);
+        let outcome = if vehicle.category.is_priority() {
+            PassageOutcome::Exempt
+        } else if badged && happens(rng, config.badge_read_rate) {
+            PassageOutcome::Badge
+        } else if !happens(rng, config.plate_read_rate) {
+            PassageOutcome::Unread
+        } else if happens(rng, config.misread_rate) {
+            PassageOutcome::Misread
+        } else if badged {
+            PassageOutcome::PlateMatched
+        } else if happens(rng, config.post_payment_rate) {
+            PassageOutcome::PostPaid
+        } else {
+            PassageOutcome::PostPaymentMissed
+        };
+        let due = config.tariff.price(vehicle);
+        let collected = match outcome.collected() {
+            true => due,
+            false => 0.0,
+        };
+        self.stats.arrived += 1;
+        self.stats.passed += 1;
+        self.stats.due += due as f64;
+        self.stats.collected += collected as f64;
+        self.outcomes[outcome as usize] += 1;
+        let payment_deadline = match badged || outcome == PassageOutcome::Exempt {
+            true => None,
+            false => Some(time.clone() + config.post_payment_window),
+        };
+        FreeFlowPassage {
+            time,
+            vehicle_type: vehicle.type_num(),
+            outcome,
+            due,
+            collected,
+            payment_deadline,
+        }
+    }
+}
+
+/// Comparaison entre le péage à barrières et le flux libre sur le même trafic
+#[derive(Debug, Clone)]
+pub struct ModeComparison {
+    /// Durée de simulation couverte par la comparaison
+    pub elapsed: Duration,
+    pub barrier: RevenueStats,
+    pub free_flow: RevenueStats,
+}
+
+impl Display for ModeComparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let hours = (self.elapsed.as_secs_f64() / 3600.0).max(f64::EPSILON);
+        writeln!(f, "{:<22}{:>14}{:>14}", "", "barrières", "flux libre")?;
+        writeln!(f, "{:<22}{:>14}{:>14}", "véhicules arrivés", self.barrier.arrived, self.free_flow.arrived)?;
+        writeln!(f, "{:<22}{:>14}{:>14}", "véhicules passés", self.barrier.passed, self.free_flow.passed)?;
+        writeln!(
+            f, "{:<22}{:>14.1}{:>14.1}", "débit (véh/h)",
+            self.barrier.passed as f64 / hours, self.free_flow.passed as f64 / hours
+        )?;
+        writeln!(f, "{:<22}{:>14.2}{:>14.2}", "recettes dues (€)", self.barrier.due, self.free_flow.due)?;
+        writeln!(
+            f, "{:<22}{:>14.2}{:>14.2}", "recettes perçues (€)",
+            self.barrier.collected, self.free_flow.collected
+        )?;
+        writeln!(
+            f, "{:<22}{:>14.2}{:>14.2}", "pertes (€)",
+            self.barrier.leakage(), self.free_flow.leakage()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use crate::enforcement::honest_assessment;
+    use crate::vehicle::VehicleCategory;
+    use super::*;
+
+    /// Portique dont les lectures et les paiements réussissent tous
+    fn reliable() -> FreeFlowConfig {
+        FreeFlowConfig {
+            badge_read_rate: 1.0,
+            plate_read_rate: 1.0,
+            misread_rate: 0.0,
+            post_payment_rate: 1.0,
+            ..Default::default()
+        }
+    }
+
+    fn vehicle(rng: &mut StdRng, payment_mean: PaymentMean) -> Vehicle {
+        let mut vehicle: Vehicle = rng.gen();
+        vehicle.payment_mean = payment_mean;
+        vehicle.category = VehicleCategory::Regular;
+        vehicle.nb_kilometres = 100.0;
+        vehicle
+    }
+
+    #[test]
+    fn passage_outcomes_follow_the_read_and_payment_rates() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let badged = vehicle(&mut rng, PaymentMean::Toll);
+        let cash = vehicle(&mut rng, PaymentMean::Cash);
+        let mut ambulance = vehicle(&mut rng, PaymentMean::Cash);
+        ambulance.category = VehicleCategory::Ambulance;
+        let outcome = |config: FreeFlowConfig, vehicle: &Vehicle, rng: &mut StdRng| {
+            FreeFlowPlaza::new(config).pass(rng, vehicle, SimpleTime::default()).outcome
+        };
+
+        assert_eq!(outcome(reliable(), &badged, &mut rng), PassageOutcome::Badge);
+        assert_eq!(outcome(reliable(), &cash, &mut rng), PassageOutcome::PostPaid);
+        assert_eq!(outcome(reliable(), &ambulance, &mut rng), PassageOutcome::Exempt);
+        let unread_badge = FreeFlowConfig { badge_read_rate: 0.0, ..reliable() };
+        assert_eq!(outcome(unread_badge, &badged, &mut rng), PassageOutcome::PlateMatched);
+        let unread_plate = FreeFlowConfig { plate_read_rate: 0.0, ..reliable() };
+        assert_eq!(outcome(unread_plate, &cash, &mut rng), PassageOutcome::Unread);
+        let misread = FreeFlowConfig { misread_rate: 1.0, ..reliable() };
+        assert_eq!(outcome(misread, &cash, &mut rng), PassageOutcome::Misread);
+        let unpaid = FreeFlowConfig { post_payment_rate: 0.0, ..reliable() };
+        assert_eq!(outcome(unpaid, &cash, &mut rng), PassageOutcome::PostPaymentMissed);
+    }
+
+    #[test]
+    fn only_collected_passages_are_cashed() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut plaza = FreeFlowPlaza::new(FreeFlowConfig { post_payment_rate: 0.0, ..reliable() });
+        let badged = vehicle(&mut rng, PaymentMean::Toll);
+        let cash = vehicle(&mut rng, PaymentMean::Cash);
+
+        let paid = plaza.pass(&mut rng, &badged, SimpleTime::default());
+        assert_eq!(paid.collected, paid.due);
+        assert!(paid.payment_deadline.is_none());
+        let missed = plaza.pass(&mut rng, &cash, SimpleTime::default());
+        assert_eq!(missed.collected, 0.0);
+        assert_eq!(
+            missed.payment_deadline.map(|deadline| deadline.as_secs()),
+            Some(SimpleTime::default().as_secs() + plaza.config.post_payment_window.as_secs()),
+        );
+
+        assert_eq!((plaza.stats.arrived, plaza.stats.passed), (2, 2));
+        assert_eq!(plaza.outcomes[PassageOutcome::Badge as usize], 1);
+        assert_eq!(plaza.outcomes[PassageOutcome::PostPaymentMissed as usize], 1);
+        assert!((plaza.stats.leakage() - missed.due as f64).abs() < 1e-6);
+    }
+
+    #[test]
+    fn barrier_leakage_is_the_unpaid_amount() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let car = vehicle(&mut rng, PaymentMean::Cash);
+        let tariff = Tariff::default();
+        let mut stats = RevenueStats::default();
+        stats.add_passage(&honest_assessment(&car, &tariff, car.type_num()));
+        stats.add_passage(&Assessment { paid: 2.0, unpaid: 3.0, ..honest_assessment(&car, &tariff, 0) });
+        assert_eq!(stats.passed, 2);
+        assert!((stats.due - (tariff.price(&car) as f64 + 5.0)).abs() < 1e-6);
+        assert!((stats.leakage() - 3.0).abs() < 1e-6);
+    }
+}
@@ -1,25 +1,180 @@
 use std::collections::vec_deque::VecDeque;
 use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::Sender;
 use std::thread;
+use std::time::Duration;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::classification::{Classification, ClassificationConfig};
+use crate::direction::Direction;
+use crate::distribution::ServiceDistribution;
+use crate::enforcement::{honest_assessment, Assessment, EnforcementConfig, Evasion};
+use crate::event::{EventKind, EventLog};
+use crate::failure::Outage;
+use crate::free_flow::RevenueStats;
+use crate::logger::{LogMessage, LogSender};
+use crate::merge::Merge;
+use crate::spillback::Upstream;
+use crate::tariff::Tariff;
+use crate::scheduler::Waker;
+use crate::ticket::TicketConfig;
 use crate::toll_clock::{SimpleTime, TollClock};
-use crate::vehicle::Vehicle;
+use crate::vehicle::{PaymentMean, Vehicle};
 
 /// Véhicule en train d'attendre son tour pour payer le péage
 #[derive(Debug)]
 pub struct WaitingVehicle {
     pub vehicle: Vehicle,
-    pub arrival: TollClock
+    pub arrival: TollClock,
+    /// Nombre de véhicules prioritaires passés devant celui-ci dans la file
+    pub overtaken: u32,
+    /// Pour un véhicule prioritaire, nombre de véhicules qu'il a doublés
+    pub jumped: u32,
+    /// Vrai si le véhicule est entré dans une file plus longue que celle
+    /// qu'il aurait choisie en connaissant exactement toutes les files
+    pub lane_choice_error: bool,
+    /// Nombre de véhicules devant celui-ci dans la file lorsqu'il l'a rejointe,
+    /// sans compter celui en train de payer
+    pub queue_position: usize,
+    /// Vrai une fois que le véhicule a rejoint la file d'une porte.
+    /// Un véhicule redirigé vers une autre porte n'y est pas compté
+    /// comme une nouvelle arrivée.
+    pub admitted: bool,
+}
+
+impl WaitingVehicle {
+    pub fn new(vehicle: Vehicle, arrival: TollClock) -> Self {
+        Self {
+            vehicle, arrival,
+            overtaken: 0, jumped: 0, lane_choice_error: false, queue_position: 0,
+            admitted: false,
+        }
+    }
 }
 
 /// Véhicule qui a fini de payer et a quitté le péage
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DepartedVehicle {
     pub vehicle: Vehicle,
+    /// Numéro de la porte par laquelle le véhicule est passé,
+    /// None s'il n'est passé par aucune porte (flux libre, voie de service)
+    pub gate: Option<usize>,
+    /// Nombre de véhicules devant celui-ci dans la file lorsqu'il l'a rejointe,
+    /// sans compter celui en train de payer
+    pub queue_position: usize,
     /// Heure d'arrivée du véhicule au péage
     pub arrival: SimpleTime,
+    /// Heure à laquelle le véhicule est arrivé à la porte, après avoir attendu
+    /// dans la file
+    pub service_start: SimpleTime,
+    /// Temps passé à la porte
+    pub service_time: Duration,
     /// Heure de départ du véhicule depuis le péage
-    pub departure: SimpleTime
+    /// (après son insertion sur l'autoroute s'il y a une zone de convergence)
+    pub departure: SimpleTime,
+    /// Nombre de véhicules prioritaires passés devant celui-ci dans la file
+    pub overtaken: u32,
+    /// Retard total imposé aux autres véhicules par ce véhicule prioritaire :
+    /// son temps de passage multiplié par le nombre de véhicules doublés
+    pub imposed_delay: Duration,
+    /// Paiement effectué à la porte et éventuelle fraude
+    pub assessment: Assessment,
+    /// Classification du véhicule par les capteurs de la porte
+    pub classification: Classification,
+    /// Temps passé dans la zone de convergence en aval du péage,
+    /// entre la sortie de la porte et l'insertion sur l'autoroute
+    pub merge_delay: Duration,
+    /// Vrai si le véhicule est entré dans une file plus longue que celle
+    /// qu'il aurait choisie en connaissant exactement toutes les files
+    pub lane_choice_error: bool,
+}
+
+/// Fait quitter le péage au véhicule : le transmet au réseau autoroutier
+/// s'il poursuit son trajet vers un autre péage, puis l'enregistre en db
+pub fn depart(
+    departed: DepartedVehicle,
+    onward: Option<&Sender<Vehicle>>,
+    log_sender: Option<&LogSender>,
+    events: Option<&EventLog>,
+) {
+    if let Some(events) = events {
+        events.emit_at(
+            departed.departure.clone(),
+            EventKind::Departure,
+            departed.gate,
+            Some(departed.vehicle.id),
+            None,
+        );
+    }
+    // l'arrêt du réseau ou du thread d'enregistrement est signalé
+    // à la boucle principale par `Toll::add_vehicle()`
+    if let Some(onward) = onward {
+        if departed.vehicle.trip.as_ref().is_some_and(|trip| trip.next().is_some()) {
+            let _ = onward.send(departed.vehicle.clone());
+        }
+    }
+    if let Some(sender) = log_sender {
+        let _ = sender.send(LogMessage::Departure(departed));
+    }
+}
+
+/// Véhicule en train de passer à une porte
+#[derive(Debug)]
+pub struct Service {
+    pub vehicle: Vehicle,
+    pub arrival: TollClock,
+    pub overtaken: u32,
+    pub jumped: u32,
+    pub lane_choice_error: bool,
+    pub queue_position: usize,
+    /// Heure à laquelle le véhicule est arrivé à la porte
+    pub service_start: SimpleTime,
+    /// Temps passé à la porte
+    pub duration: Duration,
+    pub assessment: Assessment,
+    pub classification: Classification,
+}
+
+/// Type de voie d'une porte du péage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[allow(unused)]
+pub enum LaneType {
+    /// Voie acceptant tous les moyens de paiement
+    #[default]
+    Mixed = 0,
+    /// Voie réservée au télépéage
+    Electronic,
+    /// Voie de covoiturage
+    Carpool,
+}
+
+impl LaneType {
+    /// Renvoie vrai si le véhicule a le droit (ou, pour un fraudeur,
+    /// l'intention) d'emprunter ce type de voie.
+    /// Les véhicules prioritaires peuvent emprunter toutes les voies.
+    pub fn accepts(&self, vehicle: &Vehicle) -> bool {
+        vehicle.category.is_priority() || match self {
+            LaneType::Mixed => true,
+            LaneType::Electronic => vehicle.payment_mean == PaymentMean::Toll
+                || vehicle.evasion == Some(Evasion::BadgeFraud),
+            LaneType::Carpool => vehicle.carpooling(),
+        }
+    }
+}
+/// Traitement des véhicules prioritaires au péage
+#[derive(Debug, Clone, Copy, Default)]
+#[allow(unused)]
+pub enum PriorityPolicy {
+    /// Les véhicules prioritaires font la queue comme les autres
+    Fifo,
+    /// Les véhicules prioritaires passent en tête de file,
+    /// derrière les éventuels autres véhicules prioritaires
+    #[default]
+    FrontOfQueue,
+    /// Les véhicules prioritaires empruntent un passage de service et ne passent
+    /// par aucune porte. La durée donnée est le temps de traversée du passage.
+    ServicePassage(Duration),
 }
 
 /// Porte du péage.
@@ -28,24 +183,103 @@ pub struct DepartedVehicle {
 /// représentant le temps passé à payer, passe à la voiture suivante.
 /// Si la file est vide, le thread s'endort jusqu'à l'arrivée d'une nouvelle
 /// voiture
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Gate {
+    /// Numéro de la porte au sein du péage
+    pub id: usize,
+    pub lane_type: LaneType,
     /// File de véhicules en attente pour payer le péage
     pub queue: Arc<Mutex<VecDeque<WaitingVehicle>>>,
     /// Condition servant à réveiller le thread de la porte du péage
     /// lorsqu'une voiture sur une voie auparavant vide
     pub cond: Arc<Condvar>,
+    /// Vrai si la porte accepte de nouveaux véhicules.
+    /// Une porte fermée continue de servir les véhicules déjà dans sa file.
+    pub open: Arc<AtomicBool>,
+    /// Vrai pendant qu'un véhicule est en train de payer à cette porte
+    pub busy: Arc<AtomicBool>,
+    /// Panne en cours sur cette porte, None si elle fonctionne normalement
+    pub outage: Arc<Mutex<Option<Outage>>>,
+    /// Sens de circulation desservi par la porte
+    pub direction: Arc<Mutex<Direction>>,
+    /// Vrai pendant qu'une voie réversible se vide avant de changer de sens :
+    /// elle n'accepte alors plus de nouveaux véhicules
+    pub switching: Arc<AtomicBool>,
+    /// File en amont du péage, à prévenir lorsqu'une place se libère.
+    /// None si la capacité de stockage du péage n'est pas limitée
+    pub upstream: Option<Arc<Upstream>>,
+    /// Zone de convergence en aval du péage, dans laquelle les véhicules
+    /// entrent après avoir franchi la barrière.
+    /// None si les véhicules quittent le péage dès la barrière franchie
+    pub merge: Option<Arc<Merge>>,
+    /// Loi du temps de paiement à cette porte pour chaque moyen de paiement
+    /// (indexée par `PaymentMean as usize`).
+    /// None si le temps de paiement suit la loi par défaut de la classe du véhicule
+    pub payment_times: [Option<ServiceDistribution>; 2],
+    /// Vrai si les véhicules prioritaires passent en tête de file
+    pub priority_first: bool,
+    /// Grille tarifaire appliquée aux véhicules
+    pub tariff: Tariff,
+    /// Paramètres de la fraude et des contrôles, None si tous les véhicules
+    /// paient ce qu'ils doivent
+    pub enforcement: Option<EnforcementConfig>,
+    /// Capteurs de classification automatique des véhicules,
+    /// None si la classe réelle du véhicule est toujours connue
+    pub classification: Option<ClassificationConfig>,
+    /// Délivrance des tickets si la porte appartient à une gare d'entrée,
+    /// None si les véhicules y paient le péage
+    pub ticket: Option<TicketConfig>,
     /// Sender servant à envoyer au thread d'enregistrement en db
     /// du péage une voiture qui vient de compléter son paiement.
-    pub log_sender: Option<Sender<DepartedVehicle>>
+    pub log_sender: Option<LogSender>,
+    /// Journal des événements de la porte et des véhicules qui la traversent,
+    /// None si les événements ne sont pas enregistrés
+    pub events: Option<EventLog>,
+    /// Sender servant à transmettre au réseau autoroutier les véhicules
+    /// qui quittent la porte et poursuivent leur trajet vers un autre péage.
+    /// None si le péage est isolé
+    pub onward: Option<Sender<Vehicle>>,
+    /// Nombre de véhicules ayant rejoint la file depuis le dernier relevé
+    /// de l'état de la porte
+    pub arrivals: Arc<AtomicU64>,
+    /// Passages et recettes des véhicules ayant franchi la barrière,
+    /// partagés par toutes les portes du péage
+    pub revenue: Arc<Mutex<RevenueStats>>,
+    /// Graine du générateur aléatoire des temps de service, de la classification
+    /// et des paiements à cette porte
+    pub seed: u64,
+    /// Ordonnanceur à réveiller lorsque la file change,
+    /// None si la porte a son propre thread
+    pub waker: Option<Waker>,
 }
 
 impl Gate {
-    pub fn new() -> Self {
+    pub fn new(id: usize) -> Self {
         Self {
+            id,
+            lane_type: LaneType::Mixed,
             queue: Arc::new(Mutex::new(VecDeque::with_capacity(10))),
             cond: Arc::new(Condvar::new()),
-            log_sender: None
+            open: Arc::new(AtomicBool::new(true)),
+            busy: Arc::new(AtomicBool::new(false)),
+            outage: Arc::new(Mutex::new(None)),
+            direction: Arc::new(Mutex::new(Direction::default())),
+            switching: Arc::new(AtomicBool::new(false)),
+            upstream: None,
+            merge: None,
+            payment_times: [None, None],
+            priority_first: false,
+            tariff: Tariff::default(),
+            enforcement: None,
+            classification: None,
+            ticket: None,
+            log_sender: None,
+            events: None,
+            onward: None,
+            arrivals: Arc::new(AtomicU64::new(0)),
+            revenue: Arc::new(Mutex::new(RevenueStats::default())),
+            seed: 0,
+            waker: None,
         }
     }
 
@@ -53,33 +287,305 @@ impl Gate {
         self.queue.lock().unwrap().is_empty()
     }
 
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    pub fn set_open(&self, open: bool) {
+        let was_open = self.open.swap(open, Ordering::Relaxed);
+        if was_open != open {
+            let kind = if open { EventKind::GateOpen } else { EventKind::GateClose };
+            self.emit(kind, None, None);
+        }
+    }
+
+    /// Enregistre un événement survenu maintenant à cette porte
+    fn emit(&self, kind: EventKind, vehicle: Option<u64>, detail: Option<String>) {
+        if let Some(ref events) = self.events {
+            events.emit(kind, Some(self.id), vehicle, detail);
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.busy.load(Ordering::Relaxed)
+    }
+
+    /// Vrai si la porte n'a aucun véhicule en attente ni en train de payer
+    pub fn idle(&self) -> bool {
+        !self.is_busy() && self.empty()
+    }
+
+    pub fn outage(&self) -> Option<Outage> {
+        *self.outage.lock().unwrap()
+    }
+
+    /// Déclare une panne (ou la fin de la panne si `outage` vaut None)
+    /// et réveille le thread de la porte ou l'ordonnanceur
+    pub fn set_outage(&self, outage: Option<Outage>) {
+        // le thread de la porte consulte la panne en tenant le verrou de la file
+        // avant de s'endormir : la modifier sous ce verrou évite de perdre le réveil
+        let queue = self.queue.lock().unwrap();
+        let previous = std::mem::replace(&mut *self.outage.lock().unwrap(), outage);
+        match (previous, outage) {
+            (_, Some(outage)) => self.emit(EventKind::Failure, None, Some(outage.name().to_string())),
+            (Some(previous), None) => self.emit(EventKind::Repair, None, Some(previous.name().to_string())),
+            (None, None) => {}
+        }
+        self.cond.notify_all();
+        drop(queue);
+        if let Some(ref waker) = self.waker {
+            waker.wake();
+        }
+    }
+
+    pub fn direction(&self) -> Direction {
+        *self.direction.lock().unwrap()
+    }
+
+    pub fn set_direction(&self, direction: Direction) {
+        let previous = std::mem::replace(&mut *self.direction.lock().unwrap(), direction);
+        if previous != direction {
+            self.emit(EventKind::LaneSwitch, None, Some(direction.name().to_string()));
+        }
+    }
+
+    pub fn is_switching(&self) -> bool {
+        self.switching.load(Ordering::Relaxed)
+    }
+
+    pub fn set_switching(&self, switching: bool) {
+        self.switching.store(switching, Ordering::Relaxed);
+    }
+
+    /// Renvoie vrai si la porte dessert le sens du véhicule, si son type de voie
+    /// lui convient, si elle est ouverte et n'est pas en train de changer de sens,
+    /// et si la panne en cours (s'il y en a une) le laisse passer
+    pub fn accepts(&self, vehicle: &Vehicle) -> bool {
+        self.is_open() && !self.is_switching()
+            && self.direction() == vehicle.direction
+            && self.lane_type.accepts(vehicle)
+            && self.outage().is_none_or(|o| o.accepts(&vehicle.payment_mean))
+    }
+
+    /// Ajoute un véhicule au bout de la file et réveille le thread de la porte.
+    /// Si la porte donne la priorité aux véhicules prioritaires, ceux-ci sont
+    /// placés derrière le dernier véhicule prioritaire de la file
+    /// (ou en tête de file s'il n'y en a pas).
+    ///
+    /// L'arrivée du véhicule (événement, relevé des arrivées, position dans la file)
+    /// n'est comptée que la première fois qu'il rejoint une file.
+    pub fn push(&self, mut vehicle: WaitingVehicle) {
+        let first_admission = !vehicle.admitted;
+        vehicle.admitted = true;
+        if let (true, Some(events)) = (first_admission, &self.events) {
+            events.emit_at(
+                vehicle.arrival.now(),
+                EventKind::LaneChosen,
+                Some(self.id),
+                Some(vehicle.vehicle.id),
+                None,
+            );
+        }
+        let mut queue = self.queue.lock().unwrap();
+        if self.priority_first && vehicle.vehicle.category.is_priority() {
+            let position = queue.iter()
+                .position(|v| !v.vehicle.category.is_priority())
+                .unwrap_or(queue.len());
+            for overtaken in queue.range_mut(position..) {
+                overtaken.overtaken += 1;
+                vehicle.jumped += 1;
+            }
+            if first_admission {
+                vehicle.queue_position = position;
+            }
+            queue.insert(position, vehicle);
+        } else {
+            if first_admission {
+                vehicle.queue_position = queue.len();
+            }
+            queue.push_back(vehicle);
+        }
+        if first_admission {
+            self.arrivals.fetch_add(1, Ordering::Relaxed);
+        }
+        self.cond.notify_all();
+        if let Some(ref waker) = self.waker {
+            waker.wake();
+        }
+    }
+
+    /// Renvoie vrai si le véhicule en tête de file ne peut pas passer,
+    /// parce que la file est vide ou que la panne en cours l'en empêche
+    fn front_blocked(&self, queue: &VecDeque<WaitingVehicle>) -> bool {
+        queue.front().is_none_or(|v| self.outage.lock().unwrap()
+            .is_some_and(|o| !o.accepts(&v.vehicle.payment_mean)))
+    }
+
+    /// Retire de la file le véhicule en tête s'il peut passer,
+    /// marque la porte comme occupée et prévient l'amont qu'une place s'est libérée.
+    /// Renvoie None si aucun véhicule ne peut passer.
+    pub fn take_next(&self) -> Option<WaitingVehicle> {
+        let mut queue = self.queue.lock().unwrap();
+        let next_vehicle = self.take_next_locked(&mut queue);
+        drop(queue);
+        if next_vehicle.is_some() {
+            self.notify_upstream();
+        }
+        next_vehicle
+    }
+
+    /// Comme `take_next()`, sur la file déjà verrouillée par l'appelant.
+    /// L'appelant doit prévenir l'amont (`notify_upstream()`) une fois le verrou relâché.
+    fn take_next_locked(&self, queue: &mut VecDeque<WaitingVehicle>) -> Option<WaitingVehicle> {
+        if self.front_blocked(queue) {
+            return None;
+        }
+        let next_vehicle = queue.pop_front();
+        self.busy.store(true, Ordering::Relaxed);
+        next_vehicle
+    }
+
+    /// Prévient l'amont qu'une place s'est libérée dans la file
+    fn notify_upstream(&self) {
+        if let Some(ref upstream) = self.upstream {
+            upstream.notify_departure();
+        }
+    }
+
+    /// Commence le passage du véhicule à la porte : tire son temps de service,
+    /// le classe et décide de ce qu'il paie
+    pub fn begin_service<R: Rng + ?Sized>(&self, rng: &mut R, waiting: WaitingVehicle) -> Service {
+        let WaitingVehicle {
+            mut vehicle, arrival, overtaken, jumped, lane_choice_error, queue_position, ..
+        } = waiting;
+        let service_start = arrival.now();
+        if let Some(ref events) = self.events {
+            events.emit_at(
+                service_start.clone(),
+                EventKind::ServiceStart,
+                Some(self.id),
+                Some(vehicle.id),
+                None,
+            );
+        }
+        // le véhicule suivant ne peut s'avancer qu'une fois
+        // la barrière refermée derrière celui-ci
+        let mut phases = match self.ticket {
+            Some(ref config) => config.service_phases(rng, &vehicle),
+            None => vehicle.service_phases(rng),
+        };
+        if let Some(ref dist) = self.payment_times[vehicle.payment_mean as usize] {
+            if self.ticket.is_none() && !vehicle.category.is_priority() {
+                phases.payment = dist.sample(rng);
+            }
+        }
+        let classification = match self.classification {
+            Some(ref config) => config.classify(rng, vehicle.type_num()),
+            None => Classification::exact(vehicle.type_num()),
+        };
+        let assessment = match (&self.ticket, &self.enforcement) {
+            // à une gare d'entrée, le véhicule ne paie rien
+            (Some(_), _) => Assessment {
+                paid: 0.0,
+                ..honest_assessment(&vehicle, &self.tariff, classification.detected)
+            },
+            (None, Some(config)) => config.assess(
+                rng, &vehicle, self.lane_type, &self.tariff, classification.detected
+            ),
+            (None, None) => honest_assessment(&vehicle, &self.tariff, classification.detected),
+        };
+        if assessment.evasion == Some(Evasion::Tailgating) {
+            // le véhicule passe dans la foulée du précédent sans s'arrêter
+            phases.approach = Duration::ZERO;
+            phases.payment = Duration::ZERO;
+        }
+        if self.ticket.is_some() {
+            vehicle.ticket = Some(arrival.now() + phases.approach + phases.payment);
+        }
+        Service {
+            vehicle, arrival, overtaken, jumped, lane_choice_error, queue_position, service_start,
+            duration: phases.total(),
+            assessment,
+            classification,
+        }
+    }
+
+    /// Termine le passage du véhicule à la porte
+    pub fn end_service(&self, service: Service) -> DepartedVehicle {
+        let departed = DepartedVehicle {
+            vehicle: service.vehicle,
+            gate: Some(self.id),
+            queue_position: service.queue_position,
+            arrival: service.arrival.clock.clone(),
+            service_start: service.service_start,
+            service_time: service.duration,
+            departure: service.arrival.now(),
+            overtaken: service.overtaken,
+            imposed_delay: service.duration * service.jumped,
+            assessment: service.assessment,
+            classification: service.classification,
+            merge_delay: Duration::ZERO,
+            lane_choice_error: service.lane_choice_error,
+        };
+        self.revenue.lock().unwrap().add_passage(&departed.assessment);
+        if let Some(ref events) = self.events {
+            let assessment = &departed.assessment;
+            let detail = format!(
+                "paid={};unpaid={};evasion={};detected={}",
+                assessment.paid,
+                assessment.unpaid,
+                assessment.evasion.map_or("none", |evasion| evasion.name()),
+                assessment.detected,
+            );
+            events.emit_at(
+                departed.departure.clone(),
+                EventKind::Payment,
+                Some(self.id),
+                Some(departed.vehicle.id),
+                Some(detail),
+            );
+        }
+        departed
+    }
+
+    /// Fait quitter le péage au véhicule qui vient de franchir la barrière,
+    /// sans passer par la zone de convergence
+    pub fn depart(&self, departed: DepartedVehicle) {
+        depart(departed, self.onward.as_ref(), self.log_sender.as_ref(), self.events.as_ref());
+    }
+
     /// lance le thread de la porte
     /// Celui-ci continuera indéfiniment jusqu'à l'arrêt du programme.
     pub fn launch_thread(&self) {
-        let queue = self.queue.clone();
-        let cond = self.cond.clone();
-        let log_sender = self.log_sender.clone();
+        let gate = self.clone();
         thread::spawn(move || {
-            let mut rng = rand::thread_rng();
+            let mut rng = StdRng::seed_from_u64(gate.seed);
             loop {
-                let mut lock = queue.lock().unwrap();
-                while lock.is_empty() {
-                    lock = cond.wait(lock).unwrap();
+                let mut lock = gate.queue.lock().unwrap();
+                // en cas de panne, le véhicule en tête de file attend la réparation
+                // s'il ne peut pas passer
+                while gate.front_blocked(&lock) {
+                    lock = gate.cond.wait(lock).unwrap();
                 }
-                let next_vehicle = lock.pop_front().unwrap();
+                // le véhicule est retiré sans relâcher le verrou : une panne ou
+                // une déviation ne peut pas vider la file entre-temps
+                let next_vehicle = gate.take_next_locked(&mut lock);
                 drop(lock);
-                let vehicle = next_vehicle.vehicle;
-                let clock = next_vehicle.arrival;
-                thread::sleep(
-                    vehicle.payment_duration(&mut rng) / clock.acceleration_factor
-                );
-                if let Some(ref sender) = log_sender {
-                    sender.send(DepartedVehicle {
-                        vehicle,
-                        arrival: clock.clock.clone(),
-                        departure: clock.now(),
-                    }).unwrap();
+                let Some(next_vehicle) = next_vehicle else { continue };
+                gate.notify_upstream();
+                let service = gate.begin_service(&mut rng, next_vehicle);
+                thread::sleep(service.arrival.real_duration(service.duration));
+                let departed = gate.end_service(service);
+                // la porte reste occupée tant que le véhicule
+                // ne peut pas entrer dans la zone de convergence
+                match gate.merge {
+                    Some(ref merge) => {
+                        let left_gate = departed.departure.clone();
+                        merge.enter(departed, left_gate);
+                    }
+                    None => gate.depart(departed),
                 }
+                gate.busy.store(false, Ordering::Relaxed);
             }
         });
     }
@@ -88,3 +594,65 @@ impl Gate {
         self.queue.lock().unwrap().len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use crate::vehicle::VehicleCategory;
+
+    fn waiting(rng: &mut StdRng) -> WaitingVehicle {
+        WaitingVehicle::new(rng.gen(), TollClock::default())
+    }
+
+    #[test]
+    fn rerouted_vehicle_is_counted_once() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let first = Gate::new(0);
+        let second = Gate::new(1);
+        second.push(waiting(&mut rng));
+        first.push(waiting(&mut rng));
+        let rerouted = first.queue.lock().unwrap().pop_front().unwrap();
+        second.push(rerouted);
+        assert_eq!(first.arrivals.load(Ordering::Relaxed), 1);
+        assert_eq!(second.arrivals.load(Ordering::Relaxed), 1);
+        let queue = second.queue.lock().unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue[1].queue_position, 0);
+    }
+
+    #[test]
+    fn priority_vehicle_jumps_the_queue() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut gate = Gate::new(0);
+        // comme pour `PriorityPolicy::FrontOfQueue`
+        gate.priority_first = true;
+        let mut regular = || {
+            let mut v = waiting(&mut rng);
+            v.vehicle.category = VehicleCategory::Regular;
+            v
+        };
+        let (first, second) = (regular(), regular());
+        gate.push(first);
+        gate.push(second);
+        let mut ambulance = waiting(&mut rng);
+        ambulance.vehicle.category = VehicleCategory::Ambulance;
+        gate.push(ambulance);
+        {
+            let queue = gate.queue.lock().unwrap();
+            assert!(queue[0].vehicle.category.is_priority());
+            assert_eq!((queue[0].jumped, queue[0].queue_position), (2, 0));
+            assert_eq!((queue[1].overtaken, queue[2].overtaken), (1, 1));
+        }
+
+        let next = gate.take_next().unwrap();
+        assert_eq!(next.vehicle.category, VehicleCategory::Ambulance);
+        let departed = gate.end_service(gate.begin_service(&mut rng, next));
+        assert_eq!(departed.imposed_delay, departed.service_time * 2);
+        assert_eq!(departed.overtaken, 0);
+        let next = gate.take_next().unwrap();
+        let departed = gate.end_service(gate.begin_service(&mut rng, next));
+        assert_eq!(departed.overtaken, 1);
+        assert_eq!(departed.imposed_delay, Duration::ZERO);
+    }
+}
@@ -0,0 +1,27 @@
+//! Simulation d'un péage autoroutier en temps accéléré.
+
+pub mod toll;
+pub mod vehicle;
+pub mod gate;
+pub mod toll_clock;
+pub mod logger;
+pub mod staffing;
+pub mod failure;
+pub mod spillback;
+pub mod distribution;
+pub mod tariff;
+pub mod free_flow;
+pub mod enforcement;
+pub mod event;
+pub mod classification;
+pub mod network;
+pub mod merge;
+pub mod approach;
+pub mod direction;
+pub mod ticket;
+pub mod scheduler;
+pub mod sink;
+pub mod snapshot;
+pub mod run;
+pub mod error;
+pub mod report;
@@ -1,84 +1,1323 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt::{Display, Formatter};
 use std::fs::{remove_file};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::{channel, Sender};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sqlite::{Connection, OpenFlags, Statement, Value};
+use crate::direction::ReversalRecord;
+use crate::error::Error;
+use crate::event::Event;
+use crate::failure::OutageRecord;
+use crate::free_flow::FreeFlowPassage;
 use crate::gate::{DepartedVehicle};
+use crate::run::{Run, RunTotals, VERSION};
+use crate::sink::{OutputSink, SinkError};
+use crate::snapshot::GateSnapshot;
+use crate::spillback::SpillbackRecord;
+use crate::staffing::{StaffingAction, StaffingDecision};
+
+/// Message envoyé au thread d'enregistrement en db
+#[derive(Debug, Clone)]
+pub enum LogMessage {
+    /// Un véhicule a fini de payer et a quitté le péage
+    Departure(DepartedVehicle),
+    /// Une porte a été ouverte ou fermée
+    Staffing(StaffingDecision),
+    /// Une porte a été réparée après une panne
+    Outage(OutageRecord),
+    /// Fin d'un épisode de remontée de file sur l'autoroute
+    Spillback(SpillbackRecord),
+    /// Un véhicule est passé sous le portique d'un péage en flux libre
+    FreeFlow(FreeFlowPassage),
+    /// Une voie réversible a changé de sens
+    Reversal(ReversalRecord),
+    /// Etat d'une porte lors d'un relevé périodique
+    Snapshot(GateSnapshot),
+    /// Evénement du journal complet de la simulation
+    Event(Event),
+    /// Début ou fin d'un run de simulation
+    Run(Run),
+}
+
+/// Sender vers le thread d'enregistrement en db, qui compte les messages envoyés
+/// afin de mesurer le retard de l'enregistrement dès l'envoi
+#[derive(Debug, Clone)]
+pub struct LogSender {
+    sender: Sender<LogMessage>,
+    stats: Arc<LoggerStats>,
+}
+
+impl LogSender {
+    /// Envoie le message, ou renvoie une erreur si le thread d'enregistrement s'est arrêté
+    pub fn send(&self, message: LogMessage) -> Result<(), Error> {
+        self.sender.send(message).map_err(|_| Error::LoggerStopped)?;
+        self.stats.sent.fetch_add(1, Ordering::Relaxed);
+        self.stats.max_backlog.fetch_max(self.stats.backlog(), Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Version du schéma des bases de données créées par le simulateur
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64 + 1;
+
+/// Migrations du schéma : la migration d'indice i fait passer une base
+/// de la version i + 1 à la version i + 2
+const MIGRATIONS: &[&str] = &[
+    // 2 : porte, position dans la file, temps d'attente et de service,
+    // caractéristiques du véhicule donnant accès à la voie de covoiturage
+    "alter table vehicle add column gate INTEGER; \
+    alter table vehicle add column queue_position INTEGER not null default 0; \
+    alter table vehicle add column service_start TEXT; \
+    alter table vehicle add column wait_time INTEGER not null default 0; \
+    alter table vehicle add column service_time INTEGER not null default 0; \
+    alter table vehicle add column taxi INTEGER not null default 0; \
+    alter table vehicle add column low_carbon INTEGER not null default 0; \
+    alter table vehicle add column carpool INTEGER not null default 0;",
+    // 3 : relevés périodiques de l'état des portes
+    "create table snapshot ( \
+        id           INTEGER not null \
+            primary key autoincrement, \
+        plaza        INTEGER not null, \
+        time         TEXT    not null, \
+        gate         INTEGER not null, \
+        queue_length INTEGER not null, \
+        open         INTEGER not null, \
+        busy         INTEGER not null, \
+        outage       TEXT, \
+        arrivals     INTEGER not null, \
+        upstream     INTEGER not null \
+    );",
+    // 4 : journal des événements, numéro du véhicule
+    "create table event ( \
+        id      INTEGER not null \
+            primary key autoincrement, \
+        plaza   INTEGER not null, \
+        time    TEXT    not null, \
+        kind    TEXT    not null, \
+        gate    INTEGER, \
+        vehicle INTEGER, \
+        detail  TEXT \
+    ); \
+    alter table vehicle add column vehicle_id INTEGER;",
+    // 5 : métadonnées des runs, numéro du run sur chaque ligne
+    "create table run ( \
+        id                INTEGER not null \
+            primary key, \
+        seed              INTEGER not null, \
+        version           TEXT    not null, \
+        config            TEXT    not null, \
+        started           INTEGER not null, \
+        ended             INTEGER, \
+        passages          INTEGER, \
+        mean_wait         REAL, \
+        max_wait          INTEGER, \
+        mean_service_time REAL, \
+        revenue           REAL, \
+        unpaid            REAL \
+    ); \
+    alter table vehicle add column run_id INTEGER; \
+    alter table staffing add column run_id INTEGER; \
+    alter table outage add column run_id INTEGER; \
+    alter table spillback add column run_id INTEGER; \
+    alter table free_flow add column run_id INTEGER; \
+    alter table reversal add column run_id INTEGER; \
+    alter table snapshot add column run_id INTEGER; \
+    alter table event add column run_id INTEGER;",
+];
+
+/// Manière d'ouvrir une base de données déjà existante.
+/// Il n'y a pas de mode par défaut : les constructeurs refusent d'ouvrir
+/// une base existante si aucun mode n'a été choisi.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(unused)]
+pub enum OpenMode {
+    /// Conserve les enregistrements déjà présents et ajoute les nouveaux à la suite.
+    /// Une base créée par une version précédente du simulateur est mise à jour.
+    Append,
+    /// Supprime la base existante avant d'en créer une nouvelle
+    Overwrite,
+}
+
+/// Erreur survenue à l'ouverture de la base de données
+#[derive(Debug)]
+pub enum DatabaseError {
+    /// Le chemin donné est celui d'un répertoire
+    IsDirectory(PathBuf),
+    /// La base à lire n'existe pas
+    NotFound(PathBuf),
+    /// La base existante n'a pas pu être supprimée
+    Remove(PathBuf, std::io::Error),
+    /// La base existe déjà et aucun mode d'ouverture n'a été choisi
+    AlreadyExists(PathBuf),
+    /// La base a été créée par une version plus récente du simulateur,
+    /// ou son numéro de version ne correspond à aucun schéma
+    UnsupportedVersion(i64),
+    /// La base à lire a été créée par une version précédente du simulateur
+    /// et doit d'abord être mise à jour (None si la base n'est pas versionnée,
+    /// y compris si elle n'a pas été créée par le simulateur)
+    OutdatedVersion(Option<i64>),
+    /// Erreur sqlite à l'ouverture ou pendant la mise à jour du schéma
+    Sqlite(sqlite::Error),
+}
+
+impl Display for DatabaseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatabaseError::IsDirectory(path) => write!(f, "{} is a directory", path.display()),
+            DatabaseError::NotFound(path) => write!(f, "{} does not exist", path.display()),
+            DatabaseError::Remove(path, e) => write!(f, "cannot remove {}: {}", path.display(), e),
+            DatabaseError::AlreadyExists(path) => write!(
+                f, "{} already exists: choose whether to append to it or overwrite it with open_mode()",
+                path.display(),
+            ),
+            DatabaseError::UnsupportedVersion(version) if *version > SCHEMA_VERSION => write!(
+                f, "schema version {} is newer than the supported version {}", version, SCHEMA_VERSION,
+            ),
+            DatabaseError::UnsupportedVersion(version) => write!(f, "invalid schema version {}", version),
+            DatabaseError::OutdatedVersion(Some(version)) => write!(
+                f, "schema version {} is older than the current version {}, open the database \
+                in append mode to upgrade it", version, SCHEMA_VERSION,
+            ),
+            DatabaseError::OutdatedVersion(None) => write!(
+                f, "unversioned database, open it in append mode to upgrade it \
+                if it was created by the simulator",
+            ),
+            DatabaseError::Sqlite(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<sqlite::Error> for DatabaseError {
+    fn from(e: sqlite::Error) -> Self {
+        DatabaseError::Sqlite(e)
+    }
+}
+
+/// Regroupement des enregistrements en transactions
+#[derive(Debug, Clone)]
+pub struct LoggerConfig {
+    /// Nombre maximal de lignes écrites par transaction
+    pub batch_size: usize,
+    /// Durée (réelle) maximale d'une transaction : les lignes reçues
+    /// sont écrites au plus tard au bout de ce délai
+    pub flush_interval: Duration,
+}
+
+impl Default for LoggerConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 1000,
+            flush_interval: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Compteurs du thread d'enregistrement, partagés avec les péages
+#[derive(Debug)]
+struct LoggerStats {
+    /// Instant (réel) de lancement du thread d'enregistrement
+    start: Instant,
+    /// Nombre de messages envoyés par les péages
+    sent: AtomicU64,
+    /// Nombre de messages reçus après la fin du run, qui ne sont pas enregistrés
+    discarded: AtomicU64,
+    /// Nombre de lignes écrites en base de données
+    written: AtomicU64,
+    /// Nombre de transactions validées
+    transactions: AtomicU64,
+    /// Plus grand nombre de messages en attente d'écriture
+    max_backlog: AtomicU64,
+    /// Vrai une fois le thread d'enregistrement arrêté
+    stopped: AtomicBool,
+    /// Première erreur d'écriture pas encore signalée au péage
+    error: Mutex<Option<SinkError>>,
+    /// Vrai une fois la fin du run transmise au thread d'enregistrement :
+    /// les messages suivants ne sont plus enregistrés
+    finished: AtomicBool,
+    /// Vrai une fois la fin du run écrite
+    run_ended: AtomicBool,
+    /// Run en cours, None si aucun run n'a été commencé
+    run: Mutex<Option<Run>>,
+    /// Cumuls servant à calculer les indicateurs du run
+    totals: Mutex<RunTotals>,
+}
+
+impl LoggerStats {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            sent: AtomicU64::new(0),
+            discarded: AtomicU64::new(0),
+            written: AtomicU64::new(0),
+            transactions: AtomicU64::new(0),
+            max_backlog: AtomicU64::new(0),
+            stopped: AtomicBool::new(false),
+            error: Mutex::new(None),
+            finished: AtomicBool::new(false),
+            run_ended: AtomicBool::new(false),
+            run: Mutex::new(None),
+            totals: Mutex::new(RunTotals::default()),
+        }
+    }
+
+    /// Conserve l'erreur pour la signaler au péage,
+    /// sauf si une erreur précédente n'a pas encore été signalée
+    fn report(&self, error: SinkError) {
+        self.error.lock().unwrap().get_or_insert(error);
+    }
+
+    /// Nombre de messages envoyés mais pas encore écrits
+    fn backlog(&self) -> u64 {
+        let done = self.written.load(Ordering::Relaxed) + self.discarded.load(Ordering::Relaxed);
+        self.sent.load(Ordering::Relaxed).saturating_sub(done)
+    }
+}
+
+/// Débit et retard du thread d'enregistrement en db
+#[derive(Debug, Clone)]
+pub struct LoggerReport {
+    /// Nombre de lignes écrites en base de données
+    pub written: u64,
+    /// Nombre de transactions validées
+    pub transactions: u64,
+    /// Nombre moyen de lignes écrites par seconde (réelle)
+    pub throughput: f64,
+    /// Nombre de messages en attente d'écriture
+    pub backlog: u64,
+    /// Plus grand nombre de messages en attente d'écriture depuis le lancement
+    pub max_backlog: u64,
+}
+
+impl Display for LoggerReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "db : {} lignes ({:.0}/s) | en attente : {} (max {})",
+            self.written, self.throughput, self.backlog, self.max_backlog,
+        )
+    }
+}
 
 /// Gère l'enregistrement des voitures en base de données
-/// grâce à un modèle MPSC
+/// (ou vers une autre destination) grâce à un modèle MPSC
 pub struct TollDatabase {
     /// objet Sender utilisé pour envoyer des données
     /// au thread d'enregistrement en db.
-    pub sender: Sender<DepartedVehicle>,
+    pub sender: LogSender,
+    /// Sender vers le thread d'enregistrement en db, chaque message
+    /// étant accompagné du numéro du péage qui l'envoie
+    records: Sender<Command>,
+    stats: Arc<LoggerStats>,
+    /// Numéro du run enregistré par le thread d'enregistrement
+    run_id: u64,
 }
 
 impl TollDatabase {
-    /// Lance le thread d'enregistrement en db puis
-    /// renvoie un nouvel objet TollDatabase
-    pub fn new(db_name: &str) -> Result<Self, ()> {
-        if db_name != ":memory:" {
-            let path = Path::new(db_name);
-            if path.exists() {
-                match path.is_file() {
-                    true => remove_file(path).unwrap(),
-                    false => return Err(())
-                };
-            }
+    /// Ouvre la base de données, lance le thread d'enregistrement en db puis
+    /// renvoie un nouvel objet TollDatabase.
+    /// Les opérations sont enregistrées pour le péage numéro 0.
+    ///
+    /// Si la base existe déjà, elle est complétée ou remplacée suivant `mode`.
+    pub fn new(db_name: &str, mode: OpenMode) -> Result<Self, DatabaseError> {
+        Self::with_config(db_name, mode, LoggerConfig::default())
+    }
+
+    /// Comme `TollDatabase::new()`, en regroupant les enregistrements
+    /// en transactions suivant la configuration donnée
+    pub fn with_config(
+        db_name: &str, mode: OpenMode, config: LoggerConfig,
+    ) -> Result<Self, DatabaseError> {
+        let sink = SqliteSink::open(db_name, mode)?;
+        Ok(Self::with_sink(Box::new(sink), config))
+    }
+
+    /// Lance le thread d'enregistrement vers la destination donnée
+    /// (fichiers CSV, mémoire, plusieurs destinations à la fois...)
+    /// puis renvoie un nouvel objet TollDatabase.
+    /// Les opérations sont enregistrées pour le péage numéro 0.
+    pub fn with_sink(sink: Box<dyn OutputSink>, config: LoggerConfig) -> Self {
+        let stats = Arc::new(LoggerStats::new());
+        let run_id = sink.next_run_id();
+        let records = launch_log_thread(sink, config, stats.clone(), run_id);
+        Self::tagged(records, stats, run_id, 0)
+    }
+
+    /// Renvoie un objet TollDatabase écrivant vers la même destination,
+    /// dont les opérations sont enregistrées pour le péage dont le numéro est donné.
+    /// Permet à plusieurs péages d'un même réseau de partager leur base de données.
+    pub fn for_plaza(&self, plaza: usize) -> Self {
+        Self::tagged(self.records.clone(), self.stats.clone(), self.run_id, plaza)
+    }
+
+    /// Renvoie la première erreur d'écriture survenue depuis l'appel précédent,
+    /// ou une erreur si le thread d'enregistrement s'est arrêté.
+    /// Les erreurs d'écriture sont partagées par tous les péages d'un réseau :
+    /// chacune n'est renvoyée qu'une fois, au premier péage qui la demande.
+    pub fn check(&self) -> Result<(), Error> {
+        if let Some(e) = self.stats.error.lock().unwrap().take() {
+            return Err(Error::Sink(e));
+        }
+        if self.stats.stopped.load(Ordering::Relaxed) {
+            return Err(Error::LoggerStopped);
         }
-        match sqlite::open(db_name) {
-            Err(_) => Err(()),
-            Ok(conn) => {
-                create_table(&conn);
-                Ok(Self { sender: launch_log_thread(conn) })
+        Ok(())
+    }
+
+    /// Numéro du run enregistré
+    pub fn run_id(&self) -> u64 {
+        self.run_id
+    }
+
+    /// Enregistre le début du run : graine du générateur aléatoire
+    /// et configuration complète de la simulation
+    pub fn start_run(&self, seed: u64, config: String) {
+        let run = Run {
+            id: self.run_id,
+            seed,
+            version: VERSION,
+            config,
+            started: SystemTime::now(),
+            ended: None,
+            summary: None,
+        };
+        *self.stats.run.lock().unwrap() = Some(run.clone());
+        let _ = self.sender.send(LogMessage::Run(run));
+    }
+
+    /// Enregistre la fin du run et ses indicateurs, puis attend qu'ils soient écrits,
+    /// ainsi que tous les messages envoyés auparavant par ce péage.
+    /// Les messages envoyés ensuite ne sont plus enregistrés.
+    /// Sans effet si aucun run n'a été commencé ou s'il a déjà été terminé.
+    pub fn finish_run(&self) {
+        let Some(mut run) = self.stats.run.lock().unwrap().take() else {
+            return;
+        };
+        // les indicateurs sont calculés par le thread du péage, une fois
+        // comptés les véhicules envoyés avant la fin du run
+        run.ended = Some(SystemTime::now());
+        if self.sender.send(LogMessage::Run(run)).is_err() {
+            return;
+        }
+        while !self.stats.run_ended.load(Ordering::Relaxed)
+            && !self.stats.stopped.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Attend que les messages envoyés jusqu'ici soient écrits,
+    /// ou que le thread d'enregistrement s'arrête
+    pub fn flush(&self) {
+        let target = self.stats.sent.load(Ordering::Relaxed);
+        loop {
+            let done = self.stats.written.load(Ordering::Relaxed)
+                + self.stats.discarded.load(Ordering::Relaxed);
+            if done >= target || self.stats.stopped.load(Ordering::Relaxed) {
+                return;
             }
+            // les messages peuvent encore être en transit : la demande est renouvelée
+            let _ = self.records.send(Command::Flush);
+            thread::sleep(Duration::from_millis(10));
         }
     }
+
+    /// Débit et retard actuels du thread d'enregistrement
+    pub fn report(&self) -> LoggerReport {
+        let written = self.stats.written.load(Ordering::Relaxed);
+        LoggerReport {
+            written,
+            transactions: self.stats.transactions.load(Ordering::Relaxed),
+            throughput: written as f64 / self.stats.start.elapsed().as_secs_f64().max(f64::EPSILON),
+            backlog: self.stats.backlog(),
+            max_backlog: self.stats.max_backlog.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Lance le thread qui ajoute le numéro du péage aux messages
+    /// avant de les transmettre au thread d'enregistrement en db
+    fn tagged(
+        records: Sender<Command>, stats: Arc<LoggerStats>, run_id: u64, plaza: usize,
+    ) -> Self {
+        let (sender, receiver) = channel();
+        let forward = records.clone();
+        let counters = stats.clone();
+        thread::spawn(move || {
+            while let Ok(mut message) = receiver.recv() {
+                if counters.finished.load(Ordering::Relaxed) {
+                    counters.discarded.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                match message {
+                    LogMessage::Departure(ref departed) => counters.totals.lock().unwrap().add(departed),
+                    LogMessage::Run(ref mut run) if run.ended.is_some() => {
+                        run.summary = Some(counters.totals.lock().unwrap().summary());
+                        counters.finished.store(true, Ordering::Relaxed);
+                    }
+                    _ => {}
+                }
+                if forward.send(Command::Write(plaza, message)).is_err() {
+                    return;
+                }
+            }
+        });
+        let sender = LogSender { sender, stats: stats.clone() };
+        Self { sender, records, stats, run_id }
+    }
+}
+
+/// Les messages envoyés avant la destruction de l'objet sont écrits
+/// avant la fin du programme
+impl Drop for TollDatabase {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Ordre transmis au thread d'enregistrement
+#[allow(clippy::large_enum_variant)]
+enum Command {
+    /// Ecrit le message envoyé par le péage dont le numéro est donné
+    Write(usize, LogMessage),
+    /// Ecrit immédiatement le lot en cours
+    Flush,
 }
 
-fn launch_log_thread(conn: sqlite::Connection) -> Sender<DepartedVehicle> {
+/// Lance le thread d'enregistrement.
+/// Les messages sont transmis à la destination, qui les écrit par lots
+/// d'au plus `batch_size` messages ou `flush_interval`.
+fn launch_log_thread(
+    mut sink: Box<dyn OutputSink>,
+    config: LoggerConfig,
+    stats: Arc<LoggerStats>,
+    run_id: u64,
+) -> Sender<Command> {
     let (rx, tx) = channel();
     thread::spawn(move || {
-        loop {
-            log_vehicle(&conn, tx.recv().unwrap());
+        let _stopped = Stopped(stats.clone());
+        // le premier message d'un lot fixe l'heure limite d'écriture du lot
+        while let Ok(first) = tx.recv() {
+            let start = Instant::now();
+            let mut nb_rows = 0;
+            let mut run_ended = false;
+            let mut next = Some(first);
+            while let Some(Command::Write(plaza, message)) = next {
+                if let Err(e) = sink.write(run_id, plaza, &message) {
+                    stats.report(e);
+                }
+                run_ended |= matches!(message, LogMessage::Run(ref run) if run.ended.is_some());
+                nb_rows += 1;
+                if nb_rows >= config.batch_size.max(1) {
+                    break;
+                }
+                let remaining = config.flush_interval.saturating_sub(start.elapsed());
+                next = tx.recv_timeout(remaining).ok();
+            }
+            if nb_rows == 0 {
+                continue;
+            }
+            if let Err(e) = sink.flush() {
+                stats.report(e);
+            }
+            stats.written.fetch_add(nb_rows as u64, Ordering::Relaxed);
+            stats.transactions.fetch_add(1, Ordering::Relaxed);
+            if run_ended {
+                stats.run_ended.store(true, Ordering::Relaxed);
+            }
         }
     });
     rx
 }
 
-fn create_table(conn: &sqlite::Connection) {
+/// Signale l'arrêt du thread d'enregistrement lorsqu'il se termine,
+/// y compris en cas de panique
+struct Stopped(Arc<LoggerStats>);
+
+impl Drop for Stopped {
+    fn drop(&mut self) {
+        self.0.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Ligne à enregistrer pour un message : table, noms des colonnes et valeurs
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub table: &'static str,
+    pub columns: &'static [&'static str],
+    pub values: Vec<Value>,
+}
+
+impl LogMessage {
+    /// Ligne correspondant au message envoyé, au cours du run dont le numéro
+    /// est donné, par le péage dont le numéro est donné
+    pub fn record(&self, run: u64, plaza: usize) -> Record {
+        let (table, columns, mut values) = match self {
+            LogMessage::Departure(v) => ("vehicle", VEHICLE_COLUMNS, vehicle_values(plaza, v)),
+            LogMessage::Staffing(d) => ("staffing", STAFFING_COLUMNS, staffing_values(plaza, d)),
+            LogMessage::Outage(o) => ("outage", OUTAGE_COLUMNS, outage_values(plaza, o)),
+            LogMessage::Spillback(s) => ("spillback", SPILLBACK_COLUMNS, spillback_values(plaza, s)),
+            LogMessage::FreeFlow(p) => ("free_flow", FREE_FLOW_COLUMNS, free_flow_values(plaza, p)),
+            LogMessage::Reversal(r) => ("reversal", REVERSAL_COLUMNS, reversal_values(plaza, r)),
+            LogMessage::Snapshot(s) => ("snapshot", SNAPSHOT_COLUMNS, snapshot_values(plaza, s)),
+            LogMessage::Event(e) => ("event", EVENT_COLUMNS, event_values(plaza, e)),
+            LogMessage::Run(r) => return Record { table: "run", columns: RUN_COLUMNS, values: run_values(r) },
+        };
+        // le numéro du run est la dernière colonne de chaque table
+        values.push(int(run));
+        Record { table, columns, values }
+    }
+}
+
+/// Enregistrement dans une base de données sqlite.
+/// Les lignes sont insérées à l'aide de requêtes préparées,
+/// chaque lot dans une transaction.
+pub struct SqliteSink {
+    conn: Connection,
+    /// Lignes en attente d'écriture
+    pending: Vec<Record>,
+    /// Numéro du prochain run enregistré dans la base
+    next_run: u64,
+}
+
+/// Mode d'ouverture de la base donnée à un constructeur : le mode choisi,
+/// ou une erreur si la base existe déjà alors qu'aucun mode n'a été choisi
+pub(crate) fn chosen_mode(db_name: &str, mode: Option<OpenMode>) -> Result<OpenMode, DatabaseError> {
+    match mode {
+        Some(mode) => Ok(mode),
+        None if db_name != ":memory:" && Path::new(db_name).exists() =>
+            Err(DatabaseError::AlreadyExists(PathBuf::from(db_name))),
+        // une nouvelle base est créée quel que soit le mode
+        None => Ok(OpenMode::Append),
+    }
+}
+
+impl SqliteSink {
+    /// Ouvre la base de données. Si elle existe déjà, elle est complétée
+    /// ou remplacée suivant `mode`.
+    pub fn open(db_name: &str, mode: OpenMode) -> Result<Self, DatabaseError> {
+        if db_name != ":memory:" {
+            let path = Path::new(db_name);
+            if path.is_dir() {
+                return Err(DatabaseError::IsDirectory(path.to_path_buf()));
+            }
+            if mode == OpenMode::Overwrite && path.exists() {
+                remove_file(path).map_err(|e| DatabaseError::Remove(path.to_path_buf(), e))?;
+            }
+        }
+        let conn = sqlite::open(db_name)?;
+        prepare_schema(&conn)?;
+        let next_run = next_run_id(&conn)?;
+        Ok(Self { conn, pending: Vec::new(), next_run })
+    }
+
+    /// Insère les lignes en attente, en préparant une requête par table
+    fn insert_pending(&mut self) -> sqlite::Result<()> {
+        let mut statements: HashMap<&'static str, Statement> = HashMap::new();
+        for record in self.pending.drain(..) {
+            let statement = match statements.entry(record.table) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                // la ligne d'un run est remplacée à la fin de la simulation
+                Entry::Vacant(entry) => entry.insert(self.conn.prepare(format!(
+                    "insert or replace into {} ({}) values ({});",
+                    record.table,
+                    record.columns.join(", "),
+                    vec!["?"; record.columns.len()].join(", "),
+                ))?),
+            };
+            statement.reset()?;
+            statement.bind(&record.values[..])?;
+            statement.next()?;
+        }
+        Ok(())
+    }
+}
+
+impl OutputSink for SqliteSink {
+    fn write(&mut self, run: u64, plaza: usize, message: &LogMessage) -> Result<(), SinkError> {
+        self.pending.push(message.record(run, plaza));
+        Ok(())
+    }
+
+    fn next_run_id(&self) -> u64 {
+        self.next_run
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.conn.execute("begin transaction;")?;
+        match self.insert_pending() {
+            Ok(()) => Ok(self.conn.execute("commit;")?),
+            Err(e) => {
+                self.pending.clear();
+                let _ = self.conn.execute("rollback;");
+                Err(e.into())
+            }
+        }
+    }
+}
+
+/// Convertit un entier en valeur sqlite
+fn int<T: TryInto<i64>>(n: T) -> Value {
+    Value::Integer(n.try_into().unwrap_or(i64::MAX))
+}
+
+/// Convertit un réel en valeur sqlite, en passant par son écriture décimale
+/// la plus courte pour ne pas enregistrer les décimales parasites de la conversion
+fn float(x: f32) -> Value {
+    Value::Float(x.to_string().parse().unwrap_or(x as f64))
+}
+
+/// Convertit une valeur optionnelle en valeur sqlite, NULL si elle est absente
+fn nullable<T: Into<Value>>(value: Option<T>) -> Value {
+    value.map_or(Value::Null, Into::into)
+}
+
+/// Crée les tables d'une base vide, ou met à jour le schéma
+/// d'une base créée par une version précédente du simulateur
+fn prepare_schema(conn: &Connection) -> Result<(), DatabaseError> {
+    let version = schema_version(conn)?;
+    if let Some(version) = version.filter(|v| !(1..=SCHEMA_VERSION).contains(v)) {
+        return Err(DatabaseError::UnsupportedVersion(version));
+    }
+    conn.execute("begin transaction;")?;
+    match version {
+        None if !table_exists(conn, "vehicle")? => create_table(conn)?,
+        // base antérieure au versionnement du schéma
+        None => migrate_unversioned(conn)?,
+        Some(version) => {
+            for migration in &MIGRATIONS[version as usize - 1..] {
+                conn.execute(*migration)?;
+            }
+        }
+    }
+    conn.execute(format!("delete from schema_version; \
+        insert into schema_version (version) values ({});", SCHEMA_VERSION))?;
+    conn.execute("commit;")?;
+    Ok(())
+}
+
+/// Ouvre en lecture seule une base existante afin de lire ses enregistrements.
+/// La base n'est jamais modifiée : une erreur est renvoyée si son schéma
+/// n'est pas celui de la version actuelle du simulateur.
+pub(crate) fn open_existing(db_name: &str) -> Result<Connection, DatabaseError> {
+    let path = Path::new(db_name);
+    if path.is_dir() {
+        return Err(DatabaseError::IsDirectory(path.to_path_buf()));
+    }
+    if !path.exists() {
+        return Err(DatabaseError::NotFound(path.to_path_buf()));
+    }
+    let conn = Connection::open_with_flags(db_name, OpenFlags::new().set_read_only())?;
+    match schema_version(&conn)? {
+        Some(SCHEMA_VERSION) => Ok(conn),
+        Some(version) if !(1..SCHEMA_VERSION).contains(&version) => Err(DatabaseError::UnsupportedVersion(version)),
+        version => Err(DatabaseError::OutdatedVersion(version)),
+    }
+}
+
+/// Numéro suivant celui du dernier run enregistré dans la base
+fn next_run_id(conn: &Connection) -> sqlite::Result<u64> {
+    let mut statement = conn.prepare("select coalesce(max(id), 0) + 1 from run;")?;
+    statement.next()?;
+    Ok(statement.read::<i64, _>(0)? as u64)
+}
+
+/// Version du schéma de la base, None si la base n'est pas versionnée
+fn schema_version(conn: &Connection) -> sqlite::Result<Option<i64>> {
+    if !table_exists(conn, "schema_version")? {
+        return Ok(None);
+    }
+    let mut statement = conn.prepare("select max(version) from schema_version;")?;
+    statement.next()?;
+    statement.read::<Option<i64>, _>(0)
+}
+
+fn table_exists(conn: &Connection, table: &str) -> sqlite::Result<bool> {
+    let mut statement = conn.prepare(
+        "select count(*) from sqlite_master where type = 'table' and name = ?;"
+    )?;
+    statement.bind((1, table))?;
+    statement.next()?;
+    Ok(statement.read::<i64, _>(0)? > 0)
+}
+
+/// Noms, types et contrainte NOT NULL des colonnes de la table
+fn columns(conn: &Connection, table: &str) -> sqlite::Result<Vec<(String, String, bool)>> {
+    let mut statement = conn.prepare(format!("pragma table_info({});", table))?;
+    let mut columns = Vec::new();
+    while statement.next()? == sqlite::State::Row {
+        columns.push((
+            statement.read::<String, _>("name")?,
+            statement.read::<String, _>("type")?,
+            statement.read::<i64, _>("notnull")? != 0,
+        ));
+    }
+    Ok(columns)
+}
+
+/// Met à jour une base créée avant le versionnement du schéma en la comparant
+/// au schéma actuel : les tables manquantes sont créées et les colonnes
+/// manquantes ajoutées, avec une valeur par défaut pour les enregistrements existants
+fn migrate_unversioned(conn: &Connection) -> sqlite::Result<()> {
+    let reference = sqlite::open(":memory:")?;
+    create_table(&reference)?;
+    let mut statement = reference.prepare(
+        "select name, sql from sqlite_master \
+        where type = 'table' and name not like 'sqlite_%';"
+    )?;
+    while statement.next()? == sqlite::State::Row {
+        let table = statement.read::<String, _>("name")?;
+        if !table_exists(conn, &table)? {
+            conn.execute(statement.read::<String, _>("sql")?)?;
+            continue;
+        }
+        let existing = columns(conn, &table)?;
+        for (name, decl_type, not_null) in columns(&reference, &table)? {
+            if existing.iter().any(|(column, _, _)| *column == name) {
+                continue;
+            }
+            let constraint = match (not_null, decl_type.as_str()) {
+                (false, _) => "",
+                (true, "TEXT") => " not null default ''",
+                (true, _) => " not null default 0",
+            };
+            conn.execute(format!(
+                "alter table {} add column {} {}{};", table, name, decl_type, constraint
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+fn create_table(conn: &Connection) -> sqlite::Result<()> {
     let query = "\
         create table vehicle ( \
             id            INTEGER not null \
                 constraint id \
                     primary key autoincrement, \
+            run_id        INTEGER, \
+            plaza         INTEGER not null, \
+            vehicle_id    INTEGER, \
+            gate          INTEGER, \
+            trip          INTEGER, \
             kilometres    INTEGER not null, \
             nb_passengers INTEGER not null, \
+            taxi          INTEGER not null, \
+            low_carbon    INTEGER not null, \
+            carpool       INTEGER not null, \
             type          INTEGER not null, \
             payment_mean  INTEGER not null, \
+            direction     TEXT    not null, \
+            queue_position INTEGER not null, \
             arrival       TEXT    not null, \
+            service_start TEXT, \
             departure     TEXT    not null, \
+            wait_time     INTEGER not null, \
+            service_time  INTEGER not null, \
+            ticket        TEXT, \
+            category      INTEGER not null, \
+            overtaken     INTEGER not null, \
+            imposed_delay INTEGER not null, \
+            merge_delay   INTEGER not null, \
+            lane_choice_error INTEGER not null, \
+            detected_type INTEGER not null, \
+            sensor_readings TEXT, \
+            charged_type  INTEGER not null, \
+            paid          REAL    not null, \
+            evasion       TEXT, \
+            detected      INTEGER not null, \
+            unpaid        REAL    not null, \
+            fine_recovered REAL   not null, \
             constraint type_check_1 \
                 check (type >= 0), \
             constraint type_check_2 \
                 check (type < 6) \
         ); \
         create unique index vehicle_id_uindex \
-            on vehicle (id);";
-    conn.execute(query).unwrap();
-}
-
-fn log_vehicle(conn: &sqlite::Connection, v: DepartedVehicle) {
-    let query = format!(
-        "insert into vehicle (\
-            kilometres, nb_passengers, type, \
-            payment_mean, arrival, departure\
-        ) values ({}, {}, {}, {}, \"{}\", \"{}\");",
-        v.vehicle.nb_kilometres,
-        v.vehicle.nb_passengers,
-        v.vehicle.type_num(),
-        v.vehicle.payment_mean as u32,
-        v.arrival.to_timestamp(),
-        v.departure.to_timestamp()
-    );
-    conn.execute(query.as_str()).unwrap();
+            on vehicle (id); \
+        create table staffing ( \
+            id             INTEGER not null \
+                primary key autoincrement, \
+            run_id         INTEGER, \
+            plaza          INTEGER not null, \
+            time           TEXT    not null, \
+            gate           INTEGER not null, \
+            action         TEXT    not null, \
+            reason         TEXT    not null, \
+            avg_queue      REAL    not null, \
+            predicted_wait INTEGER not null, \
+            open_gates     INTEGER not null \
+        ); \
+        create table outage ( \
+            id       INTEGER not null \
+                primary key autoincrement, \
+            run_id   INTEGER, \
+            plaza    INTEGER not null, \
+            gate     INTEGER not null, \
+            type     TEXT    not null, \
+            start    TEXT    not null, \
+            end      TEXT    not null, \
+            duration INTEGER not null, \
+            rerouted INTEGER not null \
+        ); \
+        create table spillback ( \
+            id           INTEGER not null \
+                primary key autoincrement, \
+            run_id       INTEGER, \
+            plaza        INTEGER not null, \
+            start        TEXT    not null, \
+            end          TEXT    not null, \
+            duration     INTEGER not null, \
+            max_vehicles INTEGER not null, \
+            max_length   REAL    not null \
+        ); \
+        create table free_flow ( \
+            id               INTEGER not null \
+                primary key autoincrement, \
+            run_id           INTEGER, \
+            plaza            INTEGER not null, \
+            time             TEXT    not null, \
+            type             INTEGER not null, \
+            outcome          TEXT    not null, \
+            due              REAL    not null, \
+            collected        REAL    not null, \
+            payment_deadline TEXT \
+        ); \
+        create table reversal ( \
+            id        INTEGER not null \
+                primary key autoincrement, \
+            run_id    INTEGER, \
+            plaza     INTEGER not null, \
+            gate      INTEGER not null, \
+            requested TEXT    not null, \
+            done      TEXT    not null, \
+            direction TEXT    not null \
+        ); \
+        create table snapshot ( \
+            id           INTEGER not null \
+                primary key autoincrement, \
+            run_id       INTEGER, \
+            plaza        INTEGER not null, \
+            time         TEXT    not null, \
+            gate         INTEGER not null, \
+            queue_length INTEGER not null, \
+            open         INTEGER not null, \
+            busy         INTEGER not null, \
+            outage       TEXT, \
+            arrivals     INTEGER not null, \
+            upstream     INTEGER not null \
+        ); \
+        create table event ( \
+            id      INTEGER not null \
+                primary key autoincrement, \
+            run_id  INTEGER, \
+            plaza   INTEGER not null, \
+            time    TEXT    not null, \
+            kind    TEXT    not null, \
+            gate    INTEGER, \
+            vehicle INTEGER, \
+            detail  TEXT \
+        ); \
+        create table run ( \
+            id                INTEGER not null \
+                primary key, \
+            seed              INTEGER not null, \
+            version           TEXT    not null, \
+            config            TEXT    not null, \
+            started           INTEGER not null, \
+            ended             INTEGER, \
+            passages          INTEGER, \
+            mean_wait         REAL, \
+            max_wait          INTEGER, \
+            mean_service_time REAL, \
+            revenue           REAL, \
+            unpaid            REAL \
+        ); \
+        create table schema_version ( \
+            version INTEGER not null \
+        );";
+    conn.execute(query)
+}
+
+const VEHICLE_COLUMNS: &[&str] = &[
+    "plaza", "vehicle_id", "gate", "trip", "kilometres", "nb_passengers", "taxi", "low_carbon", "carpool", "type",
+    "payment_mean", "direction", "queue_position", "arrival", "service_start", "departure",
+    "wait_time", "service_time", "ticket",
+    "category", "overtaken", "imposed_delay", "merge_delay", "lane_choice_error",
+    "detected_type", "sensor_readings", "charged_type",
+    "paid", "evasion", "detected", "unpaid", "fine_recovered", "run_id",
+];
+
+fn vehicle_values(plaza: usize, v: &DepartedVehicle) -> Vec<Value> {
+    vec![
+        int(plaza),
+        int(v.vehicle.id),
+        nullable(v.gate.map(|gate| gate as i64)),
+        nullable(v.vehicle.trip.as_ref().map(|trip| trip.id as i64)),
+        float(v.vehicle.nb_kilometres),
+        int(v.vehicle.nb_passengers),
+        int(v.vehicle.taxi),
+        int(v.vehicle.low_carbon),
+        int(v.vehicle.carpooling()),
+        int(v.vehicle.type_num()),
+        int(v.vehicle.payment_mean as u32),
+        v.vehicle.direction.name().into(),
+        int(v.queue_position),
+        v.arrival.to_timestamp().into(),
+        v.service_start.to_timestamp().into(),
+        v.departure.to_timestamp().into(),
+        int(v.service_start.as_secs().saturating_sub(v.arrival.as_secs())),
+        int(v.service_time.as_secs()),
+        nullable(v.vehicle.ticket.as_ref().map(|t| t.to_timestamp())),
+        int(v.vehicle.category as u32),
+        int(v.overtaken),
+        int(v.imposed_delay.as_secs()),
+        int(v.merge_delay.as_secs()),
+        int(v.lane_choice_error as u32),
+        int(v.assessment.detected_class),
+        v.classification.readings_to_string().into(),
+        int(v.assessment.charged_class),
+        float(v.assessment.paid),
+        nullable(v.assessment.evasion.map(|e| e.name())),
+        int(v.assessment.detected as u32),
+        float(v.assessment.unpaid),
+        float(v.assessment.fine_recovered),
+    ]
+}
+
+const STAFFING_COLUMNS: &[&str] = &[
+    "plaza", "time", "gate", "action", "reason", "avg_queue", "predicted_wait", "open_gates",
+    "run_id",
+];
+
+fn staffing_values(plaza: usize, d: &StaffingDecision) -> Vec<Value> {
+    vec![
+        int(plaza),
+        d.time.to_timestamp().into(),
+        int(d.gate),
+        match d.action {
+            StaffingAction::Open => "open",
+            StaffingAction::Close => "close",
+        }.into(),
+        d.reason.as_str().into(),
+        float(d.avg_queue),
+        int(d.predicted_wait.as_secs()),
+        int(d.open_gates),
+    ]
+}
+
+const OUTAGE_COLUMNS: &[&str] = &[
+    "plaza", "gate", "type", "start", "end", "duration", "rerouted", "run_id",
+];
+
+fn outage_values(plaza: usize, o: &OutageRecord) -> Vec<Value> {
+    vec![
+        int(plaza),
+        int(o.gate),
+        o.outage.name().into(),
+        o.start.to_timestamp().into(),
+        o.end.to_timestamp().into(),
+        int(o.end.as_secs().saturating_sub(o.start.as_secs())),
+        int(o.rerouted),
+    ]
+}
+
+const SPILLBACK_COLUMNS: &[&str] = &[
+    "plaza", "start", "end", "duration", "max_vehicles", "max_length", "run_id",
+];
+
+fn spillback_values(plaza: usize, s: &SpillbackRecord) -> Vec<Value> {
+    vec![
+        int(plaza),
+        s.start.to_timestamp().into(),
+        s.end.to_timestamp().into(),
+        int(s.end.as_secs().saturating_sub(s.start.as_secs())),
+        int(s.max_vehicles),
+        float(s.max_length),
+    ]
+}
+
+const FREE_FLOW_COLUMNS: &[&str] = &[
+    "plaza", "time", "type", "outcome", "due", "collected", "payment_deadline", "run_id",
+];
+
+fn free_flow_values(plaza: usize, p: &FreeFlowPassage) -> Vec<Value> {
+    vec![
+        int(plaza),
+        p.time.to_timestamp().into(),
+        int(p.vehicle_type),
+        p.outcome.name().into(),
+        float(p.due),
+        float(p.collected),
+        nullable(p.payment_deadline.as_ref().map(|t| t.to_timestamp())),
+    ]
+}
+
+const REVERSAL_COLUMNS: &[&str] = &[
+    "plaza", "gate", "requested", "done", "direction", "run_id",
+];
+
+fn reversal_values(plaza: usize, r: &ReversalRecord) -> Vec<Value> {
+    vec![
+        int(plaza),
+        int(r.gate),
+        r.requested.to_timestamp().into(),
+        r.done.to_timestamp().into(),
+        r.direction.name().into(),
+    ]
+}
+
+const SNAPSHOT_COLUMNS: &[&str] = &[
+    "plaza", "time", "gate", "queue_length", "open", "busy", "outage", "arrivals", "upstream",
+    "run_id",
+];
+
+fn snapshot_values(plaza: usize, s: &GateSnapshot) -> Vec<Value> {
+    vec![
+        int(plaza),
+        s.time.to_timestamp().into(),
+        int(s.gate),
+        int(s.queue_length),
+        int(s.open),
+        int(s.busy),
+        nullable(s.outage.map(|o| o.name())),
+        int(s.arrivals),
+        int(s.upstream),
+    ]
+}
+
+const EVENT_COLUMNS: &[&str] = &[
+    "plaza", "time", "kind", "gate", "vehicle", "detail", "run_id",
+];
+
+fn event_values(plaza: usize, e: &Event) -> Vec<Value> {
+    vec![
+        int(plaza),
+        e.time.to_timestamp().into(),
+        e.kind.name().into(),
+        nullable(e.gate.map(|gate| gate as i64)),
+        nullable(e.vehicle.map(|vehicle| vehicle as i64)),
+        nullable(e.detail.as_deref()),
+    ]
+}
+
+const RUN_COLUMNS: &[&str] = &[
+    "id", "seed", "version", "config", "started", "ended",
+    "passages", "mean_wait", "max_wait", "mean_service_time", "revenue", "unpaid",
+];
+
+/// Convertit une heure réelle en nombre de secondes depuis le 1er janvier 1970
+fn unix_time(time: SystemTime) -> Value {
+    int(time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()))
+}
+
+fn run_values(r: &Run) -> Vec<Value> {
+    let summary = r.summary.as_ref();
+    vec![
+        int(r.id),
+        // la graine est enregistrée telle quelle dans un entier signé
+        Value::Integer(r.seed as i64),
+        r.version.into(),
+        r.config.as_str().into(),
+        unix_time(r.started),
+        r.ended.map_or(Value::Null, unix_time),
+        nullable(summary.map(|s| s.passages as i64)),
+        nullable(summary.map(|s| s.mean_wait)),
+        nullable(summary.map(|s| s.max_wait as i64)),
+        nullable(summary.map(|s| s.mean_service_time)),
+        nullable(summary.map(|s| s.revenue)),
+        nullable(summary.map(|s| s.unpaid)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventKind;
+    use crate::sink::MemorySink;
+    use crate::toll_clock::SimpleTime;
+
+    fn event() -> LogMessage {
+        LogMessage::Event(Event {
+            time: SimpleTime::default(),
+            kind: EventKind::Arrival,
+            gate: None,
+            vehicle: Some(1),
+            detail: None,
+        })
+    }
+
+    #[test]
+    fn drop_writes_pending_messages() {
+        let memory = MemorySink::new();
+        let config = LoggerConfig { batch_size: 1000, flush_interval: Duration::from_secs(3600) };
+        let db = TollDatabase::with_sink(Box::new(memory.clone()), config);
+        let sender = db.sender.clone();
+        for _ in 0..3 {
+            sender.send(event()).unwrap();
+        }
+        drop(db);
+        assert_eq!(memory.messages().len(), 3);
+    }
+
+    fn temp_db(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("rsy40-{}-{}.sqlite", name, std::process::id()));
+        let _ = remove_file(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn open_existing_reads_current_schema() {
+        let name = temp_db("current");
+        drop(SqliteSink::open(&name, OpenMode::Overwrite).unwrap());
+        assert!(open_existing(&name).is_ok());
+        remove_file(&name).unwrap();
+    }
+
+    #[test]
+    fn open_existing_leaves_other_databases_untouched() {
+        let name = temp_db("other");
+        sqlite::open(&name).unwrap().execute("create table foo (bar INTEGER);").unwrap();
+        let result = open_existing(&name);
+        assert!(matches!(result, Err(DatabaseError::OutdatedVersion(None))));
+        let conn = sqlite::open(&name).unwrap();
+        assert!(!table_exists(&conn, "schema_version").unwrap());
+        assert!(!table_exists(&conn, "vehicle").unwrap());
+        drop(conn);
+        remove_file(&name).unwrap();
+    }
+
+    #[test]
+    fn existing_database_needs_an_open_mode() {
+        let name = temp_db("mode");
+        assert_eq!(chosen_mode(&name, None).unwrap(), OpenMode::Append);
+        drop(SqliteSink::open(&name, OpenMode::Overwrite).unwrap());
+        assert!(matches!(chosen_mode(&name, None), Err(DatabaseError::AlreadyExists(_))));
+        assert_eq!(chosen_mode(&name, Some(OpenMode::Overwrite)).unwrap(), OpenMode::Overwrite);
+        remove_file(&name).unwrap();
+    }
+
+    #[test]
+    fn backlog_counts_messages_not_yet_forwarded() {
+        let memory = MemorySink::new();
+        let config = LoggerConfig { batch_size: 1000, flush_interval: Duration::from_secs(3600) };
+        let db = TollDatabase::with_sink(Box::new(memory), config);
+        db.sender.send(event()).unwrap();
+        assert_eq!(db.report().backlog, 1);
+        db.flush();
+        assert_eq!(db.report().backlog, 0);
+    }
+
+    /// Nom de chaque table et ses colonnes (voir `columns()`)
+    type Schema = Vec<(String, Vec<(String, String, bool)>)>;
+
+    /// Colonnes de chaque table de la base, triées par nom
+    fn schema(conn: &Connection) -> Schema {
+        let mut statement = conn.prepare(
+            "select name from sqlite_master where type = 'table' and name not like 'sqlite_%' order by name;"
+        ).unwrap();
+        let mut tables = Vec::new();
+        while statement.next().unwrap() == sqlite::State::Row {
+            let table = statement.read::<String, _>("name").unwrap();
+            let mut table_columns = columns(conn, &table).unwrap();
+            table_columns.sort();
+            tables.push((table, table_columns));
+        }
+        tables
+    }
+
+    fn current_schema() -> Schema {
+        let reference = sqlite::open(":memory:").unwrap();
+        create_table(&reference).unwrap();
+        schema(&reference)
+    }
+
+    #[test]
+    fn unversioned_database_is_upgraded_in_append_mode() {
+        let name = temp_db("unversioned");
+        let conn = sqlite::open(&name).unwrap();
+        // schéma des premières versions du simulateur
+        conn.execute("\
+            create table vehicle ( \
+                id            INTEGER not null \
+                    constraint id \
+                        primary key autoincrement, \
+                kilometres    INTEGER not null, \
+                nb_passengers INTEGER not null, \
+                type          INTEGER not null, \
+                payment_mean  INTEGER not null, \
+                arrival       TEXT    not null, \
+                departure     TEXT    not null \
+            ); \
+            insert into vehicle (kilometres, nb_passengers, type, payment_mean, arrival, departure) \
+                values (60, 1, 0, 0, '0T07:00:00', '0T07:00:10');").unwrap();
+        drop(conn);
+
+        drop(SqliteSink::open(&name, OpenMode::Append).unwrap());
+        let conn = sqlite::open(&name).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), Some(SCHEMA_VERSION));
+        assert_eq!(schema(&conn), current_schema());
+        let mut statement = conn.prepare("select count(*) from vehicle;").unwrap();
+        statement.next().unwrap();
+        assert_eq!(statement.read::<i64, _>(0).unwrap(), 1);
+        drop(statement);
+        drop(conn);
+        remove_file(&name).unwrap();
+    }
+
+    #[test]
+    fn first_versioned_schema_is_migrated_in_append_mode() {
+        let name = temp_db("v1");
+        let conn = sqlite::open(&name).unwrap();
+        // schéma de la version 1 : le schéma actuel sans les ajouts des migrations
+        create_table(&conn).unwrap();
+        conn.execute("\
+            drop table snapshot; \
+            drop table event; \
+            drop table run; \
+            alter table vehicle drop column gate; \
+            alter table vehicle drop column queue_position; \
+            alter table vehicle drop column service_start; \
+            alter table vehicle drop column wait_time; \
+            alter table vehicle drop column service_time; \
+            alter table vehicle drop column taxi; \
+            alter table vehicle drop column low_carbon; \
+            alter table vehicle drop column carpool; \
+            alter table vehicle drop column vehicle_id; \
+            alter table vehicle drop column run_id; \
+            alter table staffing drop column run_id; \
+            alter table outage drop column run_id; \
+            alter table spillback drop column run_id; \
+            alter table free_flow drop column run_id; \
+            alter table reversal drop column run_id; \
+            delete from schema_version; \
+            insert into schema_version (version) values (1);").unwrap();
+        drop(conn);
+
+        drop(SqliteSink::open(&name, OpenMode::Append).unwrap());
+        let conn = sqlite::open(&name).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), Some(SCHEMA_VERSION));
+        assert_eq!(schema(&conn), current_schema());
+        drop(conn);
+        remove_file(&name).unwrap();
+    }
+
+    #[test]
+    fn invalid_schema_version_is_rejected() {
+        let name = temp_db("v0");
+        let conn = sqlite::open(&name).unwrap();
+        create_table(&conn).unwrap();
+        conn.execute("delete from schema_version; insert into schema_version (version) values (0);").unwrap();
+        drop(conn);
+        assert!(matches!(SqliteSink::open(&name, OpenMode::Append), Err(DatabaseError::UnsupportedVersion(0))));
+        assert!(matches!(open_existing(&name), Err(DatabaseError::UnsupportedVersion(0))));
+        remove_file(&name).unwrap();
+    }
 }
@@ -1,34 +1,110 @@
+use std::process::exit;
 use std::thread::sleep;
-use rand::Rng;
+use std::time::Duration;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
-use crate::toll::Toll;
-use crate::vehicle::Vehicle;
+use rsy40::error::Error;
+use rsy40::logger::OpenMode;
+use rsy40::report::{Report, ReportConfig, ReportFormat};
+use rsy40::toll::Toll;
+use rsy40::vehicle::Vehicle;
 
-mod toll;
-mod vehicle;
-mod gate;
-mod toll_clock;
-mod logger;
 mod vt100;
 
+/// Durée simulée d'une exécution du programme
+const SIMULATED_DURATION: Duration = Duration::from_secs(24 * 3600);
+
 /// Fonction principale du programme
-/// Crée le péage, puis effectue une boucle infinie pour rajouter
-/// des véhicules dans le péage à intervalles de temps aléatoires.
+/// Crée le péage, puis rajoute des véhicules dans le péage à intervalles
+/// de temps aléatoires pendant `SIMULATED_DURATION` (temps simulé).
+/// Le run est ensuite terminé, ce qui enregistre son heure de fin et ses indicateurs.
+/// Les runs successifs s'ajoutent à `toll.sqlite`.
+///
+/// Avec la sous-commande `report`, écrit plutôt le rapport des indicateurs
+/// d'un run enregistré (voir `report()`).
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("report") {
+        report(&args[1..]);
+        return;
+    }
     vt100::init();
-    println!("{}", "\n".repeat(7));
-    let mut rng = rand::thread_rng();
-    let mut toll = Toll::builder()
+    println!("{}", "\n".repeat(8));
+    let build = Toll::builder()
         .nb_gates(6)
         .acceleration_factor(60) // 1 seconde = 1 minute
         .set_logger("toll.sqlite")
+        .open_mode(OpenMode::Append)
         .build();
-    loop {
+    let mut toll = match build {
+        Ok(toll) => toll,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+    // les véhicules sont tirés à partir de la graine enregistrée avec le run
+    let mut rng = StdRng::seed_from_u64(toll.seed);
+    let end = toll.clock.clock.as_secs() + SIMULATED_DURATION.as_secs();
+    while toll.clock.clock.as_secs() < end {
         let vehicle = rng.gen::<Vehicle>();
         let time_until_next = toll.time_until_next_vehicle(&mut rng);
-        toll.add_vehicle(vehicle);
+        match toll.add_vehicle(vehicle) {
+            // le véhicule ne trouve aucune porte ouverte et renonce à passer
+            Ok(()) | Err(Error::NoGate(_)) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
         println!("{}", &toll);
         sleep(time_until_next / toll.clock.acceleration_factor);
         toll.clock.update();
     }
+    toll.finish();
+}
+
+/// Ecrit le rapport des indicateurs d'un run enregistré :
+///
+/// `rsy40 report [base] [--run N] [--plaza N] [--format text|markdown|json]`
+///
+/// Par défaut, le rapport porte sur le dernier run de `toll.sqlite`,
+/// tous péages confondus, et est écrit sous forme de tableaux de texte.
+fn report(args: &[String]) {
+    let usage = "usage: rsy40 report [database] [--run N] [--plaza N] [--format text|markdown|json]";
+    let mut db_name = "toll.sqlite".to_string();
+    let mut config = ReportConfig::default();
+    let mut format = ReportFormat::Text;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--run" => args.next().and_then(|n| n.parse().ok()).map(|n| config.run = Some(n)),
+            "--plaza" => args.next().and_then(|n| n.parse().ok()).map(|n| config.plaza = Some(n)),
+            "--format" => args.next()
+                .and_then(|f| match f.as_str() {
+                    "text" => Some(ReportFormat::Text),
+                    "markdown" => Some(ReportFormat::Markdown),
+                    "json" => Some(ReportFormat::Json),
+                    _ => None,
+                })
+                .map(|f| format = f),
+            name if !name.starts_with("--") => {
+                db_name = name.to_string();
+                Some(())
+            }
+            _ => None,
+        };
+        if parsed.is_none() {
+            eprintln!("{}", usage);
+            exit(2);
+        }
+    }
+    match Report::from_database(&db_name, &config) {
+        Ok(report) => println!("{}", report.render(format)),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
 }
@@ -0,0 +1,201 @@
+//! Zone de convergence en aval du péage.
+//!
+//! Après la barrière, les voies du péage se rabattent sur les quelques voies
+//! de l'autoroute. Le débit de cette convergence est limité : les véhicules
+//! qui ont payé patientent dans la zone de convergence, et lorsque celle-ci
+//! est pleine, les véhicules restent bloqués à la porte, qui ne peut plus
+//! servir le véhicule suivant.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+use crate::event::EventLog;
+use crate::gate::{depart, DepartedVehicle};
+use crate::logger::LogSender;
+use crate::scheduler::Waker;
+use crate::toll_clock::{SimpleTime, TollClock};
+use crate::vehicle::Vehicle;
+
+/// Caractéristiques de la zone de convergence
+#[derive(Debug, Clone)]
+pub struct MergeConfig {
+    /// Nombre de voies de l'autoroute en aval du péage
+    pub nb_lanes: u32,
+    /// Temps minimal entre deux véhicules s'insérant sur une même voie
+    pub headway: Duration,
+    /// Nombre de véhicules que peut contenir la zone de convergence
+    pub capacity: usize,
+}
+
+impl Default for MergeConfig {
+    fn default() -> Self {
+        Self {
+            nb_lanes: 3,
+            headway: Duration::from_secs(2),
+            capacity: 12,
+        }
+    }
+}
+
+/// Véhicule ayant franchi la barrière et attendant de s'insérer sur l'autoroute
+#[derive(Debug)]
+struct MergingVehicle {
+    departed: DepartedVehicle,
+    /// Heure à laquelle le véhicule a quitté la porte
+    left_gate: SimpleTime,
+}
+
+/// Zone de convergence en aval du péage
+#[derive(Debug)]
+pub struct Merge {
+    pub config: MergeConfig,
+    queue: Mutex<VecDeque<MergingVehicle>>,
+    /// Condition servant à réveiller le thread de la convergence lorsqu'un
+    /// véhicule arrive, et les portes bloquées lorsqu'une place se libère
+    cond: Condvar,
+    /// Ordonnanceur à réveiller lorsqu'une place se libère,
+    /// None si les portes ont chacune leur thread
+    waker: Option<Waker>,
+}
+
+impl Merge {
+    pub fn new(config: MergeConfig, waker: Option<Waker>) -> Self {
+        Self {
+            config,
+            queue: Mutex::new(VecDeque::new()),
+            cond: Condvar::new(),
+            waker,
+        }
+    }
+
+    /// Fait entrer dans la zone de convergence un véhicule qui vient de quitter
+    /// sa porte à l'heure donnée. Bloque tant que la zone est pleine.
+    pub fn enter(&self, departed: DepartedVehicle, left_gate: SimpleTime) {
+        let mut queue = self.queue.lock().unwrap();
+        while queue.len() >= self.config.capacity.max(1) {
+            queue = self.cond.wait(queue).unwrap();
+        }
+        queue.push_back(MergingVehicle { departed, left_gate });
+        self.cond.notify_all();
+    }
+
+    /// Fait entrer le véhicule dans la zone de convergence s'il y a de la place,
+    /// sinon le rend sans attendre
+    pub fn try_enter(
+        &self, departed: DepartedVehicle, left_gate: SimpleTime,
+    ) -> Result<(), Box<(DepartedVehicle, SimpleTime)>> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= self.config.capacity.max(1) {
+            return Err(Box::new((departed, left_gate)));
+        }
+        queue.push_back(MergingVehicle { departed, left_gate });
+        self.cond.notify_all();
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.lock().unwrap().is_empty()
+    }
+}
+
+/// Lance le thread qui insère les véhicules de la zone de convergence
+/// sur l'autoroute, au débit permis par le nombre de voies en aval.
+/// Le temps passé dans la zone est enregistré comme retard de convergence.
+pub fn launch_merge_thread(
+    merge: Arc<Merge>,
+    clock: TollClock,
+    log_sender: Option<LogSender>,
+    onward: Option<Sender<Vehicle>>,
+    events: Option<EventLog>,
+) {
+    let interval = merge.config.headway / merge.config.nb_lanes.max(1);
+    thread::spawn(move || {
+        loop {
+            let mut queue = merge.queue.lock().unwrap();
+            while queue.is_empty() {
+                queue = merge.cond.wait(queue).unwrap();
+            }
+            let MergingVehicle { mut departed, left_gate } = queue.pop_front().unwrap();
+            merge.cond.notify_all();
+            drop(queue);
+            if let Some(ref waker) = merge.waker {
+                waker.wake();
+            }
+            let now = clock.now();
+            departed.merge_delay = Duration::from_secs(
+                now.as_secs().saturating_sub(left_gate.as_secs())
+            );
+            departed.departure = now;
+            depart(departed, onward.as_ref(), log_sender.as_ref(), events.as_ref());
+            thread::sleep(clock.real_duration(interval));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use crate::gate::{Gate, WaitingVehicle};
+    use crate::logger::{LoggerConfig, TollDatabase};
+    use crate::sink::MemorySink;
+    use super::*;
+
+    /// Véhicules ayant payé à la porte donnée
+    fn departed(gate: &Gate, n: usize) -> Vec<DepartedVehicle> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..n)
+            .map(|_| {
+                gate.push(WaitingVehicle::new(rng.gen(), TollClock::default()));
+                let next = gate.take_next().unwrap();
+                gate.end_service(gate.begin_service(&mut rng, next))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn full_merge_area_rejects_vehicles() {
+        let merge = Merge::new(MergeConfig { capacity: 2, ..Default::default() }, None);
+        let mut vehicles = departed(&Gate::new(0), 3).into_iter();
+        assert!(merge.is_empty());
+        for _ in 0..2 {
+            assert!(merge.try_enter(vehicles.next().unwrap(), SimpleTime::default()).is_ok());
+        }
+        let third = vehicles.next().unwrap();
+        let Err(rejected) = merge.try_enter(third, SimpleTime::default()) else {
+            panic!("the merge area should be full");
+        };
+        assert_eq!(rejected.0.gate, Some(0));
+        assert_eq!(merge.len(), 2);
+    }
+
+    #[test]
+    fn vehicles_merge_one_headway_apart() {
+        let memory = MemorySink::new();
+        let db = TollDatabase::with_sink(Box::new(memory.clone()), LoggerConfig::default());
+        let mut clock = TollClock::default();
+        clock.acceleration_factor = 600;
+        let config = MergeConfig { nb_lanes: 1, headway: Duration::from_secs(30), capacity: 3 };
+        let merge = Arc::new(Merge::new(config, None));
+        let left_gate = clock.now();
+        for vehicle in departed(&Gate::new(0), 3) {
+            merge.enter(vehicle, left_gate.clone());
+        }
+        launch_merge_thread(merge.clone(), clock, Some(db.sender.clone()), None, None);
+        while memory.departures().len() < 3 {
+            db.flush();
+            thread::sleep(Duration::from_millis(10));
+        }
+        let delays: Vec<u64> = memory.departures().iter().map(|d| d.merge_delay.as_secs()).collect();
+        // le troisième véhicule attend que les deux premiers se soient insérés
+        assert!(delays[1] >= delays[0] + 25, "{:?}", delays);
+        assert!(delays[2] >= delays[0] + 55, "{:?}", delays);
+        assert!(merge.is_empty());
+    }
+}
@@ -0,0 +1,507 @@
+//! Réseau autoroutier composé de plusieurs péages reliés par des tronçons.
+//!
+//! Chaque véhicule suit un itinéraire d'un péage à l'autre : lorsqu'il quitte
+//! un péage, il arrive au péage suivant de son itinéraire après le temps de
+//! parcours du tronçon qui les sépare. Tous les péages du réseau enregistrent
+//! leurs opérations dans la même base de données, avec leur numéro.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use crate::error::{ConfigError, Error};
+use crate::logger::{chosen_mode, OpenMode, TollDatabase};
+use crate::run::{derive_seed, VERSION};
+use crate::scheduler::Scheduler;
+use crate::toll::{sorted, Toll, TollBuilder, DEFAULT_ARRIVAL_RATES};
+use crate::toll_clock::TollClock;
+use crate::vehicle::Vehicle;
+
+/// Rôle d'un péage au sein du réseau
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(unused)]
+pub enum PlazaKind {
+    /// Gare d'entrée : le véhicule y prend un ticket sans rien payer
+    Entry,
+    /// Gare de sortie : le véhicule y paie le trajet parcouru
+    /// depuis le dernier péage
+    Exit,
+    /// Barrière en pleine voie, traversée par tout le trafic de l'autoroute :
+    /// le véhicule y paie le trajet parcouru depuis le dernier péage
+    Mainline,
+}
+
+impl PlazaKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlazaKind::Entry => "entrée",
+            PlazaKind::Exit => "sortie",
+            PlazaKind::Mainline => "pleine voie",
+        }
+    }
+}
+
+/// Tronçon d'autoroute reliant deux péages
+#[derive(Debug, Clone)]
+pub struct Segment {
+    /// Longueur du tronçon, en kilomètres
+    pub length: f32,
+    /// Temps de parcours du tronçon
+    pub travel_time: Duration,
+}
+
+/// Trajet d'un véhicule dans le réseau
+#[derive(Debug, Clone)]
+pub struct Trip {
+    /// Numéro du trajet, unique au sein du réseau
+    pub id: u64,
+    /// Numéros des péages traversés, dans l'ordre
+    pub stops: Arc<[usize]>,
+    /// Indice dans `stops` du péage où se trouve le véhicule
+    pub leg: usize,
+}
+
+impl Trip {
+    /// Péage où se trouve le véhicule
+    pub fn plaza(&self) -> usize {
+        self.stops[self.leg]
+    }
+
+    /// Péage suivant de l'itinéraire, None si le véhicule quitte le réseau
+    pub fn next(&self) -> Option<usize> {
+        self.stops.get(self.leg + 1).copied()
+    }
+}
+
+/// Réseau de péages
+pub struct Network {
+    /// Péages du réseau, par numéro
+    pub plazas: Vec<Arc<Mutex<Toll>>>,
+    /// Rôle de chaque péage, par numéro
+    pub kinds: Vec<PlazaKind>,
+    /// Itinéraires possibles
+    routes: Vec<Arc<[usize]>>,
+    /// Loi du choix de l'itinéraire d'un nouveau véhicule
+    route_dist: WeightedIndex<f64>,
+    /// Taux d'entrée des véhicules dans le réseau (par seconde),
+    /// tous itinéraires confondus, pour chaque heure de la journée
+    arrival_rates: [f64; 24],
+    /// Numéro du prochain trajet
+    next_trip: u64,
+    /// Base de données partagée par les péages, None si aucun enregistrement n'a lieu
+    database: Option<TollDatabase>,
+    /// Graine du générateur aléatoire de la simulation
+    pub seed: u64,
+    /// Générateur aléatoire du choix des itinéraires
+    rng: StdRng,
+}
+
+#[allow(unused)]
+impl Network {
+    pub fn builder() -> NetworkBuilder {
+        NetworkBuilder::default()
+    }
+
+    /// Fait entrer un véhicule dans le réseau : un itinéraire lui est attribué
+    /// au hasard et il arrive au premier péage de cet itinéraire.
+    ///
+    /// Renvoie une erreur dans les mêmes cas que `Toll::add_vehicle()`.
+    pub fn add_vehicle(&mut self, mut vehicle: Vehicle) -> Result<(), Error> {
+        let stops = self.routes[self.route_dist.sample(&mut self.rng)].clone();
+        let origin = stops[0];
+        if self.kinds[origin] == PlazaKind::Entry {
+            vehicle.nb_kilometres = 0.0;
+        }
+        vehicle.trip = Some(Trip { id: self.next_trip, stops, leg: 0 });
+        self.next_trip += 1;
+        self.plazas[origin].lock().unwrap().add_vehicle(vehicle)
+    }
+
+    /// Renvoie le temps qui s'écoulera avant l'entrée du prochain véhicule
+    /// dans le réseau, suivant le taux d'entrée du réseau à l'heure actuelle
+    /// (voir `NetworkBuilder::arrival_rates()`).
+    /// Les taux d'arrivée donnés aux péages ne sont pas utilisés.
+    pub fn time_until_next_vehicle<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
+        let lambda = self.arrival_rates[self.clock().clock.hour as usize % 24];
+        if lambda <= 0.0 {
+            return Duration::from_secs(3600);
+        }
+        Duration::from_secs(rand_distr::Exp::new(lambda).unwrap().sample(rng) as u64)
+    }
+
+    /// Horloge de la simulation, commune à tous les péages
+    pub fn clock(&self) -> TollClock {
+        self.plazas[0].lock().unwrap().clock.clone()
+    }
+
+    /// Termine la simulation : enregistre l'heure de fin du run et ses indicateurs
+    /// dans la table `run`, puis attend que les enregistrements soient écrits.
+    /// Les opérations suivantes ne sont plus enregistrées.
+    /// Sans effet si aucun enregistrement n'a lieu.
+    pub fn finish(&self) {
+        if let Some(ref database) = self.database {
+            database.finish_run();
+        }
+    }
+
+    /// Met à jour l'horloge de tous les péages
+    pub fn update(&self) {
+        self.plazas.iter().for_each(|plaza| plaza.lock().unwrap().clock.update());
+    }
+}
+
+impl Display for Network {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut buffer = format!("\x1b[{}A\x1b[J", self.plazas.len() + 2);
+        buffer.push_str(self.clock().clock.to_string().as_str());
+        for (i, (plaza, kind)) in self.plazas.iter().zip(self.kinds.iter()).enumerate() {
+            let toll = plaza.lock().unwrap();
+            let waiting = toll.gates.iter().map(|gate| gate.nb_cars()).sum::<usize>()
+                + toll.upstream.as_ref().map_or(0, |upstream| upstream.len());
+            buffer.push_str(format!("{} {:<12}| {}\n", i, kind.name(), waiting).as_str());
+        }
+        f.write_str(buffer.as_str())
+    }
+}
+
+/// Véhicule roulant vers le péage suivant de son itinéraire
+struct InTransit {
+    /// Instant (réel) d'arrivée au péage suivant
+    due: Instant,
+    /// Numéro d'ordre départageant les véhicules arrivant au même instant
+    seq: u64,
+    vehicle: Vehicle,
+}
+
+impl PartialEq for InTransit {
+    fn eq(&self, other: &Self) -> bool {
+        (self.due, self.seq) == (other.due, other.seq)
+    }
+}
+
+impl Eq for InTransit {}
+
+impl PartialOrd for InTransit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InTransit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.due, self.seq).cmp(&(other.due, other.seq))
+    }
+}
+
+/// Lance le thread qui fait rouler les véhicules d'un péage au suivant.
+/// Un véhicule qui quitte un péage autre qu'une gare d'entrée a payé son trajet :
+/// son compteur de kilomètres repart de la longueur du tronçon emprunté.
+fn launch_network_thread(
+    plazas: Vec<Arc<Mutex<Toll>>>,
+    kinds: Vec<PlazaKind>,
+    segments: HashMap<(usize, usize), Segment>,
+    clock: TollClock,
+    departures: Receiver<Vehicle>,
+) {
+    thread::spawn(move || {
+        let mut in_transit = BinaryHeap::new();
+        let mut seq = 0;
+        loop {
+            let timeout = match in_transit.peek() {
+                Some(Reverse(InTransit { due, .. })) => due.saturating_duration_since(Instant::now()),
+                None => Duration::from_secs(3600),
+            };
+            match departures.recv_timeout(timeout) {
+                Ok(mut vehicle) => {
+                    let trip = vehicle.trip.as_mut().unwrap();
+                    let from = trip.plaza();
+                    let to = trip.next().unwrap();
+                    let segment = &segments[&(from, to)];
+                    trip.leg += 1;
+                    if kinds[from] != PlazaKind::Entry {
+                        vehicle.nb_kilometres = 0.0;
+                    }
+                    vehicle.nb_kilometres += segment.length;
+                    in_transit.push(Reverse(InTransit {
+                        due: Instant::now() + clock.real_duration(segment.travel_time),
+                        seq,
+                        vehicle,
+                    }));
+                    seq += 1;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            while in_transit.peek().is_some_and(|Reverse(v)| v.due <= Instant::now()) {
+                let Reverse(InTransit { vehicle, .. }) = in_transit.pop().unwrap();
+                let plaza = vehicle.trip.as_ref().unwrap().plaza();
+                // un véhicule qu'aucune porte n'accepte quitte le réseau ;
+                // les erreurs d'enregistrement sont signalées par `Network::add_vehicle()`
+                let _ = plazas[plaza].lock().unwrap().admit(vehicle);
+            }
+        }
+    });
+}
+
+#[derive(Default)]
+pub struct NetworkBuilder {
+    /// Rôle et constructeur de chaque péage, par numéro
+    plazas: Vec<(PlazaKind, TollBuilder)>,
+    /// Tronçons, par numéros des péages de départ et d'arrivée
+    segments: HashMap<(usize, usize), Segment>,
+    /// Itinéraires et leur poids
+    routes: Vec<(Arc<[usize]>, f64)>,
+    clock: TollClock,
+    /// nom du fichier de la base de données sqlite partagée par les péages
+    logger_name: Option<String>,
+    /// Manière d'ouvrir la base de données si elle existe déjà,
+    /// None si elle n'a pas été choisie
+    open_mode: Option<OpenMode>,
+    /// Graine du générateur aléatoire, None pour en tirer une au hasard
+    seed: Option<u64>,
+    /// Taux d'entrée des véhicules dans le réseau, None pour utiliser le profil par défaut
+    arrival_rates: Option<[f64; 24]>,
+}
+
+impl std::fmt::Debug for NetworkBuilder {
+    /// Configuration du réseau, enregistrée avec chaque run.
+    /// La base de données et la graine n'en font pas partie.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetworkBuilder")
+            .field("plazas", &self.plazas)
+            .field("segments", &sorted(&self.segments))
+            .field("routes", &self.routes)
+            .field("start", &self.clock.clock)
+            .field("acceleration_factor", &self.clock.acceleration_factor)
+            .field("arrival_rates", &self.arrival_rates)
+            .finish_non_exhaustive()
+    }
+}
+
+#[allow(unused)]
+impl NetworkBuilder {
+    /// Configuration du réseau enregistrée avec chaque run : version du simulateur
+    /// et paramètres du réseau et de ses péages, toujours écrits dans le même ordre
+    pub fn config_string(&self) -> String {
+        format!("rsy40 {} {:?}", VERSION, self)
+    }
+
+    /// Construit le réseau et lance les threads de ses péages,
+    /// ainsi que le thread qui fait rouler les véhicules entre les péages.
+    ///
+    /// Renvoie une erreur, sans lancer aucun thread, si aucun itinéraire n'a été donné,
+    /// si un itinéraire est vide, passe par un péage inconnu ou emprunte un tronçon
+    /// qui n'existe pas, si la configuration d'un péage est invalide,
+    /// ou si la base de données ne peut pas être ouverte.
+    /// Tous les problèmes de configuration relevés sont renvoyés ensemble.
+    pub fn build(mut self) -> Result<Network, Error> {
+        let mut problems = Vec::new();
+        for (route, (stops, _)) in self.routes.iter().enumerate() {
+            if stops.is_empty() {
+                problems.push(ConfigError::EmptyRoute(route));
+            }
+            if let Some(&plaza) = stops.iter().find(|&&plaza| plaza >= self.plazas.len()) {
+                problems.push(ConfigError::UnknownPlaza { route, plaza });
+            }
+            if let Some(pair) = stops.windows(2).find(|pair| !self.segments.contains_key(&(pair[0], pair[1]))) {
+                problems.push(ConfigError::MissingSegment { from: pair[0], to: pair[1] });
+            }
+        }
+        let arrival_rates = self.arrival_rates.unwrap_or(DEFAULT_ARRIVAL_RATES);
+        problems.extend(arrival_rates.iter()
+            .enumerate()
+            .filter(|(_, rate)| !rate.is_finite() || **rate < 0.0)
+            .map(|(hour, &rate)| ConfigError::InvalidNetworkRate { hour, rate }));
+        let route_dist = WeightedIndex::new(self.routes.iter().map(|(_, weight)| *weight));
+        if route_dist.is_err() {
+            problems.push(ConfigError::NoRoute);
+        }
+        // les péages sont vérifiés avec l'horloge du réseau qui remplace la leur
+        self.plazas = self.plazas.into_iter()
+            .map(|(kind, builder)| (kind, builder.start_hour(self.clock.clone())))
+            .collect();
+        for (id, (_, builder)) in self.plazas.iter().enumerate() {
+            problems.extend(builder.validate()
+                .into_iter()
+                .map(|problem| ConfigError::Plaza(id, Box::new(problem))));
+        }
+        let route_dist = match route_dist {
+            Ok(route_dist) if problems.is_empty() => route_dist,
+            _ => return Err(Error::Config(problems)),
+        };
+        let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let config = self.config_string();
+        let database = match self.logger_name {
+            Some(ref name) => {
+                let mode = chosen_mode(name, self.open_mode)?;
+                Some(TollDatabase::new(name.as_str(), mode)?)
+            }
+            None => None,
+        };
+        if let Some(ref database) = database {
+            database.start_run(seed, config);
+        }
+        let (onward, departures) = channel();
+        let kinds: Vec<PlazaKind> = self.plazas.iter().map(|(kind, _)| *kind).collect();
+        // toutes les portes du réseau sont servies par un même thread
+        let scheduler = Scheduler::new();
+        let plazas: Vec<Arc<Mutex<Toll>>> = self.plazas.into_iter()
+            .enumerate()
+            .map(|(id, (_, builder))| {
+                let mut builder = builder
+                    .onward(onward.clone())
+                    .scheduler(scheduler.clone())
+                    // flux 0 : choix des itinéraires
+                    .seed(derive_seed(seed, id as u64 + 1));
+                if let Some(ref database) = database {
+                    builder = builder.shared_logger(database.for_plaza(id));
+                }
+                builder.build().map(|toll| Arc::new(Mutex::new(toll)))
+            })
+            .collect::<Result<_, _>>()?;
+        launch_network_thread(plazas.clone(), kinds.clone(), self.segments, self.clock, departures);
+        Ok(Network {
+            plazas,
+            kinds,
+            routes: self.routes.into_iter().map(|(stops, _)| stops).collect(),
+            route_dist,
+            arrival_rates,
+            next_trip: 0,
+            database,
+            seed,
+            rng: StdRng::seed_from_u64(derive_seed(seed, 0)),
+        })
+    }
+
+    /// Ajoute un péage au réseau. Les péages sont numérotés à partir de 0
+    /// dans l'ordre des appels à cette méthode.
+    /// L'heure de départ et le facteur d'accélération du constructeur du péage
+    /// sont remplacés par ceux du réseau, et sa base de données par celle du réseau.
+    /// Ses taux d'arrivée ne sont pas utilisés (voir `.arrival_rates()`).
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use rsy40::network::{Network, PlazaKind};
+    /// # use rsy40::toll::Toll;
+    /// let network = Network::builder()
+    ///     .plaza(PlazaKind::Entry, Toll::builder().nb_gates(3))
+    ///     .plaza(PlazaKind::Mainline, Toll::builder().nb_gates(8))
+    ///     .plaza(PlazaKind::Exit, Toll::builder().nb_gates(4))
+    ///     .segment(0, 1, 42.0, Duration::from_secs(25 * 60))
+    ///     .segment(1, 2, 18.0, Duration::from_secs(11 * 60))
+    ///     .route(&[0, 1, 2], 0.3)
+    ///     .route(&[1, 2], 0.7)
+    ///     .set_logger("corridor")
+    ///     .build()?;
+    /// # Ok::<(), rsy40::error::Error>(())
+    /// ```
+    pub fn plaza(mut self, kind: PlazaKind, builder: TollBuilder) -> Self {
+        self.plazas.push((kind, builder));
+        self
+    }
+
+    /// Tronçon d'autoroute allant du péage `from` au péage `to`,
+    /// de la longueur donnée (en kilomètres) et parcouru dans le temps donné
+    pub fn segment(mut self, from: usize, to: usize, length: f32, travel_time: Duration) -> Self {
+        self.segments.insert((from, to), Segment { length, travel_time });
+        self
+    }
+
+    /// Itinéraire passant par les péages donnés, dans l'ordre.
+    /// Chaque nouveau véhicule suit un itinéraire tiré au hasard
+    /// proportionnellement au poids des itinéraires.
+    pub fn route(mut self, stops: &[usize], weight: f64) -> Self {
+        self.routes.push((stops.into(), weight));
+        self
+    }
+
+    /// heure de départ de la simulation, commune à tous les péages
+    pub fn start_hour(mut self, hour: TollClock) -> Self {
+        self.clock = hour;
+        self
+    }
+
+    /// Facteur d'accélération de la simulation, commun à tous les péages
+    pub fn acceleration_factor(mut self, factor: u32) -> Self {
+        self.clock.acceleration_factor = factor;
+        self
+    }
+
+    /// Spécifie que les opérations de tous les péages seront enregistrées
+    /// dans la base de données dont le nom est spécifié en argument,
+    /// avec le numéro du péage concerné.
+    ///
+    /// Si cette méthode n'est pas appelée, aucun enregistrement n'aura lieu.
+    pub fn set_logger(mut self, s: &str) -> Self {
+        let mut name = s.to_string();
+        if !s.ends_with(".sqlite") && s != ":memory:" {
+            name.push_str(".sqlite");
+        }
+        self.logger_name = Some(name);
+        self
+    }
+
+    /// Graine du générateur aléatoire de la simulation, enregistrée avec le run.
+    /// La graine de chaque péage en découle.
+    /// Si cette méthode n'est pas appelée, une graine est tirée au hasard.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Manière d'ouvrir la base de données donnée par `.set_logger()`
+    /// si elle existe déjà : compléter ses enregistrements ou la remplacer.
+    /// Si cette méthode n'est pas appelée et que la base existe déjà,
+    /// la construction échoue plutôt que de choisir à la place de l'utilisateur.
+    pub fn open_mode(mut self, mode: OpenMode) -> Self {
+        self.open_mode = Some(mode);
+        self
+    }
+
+    /// Taux d'entrée des véhicules dans le réseau (par seconde), tous itinéraires
+    /// confondus, pour chaque heure de la journée
+    /// (voir `Network::time_until_next_vehicle()`).
+    /// Remplace les taux d'arrivée des péages, qui ne sont pas utilisés dans un réseau.
+    /// Si cette méthode n'est pas appelée, le profil par défaut d'un péage est utilisé.
+    pub fn arrival_rates(mut self, rates: [f64; 24]) -> Self {
+        self.arrival_rates = Some(rates);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_arrival_rates_are_rejected() {
+        let mut rates = DEFAULT_ARRIVAL_RATES;
+        rates[3] = -1.0;
+        let result = Network::builder()
+            .plaza(PlazaKind::Mainline, Toll::builder())
+            .route(&[0], 1.0)
+            .arrival_rates(rates)
+            .build();
+        let Err(Error::Config(problems)) = result else {
+            panic!("the network should not be built");
+        };
+        assert_eq!(problems, vec![ConfigError::InvalidNetworkRate { hour: 3, rate: -1.0 }]);
+    }
+
+    #[test]
+    fn config_string_lists_segments_in_order() {
+        let network = |pairs: Vec<(usize, usize)>| pairs.into_iter()
+            .fold(Network::builder(), |builder, (from, to)| {
+                builder.segment(from, to, 10.0, Duration::from_secs(600))
+            });
+        let pairs: Vec<(usize, usize)> = (0..10).map(|from| (from, from + 1)).collect();
+        let config = network(pairs.clone()).config_string();
+        assert!(config.starts_with(&format!("rsy40 {} NetworkBuilder {{", VERSION)));
+        assert_eq!(config, network(pairs.into_iter().rev().collect()).config_string());
+    }
+}
@@ -0,0 +1,752 @@
+//! Rapport des indicateurs d'exploitation d'un run.
+//!
+//! Le rapport est calculé après la simulation à partir des enregistrements
+//! de la base de données : temps d'attente (moyenne, médiane, 90e et 95e centiles,
+//! maximum) par heure, par porte, par sens de circulation, par classe de véhicule
+//! et par moyen de paiement, débit horaire de chaque sens, taux d'occupation
+//! et plus longue file de chaque porte,
+//! et recette par classe suivant une grille tarifaire donnée.
+//! Il peut être écrit sous forme de tableaux de texte, en Markdown ou en JSON.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use sqlite::{Connection, State};
+use crate::direction::Direction;
+use crate::error::Error;
+use crate::logger::{open_existing, DatabaseError};
+use crate::sink::json_string;
+use crate::tariff::Tariff;
+use crate::toll_clock::SimpleTime;
+use crate::vehicle::PaymentMean;
+
+/// Noms des classes de véhicule, par numéro (voir `Vehicle::type_num()`)
+const CLASS_NAMES: [&str; 5] = ["light", "medium", "truck", "heavy_truck", "motorcycle"];
+
+/// Paramètres du rapport
+#[derive(Debug, Clone, Default)]
+pub struct ReportConfig {
+    /// Numéro du run, None pour le dernier run enregistré
+    pub run: Option<u64>,
+    /// Numéro du péage d'un réseau, None pour tous les péages du run
+    pub plaza: Option<usize>,
+    /// Grille tarifaire servant à calculer la recette de chaque classe
+    pub tariff: Tariff,
+}
+
+/// Format d'écriture du rapport
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[allow(unused)]
+pub enum ReportFormat {
+    /// Tableaux alignés, pour un terminal
+    #[default]
+    Text,
+    Markdown,
+    Json,
+}
+
+/// Statistiques des temps d'attente (en secondes) d'un ensemble de passages
+#[derive(Debug, Clone, Default)]
+pub struct WaitStats {
+    pub passages: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p95: u64,
+    pub max: u64,
+}
+
+impl WaitStats {
+    fn new(mut waits: Vec<u64>) -> Self {
+        if waits.is_empty() {
+            return Self::default();
+        }
+        waits.sort_unstable();
+        // centile au rang le plus proche
+        let percentile = |p: f64| waits[((p * waits.len() as f64).ceil() as usize).max(1) - 1];
+        Self {
+            passages: waits.len() as u64,
+            mean: waits.iter().sum::<u64>() as f64 / waits.len() as f64,
+            p50: percentile(0.50),
+            p90: percentile(0.90),
+            p95: percentile(0.95),
+            max: *waits.last().unwrap(),
+        }
+    }
+}
+
+/// Indicateurs d'une heure de la simulation
+#[derive(Debug, Clone)]
+pub struct HourStats {
+    /// Heure, en heures écoulées depuis le jour 0 à 00h00
+    pub hour: u64,
+    /// Attente des véhicules arrivés pendant cette heure
+    pub wait: WaitStats,
+    /// Nombre de véhicules ayant quitté le péage pendant cette heure
+    pub throughput: u64,
+}
+
+/// Indicateurs d'un sens de circulation
+#[derive(Debug, Clone)]
+pub struct DirectionStats {
+    pub direction: Direction,
+    /// Attente des véhicules circulant dans ce sens
+    pub wait: WaitStats,
+    /// Nombre de véhicules ayant quitté le péage dans ce sens
+    pub throughput: u64,
+    /// Attente et débit de ce sens pour chaque heure de la simulation
+    pub hours: Vec<HourStats>,
+}
+
+/// Indicateurs d'une porte
+#[derive(Debug, Clone)]
+pub struct GateStats {
+    pub plaza: usize,
+    pub gate: usize,
+    pub wait: WaitStats,
+    /// Temps pendant lequel au moins un véhicule était à la porte, en secondes
+    pub busy_time: u64,
+    /// Part de la durée du run pendant laquelle la porte a servi un véhicule
+    pub utilisation: f64,
+    /// Plus longue file observée, véhicule qui la rejoint compris
+    pub max_queue: u64,
+}
+
+/// Indicateurs d'une classe de véhicule
+#[derive(Debug, Clone)]
+pub struct ClassStats {
+    pub class: usize,
+    /// Attente des véhicules de cette classe
+    pub wait: WaitStats,
+    /// Nombre de passages facturés dans cette classe
+    pub charged: u64,
+    /// Recette des passages facturés dans cette classe, suivant la grille tarifaire
+    /// du rapport (les véhicules prioritaires ne paient pas)
+    pub revenue: f64,
+    /// Montant effectivement payé aux portes pour ces passages
+    pub paid: f64,
+}
+
+/// Indicateurs d'un moyen de paiement
+#[derive(Debug, Clone)]
+pub struct PaymentStats {
+    pub mean: PaymentMean,
+    pub wait: WaitStats,
+}
+
+/// Rapport des indicateurs d'un run
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub run: u64,
+    /// Péage concerné, None pour tous les péages du run
+    pub plaza: Option<usize>,
+    /// Durée couverte par les passages, de la première arrivée
+    /// au dernier départ, en secondes
+    pub duration: u64,
+    /// Attente de tous les véhicules
+    pub wait: WaitStats,
+    pub hours: Vec<HourStats>,
+    pub directions: Vec<DirectionStats>,
+    pub gates: Vec<GateStats>,
+    pub classes: Vec<ClassStats>,
+    pub payment_means: Vec<PaymentStats>,
+}
+
+/// Passage enregistré dans la table `vehicle`
+struct Passage {
+    plaza: usize,
+    gate: Option<usize>,
+    direction: Direction,
+    class: usize,
+    charged_class: usize,
+    priority: bool,
+    payment_mean: usize,
+    kilometres: f64,
+    arrival: u64,
+    /// Arrivée à la porte
+    service_start: u64,
+    departure: u64,
+    wait: u64,
+    queue_position: u64,
+    paid: f64,
+}
+
+/// Attentes des véhicules passés par une porte, et périodes (début et fin,
+/// en secondes) pendant lesquelles chacun était à la porte
+type GatePassages = (Vec<u64>, Vec<(u64, u64)>);
+
+impl Report {
+    /// Calcule le rapport d'un run enregistré dans la base de données donnée.
+    /// Il n'est pas obligé de renseigner l'extension de la base de données.
+    ///
+    /// ```no_run
+    /// # use rsy40::report::{Report, ReportConfig, ReportFormat};
+    /// let report = Report::from_database("toll", &ReportConfig::default())?;
+    /// println!("{}", report);
+    /// std::fs::write("rapport.md", report.render(ReportFormat::Markdown))?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_database(db_name: &str, config: &ReportConfig) -> Result<Self, Error> {
+        let mut name = db_name.to_string();
+        if !name.ends_with(".sqlite") && name != ":memory:" {
+            name.push_str(".sqlite");
+        }
+        let conn = open_existing(&name)?;
+        let run = match config.run {
+            Some(run) => run,
+            None => last_run(&conn).map_err(DatabaseError::from)?.ok_or(Error::NoRun(None))?,
+        };
+        if !run_exists(&conn, run).map_err(DatabaseError::from)? {
+            return Err(Error::NoRun(Some(run)));
+        }
+        let passages = read_passages(&conn, run, config.plaza).map_err(DatabaseError::from)?;
+        let queues = read_max_queues(&conn, run, config.plaza).map_err(DatabaseError::from)?;
+        Ok(Self::new(run, config, &passages, queues))
+    }
+
+    fn new(
+        run: u64,
+        config: &ReportConfig,
+        passages: &[Passage],
+        mut max_queues: BTreeMap<(usize, usize), u64>,
+    ) -> Self {
+        let start = passages.iter().map(|p| p.arrival).min().unwrap_or(0);
+        let end = passages.iter().map(|p| p.departure).max().unwrap_or(0);
+        let duration = end.saturating_sub(start);
+
+        let hours = hour_stats(passages.iter());
+
+        let directions = [Direction::Outbound, Direction::Inbound].into_iter()
+            .map(|direction| {
+                let passages = passages.iter().filter(move |p| p.direction == direction);
+                DirectionStats {
+                    direction,
+                    wait: WaitStats::new(passages.clone().map(|p| p.wait).collect()),
+                    throughput: passages.clone().count() as u64,
+                    hours: hour_stats(passages),
+                }
+            })
+            .filter(|stats| stats.throughput > 0)
+            .collect();
+
+        let mut gates: BTreeMap<(usize, usize), GatePassages> = BTreeMap::new();
+        for p in passages {
+            let Some(gate) = p.gate else { continue };
+            let entry = gates.entry((p.plaza, gate)).or_default();
+            entry.0.push(p.wait);
+            entry.1.push((p.service_start, p.departure));
+            let queue = max_queues.entry((p.plaza, gate)).or_default();
+            *queue = (*queue).max(p.queue_position + 1);
+        }
+
+        let classes = (0..CLASS_NAMES.len())
+            .map(|class| {
+                let charged: Vec<&Passage> = passages.iter()
+                    .filter(|p| p.charged_class == class)
+                    .collect();
+                ClassStats {
+                    class,
+                    wait: WaitStats::new(passages.iter()
+                        .filter(|p| p.class == class)
+                        .map(|p| p.wait)
+                        .collect()),
+                    charged: charged.len() as u64,
+                    revenue: charged.iter()
+                        .filter(|p| !p.priority)
+                        .map(|p| config.tariff.per_km[class] as f64 * p.kilometres.max(0.0))
+                        .sum(),
+                    paid: charged.iter().map(|p| p.paid).sum(),
+                }
+            })
+            .filter(|stats| stats.wait.passages > 0 || stats.charged > 0)
+            .collect();
+
+        let payment_means = [PaymentMean::Cash, PaymentMean::Toll].into_iter()
+            .map(|mean| PaymentStats {
+                mean,
+                wait: WaitStats::new(passages.iter()
+                    .filter(|p| p.payment_mean == mean as usize)
+                    .map(|p| p.wait)
+                    .collect()),
+            })
+            .filter(|stats| stats.wait.passages > 0)
+            .collect();
+
+        Self {
+            run,
+            plaza: config.plaza,
+            duration,
+            wait: WaitStats::new(passages.iter().map(|p| p.wait).collect()),
+            hours,
+            directions,
+            gates: gates.into_iter()
+                .map(|((plaza, gate), (waits, periods))| {
+                    let busy_time = busy_time(periods);
+                    GateStats {
+                        plaza,
+                        gate,
+                        wait: WaitStats::new(waits),
+                        busy_time,
+                        utilisation: busy_time as f64 / duration.max(1) as f64,
+                        max_queue: max_queues.get(&(plaza, gate)).copied().unwrap_or(0),
+                    }
+                })
+                .collect(),
+            classes,
+            payment_means,
+        }
+    }
+
+    /// Recette totale des passages, suivant la grille tarifaire du rapport
+    pub fn revenue(&self) -> f64 {
+        self.classes.iter().map(|class| class.revenue).sum()
+    }
+
+    /// Ecrit le rapport dans le format donné
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => format!(
+                "{}\n\n{}",
+                self.title(),
+                self.tables().iter().map(Table::text).collect::<Vec<_>>().join("\n"),
+            ),
+            ReportFormat::Markdown => format!(
+                "# {}\n\n{}",
+                self.title(),
+                self.tables().iter().map(Table::markdown).collect::<Vec<_>>().join("\n"),
+            ),
+            ReportFormat::Json => self.json(),
+        }
+    }
+
+    fn title(&self) -> String {
+        match self.plaza {
+            Some(plaza) => format!("Run {}, plaza {}", self.run, plaza),
+            None => format!("Run {}", self.run),
+        }
+    }
+
+    /// Tableaux du rapport, dans leur ordre d'écriture
+    fn tables(&self) -> Vec<Table> {
+        let summary = Table {
+            title: "Summary".to_string(),
+            headers: vec!["passages", "duration", "mean wait", "p95 wait", "max wait", "revenue"],
+            rows: vec![vec![
+                self.wait.passages.to_string(),
+                duration(self.duration),
+                format!("{:.1}", self.wait.mean),
+                self.wait.p95.to_string(),
+                self.wait.max.to_string(),
+                format!("{:.2}", self.revenue()),
+            ]],
+        };
+        let hours = Table {
+            title: "Waiting time (s) and throughput per hour".to_string(),
+            headers: with_waits(&["hour"], &["throughput"]),
+            rows: self.hours.iter()
+                .map(|h| wait_row(vec![hour(h.hour)], &h.wait, vec![h.throughput.to_string()]))
+                .collect(),
+        };
+        let directions = Table {
+            title: "Waiting time (s) and throughput per direction".to_string(),
+            headers: with_waits(&["direction"], &["throughput"]),
+            rows: self.directions.iter()
+                .map(|d| wait_row(
+                    vec![d.direction.name().to_string()],
+                    &d.wait,
+                    vec![d.throughput.to_string()],
+                ))
+                .collect(),
+        };
+        let direction_hours = Table {
+            title: "Waiting time (s) and throughput per direction and hour".to_string(),
+            headers: with_waits(&["direction", "hour"], &["throughput"]),
+            rows: self.directions.iter()
+                .flat_map(|d| d.hours.iter().map(|h| wait_row(
+                    vec![d.direction.name().to_string(), hour(h.hour)],
+                    &h.wait,
+                    vec![h.throughput.to_string()],
+                )))
+                .collect(),
+        };
+        let gates = Table {
+            title: "Waiting time (s) and utilisation per gate".to_string(),
+            headers: with_waits(&["plaza", "gate"], &["busy", "utilisation", "max queue"]),
+            rows: self.gates.iter()
+                .map(|g| wait_row(
+                    vec![g.plaza.to_string(), g.gate.to_string()],
+                    &g.wait,
+                    vec![
+                        duration(g.busy_time),
+                        format!("{:.1}%", 100.0 * g.utilisation),
+                        g.max_queue.to_string(),
+                    ],
+                ))
+                .collect(),
+        };
+        let classes = Table {
+            title: "Waiting time (s) and revenue per class".to_string(),
+            headers: with_waits(&["class"], &["charged", "revenue", "paid"]),
+            rows: self.classes.iter()
+                .map(|c| wait_row(
+                    vec![CLASS_NAMES[c.class].to_string()],
+                    &c.wait,
+                    vec![c.charged.to_string(), format!("{:.2}", c.revenue), format!("{:.2}", c.paid)],
+                ))
+                .collect(),
+        };
+        let payment_means = Table {
+            title: "Waiting time (s) per payment mean".to_string(),
+            headers: with_waits(&["payment"], &[]),
+            rows: self.payment_means.iter()
+                .map(|p| wait_row(vec![payment_name(p.mean).to_string()], &p.wait, vec![]))
+                .collect(),
+        };
+        vec![summary, hours, directions, direction_hours, gates, classes, payment_means]
+    }
+
+    fn json(&self) -> String {
+        let list = |items: Vec<String>| format!("[{}]", items.join(","));
+        format!(
+            "{{\"run\":{},\"plaza\":{},\"duration\":{},\"revenue\":{},\"wait\":{},\
+            \"hours\":{},\"directions\":{},\"gates\":{},\"classes\":{},\"payment_means\":{}}}",
+            self.run,
+            self.plaza.map_or("null".to_string(), |plaza| plaza.to_string()),
+            self.duration,
+            json_number(self.revenue()),
+            wait_json(&self.wait),
+            list(self.hours.iter().map(hour_json).collect()),
+            list(self.directions.iter()
+                .map(|d| format!(
+                    "{{\"direction\":{},\"wait\":{},\"throughput\":{},\"hours\":{}}}",
+                    json_string(d.direction.name()), wait_json(&d.wait), d.throughput,
+                    list(d.hours.iter().map(hour_json).collect()),
+                ))
+                .collect()),
+            list(self.gates.iter()
+                .map(|g| format!(
+                    "{{\"plaza\":{},\"gate\":{},\"wait\":{},\"busy_time\":{},\
+                    \"utilisation\":{},\"max_queue\":{}}}",
+                    g.plaza, g.gate, wait_json(&g.wait), g.busy_time,
+                    json_number(g.utilisation), g.max_queue,
+                ))
+                .collect()),
+            list(self.classes.iter()
+                .map(|c| format!(
+                    "{{\"class\":{},\"wait\":{},\"charged\":{},\"revenue\":{},\"paid\":{}}}",
+                    json_string(CLASS_NAMES[c.class]), wait_json(&c.wait), c.charged,
+                    json_number(c.revenue), json_number(c.paid),
+                ))
+                .collect()),
+            list(self.payment_means.iter()
+                .map(|p| format!(
+                    "{{\"payment_mean\":{},\"wait\":{}}}",
+                    json_string(payment_name(p.mean)), wait_json(&p.wait),
+                ))
+                .collect()),
+        )
+    }
+}
+
+impl Display for Report {
+    /// Ecrit le rapport sous forme de tableaux de texte
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.render(ReportFormat::Text))
+    }
+}
+
+/// Tableau du rapport, écrit en texte ou en Markdown
+struct Table {
+    title: String,
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn text(&self) -> String {
+        let widths: Vec<usize> = self.headers.iter()
+            .enumerate()
+            .map(|(i, header)| self.rows.iter()
+                .map(|row| row[i].chars().count())
+                .fold(header.len(), usize::max))
+            .collect();
+        let line = |cells: Vec<&str>| cells.iter()
+            .zip(widths.iter())
+            .map(|(cell, &width)| format!("{:>width$}", cell, width = width))
+            .collect::<Vec<String>>()
+            .join("  ");
+        let mut text = format!("{}\n", self.title);
+        text.push_str(&line(self.headers.clone()));
+        text.push('\n');
+        text.push_str(&widths.iter().map(|&width| "-".repeat(width)).collect::<Vec<_>>().join("  "));
+        text.push('\n');
+        for row in self.rows.iter() {
+            text.push_str(&line(row.iter().map(String::as_str).collect()));
+            text.push('\n');
+        }
+        text
+    }
+
+    fn markdown(&self) -> String {
+        let mut text = format!("## {}\n\n| {} |\n|{}\n", self.title, self.headers.join(" | "),
+            "---:|".repeat(self.headers.len()));
+        for row in self.rows.iter() {
+            text.push_str(&format!("| {} |\n", row.join(" | ")));
+        }
+        text
+    }
+}
+
+/// Durée de la réunion des périodes données : les phases de service de deux
+/// véhicules successifs peuvent se chevaucher (dégagement et approche)
+fn busy_time(mut periods: Vec<(u64, u64)>) -> u64 {
+    periods.sort_unstable();
+    let mut busy = 0;
+    let mut covered = 0;
+    for (start, end) in periods {
+        let start = start.max(covered);
+        if end > start {
+            busy += end - start;
+            covered = end;
+        }
+    }
+    busy
+}
+
+/// Attente des véhicules arrivés et nombre de véhicules partis pendant
+/// chaque heure, pour les passages donnés
+fn hour_stats<'a>(passages: impl Iterator<Item = &'a Passage>) -> Vec<HourStats> {
+    let mut hours: BTreeMap<u64, (Vec<u64>, u64)> = BTreeMap::new();
+    for p in passages {
+        hours.entry(p.arrival / 3600).or_default().0.push(p.wait);
+        hours.entry(p.departure / 3600).or_default().1 += 1;
+    }
+    hours.into_iter()
+        .map(|(hour, (waits, throughput))| HourStats {
+            hour,
+            wait: WaitStats::new(waits),
+            throughput,
+        })
+        .collect()
+}
+
+/// En-têtes d'un tableau de temps d'attente, entre les colonnes données
+fn with_waits(before: &[&'static str], after: &[&'static str]) -> Vec<&'static str> {
+    let mut headers = before.to_vec();
+    headers.extend(["passages", "mean", "p50", "p90", "p95", "max"]);
+    headers.extend(after);
+    headers
+}
+
+fn wait_row(mut row: Vec<String>, wait: &WaitStats, after: Vec<String>) -> Vec<String> {
+    row.extend([
+        wait.passages.to_string(),
+        format!("{:.1}", wait.mean),
+        wait.p50.to_string(),
+        wait.p90.to_string(),
+        wait.p95.to_string(),
+        wait.max.to_string(),
+    ]);
+    row.extend(after);
+    row
+}
+
+fn wait_json(wait: &WaitStats) -> String {
+    format!(
+        "{{\"passages\":{},\"mean\":{},\"p50\":{},\"p90\":{},\"p95\":{},\"max\":{}}}",
+        wait.passages, json_number(wait.mean), wait.p50, wait.p90, wait.p95, wait.max,
+    )
+}
+
+fn hour_json(h: &HourStats) -> String {
+    format!(
+        "{{\"hour\":{},\"wait\":{},\"throughput\":{}}}",
+        json_string(&hour(h.hour)), wait_json(&h.wait), h.throughput,
+    )
+}
+
+fn json_number(x: f64) -> String {
+    match x.is_finite() {
+        true => x.to_string(),
+        false => "null".to_string(),
+    }
+}
+
+/// Ecrit une heure comptée depuis le jour 0 au format des horodatages de la base
+fn hour(hour: u64) -> String {
+    format!("{}T{:02}:00", hour / 24, hour % 24)
+}
+
+/// Ecrit une durée en secondes au format `1h02m03s`
+fn duration(seconds: u64) -> String {
+    format!("{}h{:02}m{:02}s", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn payment_name(mean: PaymentMean) -> &'static str {
+    match mean {
+        PaymentMean::Cash => "cash",
+        PaymentMean::Toll => "electronic",
+    }
+}
+
+/// Numéro du dernier run enregistré, None si la base n'en contient aucun
+fn last_run(conn: &Connection) -> sqlite::Result<Option<u64>> {
+    let mut statement = conn.prepare("select max(id) from run;")?;
+    statement.next()?;
+    Ok(statement.read::<Option<i64>, _>(0)?.map(|id| id as u64))
+}
+
+fn run_exists(conn: &Connection, run: u64) -> sqlite::Result<bool> {
+    let mut statement = conn.prepare("select count(*) from run where id = ?;")?;
+    statement.bind((1, run as i64))?;
+    statement.next()?;
+    Ok(statement.read::<i64, _>(0)? > 0)
+}
+
+/// Condition sur le run et, s'il est donné, le péage des lignes lues
+fn run_filter(run: u64, plaza: Option<usize>) -> String {
+    match plaza {
+        Some(plaza) => format!("run_id = {} and plaza = {}", run, plaza),
+        None => format!("run_id = {}", run),
+    }
+}
+
+fn read_passages(conn: &Connection, run: u64, plaza: Option<usize>) -> sqlite::Result<Vec<Passage>> {
+    let mut statement = conn.prepare(format!(
+        "select plaza, gate, direction, type, charged_type, category, payment_mean, kilometres, \
+        arrival, service_start, departure, wait_time, queue_position, paid \
+        from vehicle where {};",
+        run_filter(run, plaza),
+    ))?;
+    let time = |timestamp: String| SimpleTime::from_timestamp(&timestamp).map_or(0, |t| t.as_secs());
+    let mut passages = Vec::new();
+    while statement.next()? == State::Row {
+        let arrival = time(statement.read::<String, _>("arrival")?);
+        let wait = statement.read::<i64, _>("wait_time")?.max(0) as u64;
+        passages.push(Passage {
+            plaza: statement.read::<i64, _>("plaza")? as usize,
+            gate: statement.read::<Option<i64>, _>("gate")?.map(|gate| gate as usize),
+            direction: match statement.read::<String, _>("direction")?.as_str() {
+                "inbound" => Direction::Inbound,
+                _ => Direction::Outbound,
+            },
+            class: (statement.read::<i64, _>("type")? as usize).min(CLASS_NAMES.len() - 1),
+            charged_class: (statement.read::<i64, _>("charged_type")? as usize).min(CLASS_NAMES.len() - 1),
+            priority: statement.read::<i64, _>("category")? != 0,
+            payment_mean: statement.read::<i64, _>("payment_mean")? as usize,
+            kilometres: statement.read::<f64, _>("kilometres")?,
+            arrival,
+            // colonne vide dans les bases antérieures à son ajout
+            service_start: statement.read::<Option<String>, _>("service_start")?
+                .map_or(arrival + wait, time),
+            departure: time(statement.read::<String, _>("departure")?),
+            wait,
+            queue_position: statement.read::<i64, _>("queue_position")?.max(0) as u64,
+            paid: statement.read::<f64, _>("paid")?,
+        });
+    }
+    Ok(passages)
+}
+
+/// Plus longue file relevée à chaque porte, par numéros du péage et de la porte
+fn read_max_queues(
+    conn: &Connection, run: u64, plaza: Option<usize>,
+) -> sqlite::Result<BTreeMap<(usize, usize), u64>> {
+    let mut statement = conn.prepare(format!(
+        "select plaza, gate, max(queue_length) as queue_length from snapshot \
+        where {} group by plaza, gate;",
+        run_filter(run, plaza),
+    ))?;
+    let mut queues = BTreeMap::new();
+    while statement.next()? == State::Row {
+        queues.insert(
+            (statement.read::<i64, _>("plaza")? as usize, statement.read::<i64, _>("gate")? as usize),
+            statement.read::<i64, _>("queue_length")?.max(0) as u64,
+        );
+    }
+    Ok(queues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_stats_of_no_passage() {
+        let stats = WaitStats::new(Vec::new());
+        assert_eq!(stats.passages, 0);
+        assert_eq!(stats.mean, 0.0);
+        assert_eq!(stats.max, 0);
+    }
+
+    #[test]
+    fn wait_stats_use_nearest_rank_percentiles() {
+        let stats = WaitStats::new((1..=10).rev().collect());
+        assert_eq!(stats.passages, 10);
+        assert_eq!(stats.mean, 5.5);
+        assert_eq!(stats.p50, 5);
+        assert_eq!(stats.p90, 9);
+        assert_eq!(stats.p95, 10);
+        assert_eq!(stats.max, 10);
+        let single = WaitStats::new(vec![42]);
+        assert_eq!((single.p50, single.p90, single.p95, single.max), (42, 42, 42, 42));
+    }
+
+    #[test]
+    fn busy_time_merges_overlapping_periods() {
+        assert_eq!(busy_time(Vec::new()), 0);
+        assert_eq!(busy_time(vec![(10, 20), (0, 5)]), 15);
+        assert_eq!(busy_time(vec![(0, 10), (5, 15)]), 15);
+        assert_eq!(busy_time(vec![(0, 10), (2, 5)]), 10);
+        assert_eq!(busy_time(vec![(0, 10), (10, 12), (20, 20)]), 12);
+    }
+
+    fn passage(direction: Direction, arrival: u64, wait: u64) -> Passage {
+        Passage {
+            plaza: 0,
+            gate: Some(0),
+            direction,
+            class: 0,
+            charged_class: 0,
+            priority: false,
+            payment_mean: PaymentMean::Cash as usize,
+            kilometres: 0.0,
+            arrival,
+            service_start: arrival + wait,
+            departure: arrival + wait + 10,
+            wait,
+            queue_position: 0,
+            paid: 0.0,
+        }
+    }
+
+    #[test]
+    fn waits_and_throughput_per_direction() {
+        let passages = [
+            passage(Direction::Outbound, 0, 10),
+            passage(Direction::Outbound, 3600, 30),
+            passage(Direction::Inbound, 100, 50),
+        ];
+        let report = Report::new(0, &ReportConfig::default(), &passages, BTreeMap::new());
+        assert_eq!(report.directions.len(), 2);
+        let outbound = &report.directions[0];
+        assert_eq!(outbound.direction, Direction::Outbound);
+        assert_eq!((outbound.throughput, outbound.wait.mean, outbound.wait.max), (2, 20.0, 30));
+        assert_eq!(outbound.hours.iter().map(|h| (h.hour, h.throughput)).collect::<Vec<_>>(),
+            vec![(0, 1), (1, 1)]);
+        let inbound = &report.directions[1];
+        assert_eq!(inbound.direction, Direction::Inbound);
+        assert_eq!((inbound.throughput, inbound.wait.max), (1, 50));
+        assert_eq!(inbound.hours.len(), 1);
+        let text = report.render(ReportFormat::Text);
+        assert!(text.contains("Waiting time (s) and throughput per direction\n"));
+        assert!(report.render(ReportFormat::Json).contains("\"direction\":\"inbound\""));
+    }
+
+    #[test]
+    fn one_way_run_has_a_single_direction() {
+        let passages = [passage(Direction::Inbound, 0, 0)];
+        let report = Report::new(0, &ReportConfig::default(), &passages, BTreeMap::new());
+        assert_eq!(report.directions.iter().map(|d| d.direction).collect::<Vec<_>>(),
+            vec![Direction::Inbound]);
+    }
+}
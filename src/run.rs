@@ -0,0 +1,97 @@
+//! Métadonnées d'un run de simulation.
+//!
+//! Chaque simulation enregistrée est un run : sa configuration complète,
+//! la graine du générateur aléatoire, la version du simulateur, ses heures
+//! de début et de fin et ses principaux indicateurs sont enregistrés dans
+//! la table `run`. Toutes les autres lignes portent le numéro du run (`run_id`),
+//! ce qui permet de conserver plusieurs scénarios dans une même base de données
+//! et de les comparer.
+
+use std::time::SystemTime;
+use crate::gate::DepartedVehicle;
+
+/// Version du simulateur enregistrée avec chaque run
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Run de simulation, destiné à être enregistré en base de données
+#[derive(Debug, Clone)]
+pub struct Run {
+    /// Numéro du run, unique au sein d'une base de données
+    pub id: u64,
+    /// Graine du générateur aléatoire de la simulation
+    pub seed: u64,
+    /// Version du simulateur
+    pub version: &'static str,
+    /// Configuration complète du péage (ou du réseau) simulé
+    pub config: String,
+    /// Heure (réelle) de début de la simulation
+    pub started: SystemTime,
+    /// Heure (réelle) de fin de la simulation, None tant qu'elle n'est pas terminée
+    pub ended: Option<SystemTime>,
+    /// Indicateurs du run, None tant que la simulation n'est pas terminée
+    pub summary: Option<RunSummary>,
+}
+
+/// Principaux indicateurs d'un run
+#[derive(Debug, Clone, Default)]
+pub struct RunSummary {
+    /// Nombre de passages enregistrés (un véhicule traversant plusieurs péages
+    /// d'un réseau compte une fois par péage)
+    pub passages: u64,
+    /// Temps d'attente moyen dans la file, en secondes
+    pub mean_wait: f64,
+    /// Plus long temps d'attente dans la file, en secondes
+    pub max_wait: u64,
+    /// Temps moyen passé à la porte, en secondes
+    pub mean_service_time: f64,
+    /// Montant total payé aux portes
+    pub revenue: f64,
+    /// Montant total dû mais non payé
+    pub unpaid: f64,
+}
+
+/// Cumuls servant à calculer les indicateurs d'un run au fil des passages
+#[derive(Debug, Clone, Default)]
+pub struct RunTotals {
+    passages: u64,
+    wait: u64,
+    max_wait: u64,
+    service_time: u64,
+    revenue: f64,
+    unpaid: f64,
+}
+
+impl RunTotals {
+    pub fn add(&mut self, departed: &DepartedVehicle) {
+        let wait = departed.service_start.as_secs().saturating_sub(departed.arrival.as_secs());
+        self.passages += 1;
+        self.wait += wait;
+        self.max_wait = self.max_wait.max(wait);
+        self.service_time += departed.service_time.as_secs();
+        self.revenue += departed.assessment.paid as f64;
+        self.unpaid += departed.assessment.unpaid as f64;
+    }
+
+    pub fn summary(&self) -> RunSummary {
+        let passages = self.passages.max(1) as f64;
+        RunSummary {
+            passages: self.passages,
+            mean_wait: self.wait as f64 / passages,
+            max_wait: self.max_wait,
+            mean_service_time: self.service_time as f64 / passages,
+            revenue: self.revenue,
+            unpaid: self.unpaid,
+        }
+    }
+}
+
+/// Graine du générateur aléatoire du flux numéro `stream` d'une simulation
+/// (péage, porte, pannes...), tirée de la graine de la simulation.
+/// Deux flux d'une même simulation ne tirent pas les mêmes nombres.
+pub fn derive_seed(seed: u64, stream: u64) -> u64 {
+    // splitmix64
+    let mut z = seed ^ stream.wrapping_add(1).wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,280 @@
+//! Exécution des portes sans thread dédié.
+//!
+//! Avec un thread par porte, un péage de plusieurs centaines de voies, ou un
+//! réseau de plusieurs péages, lance autant de threads qui passent l'essentiel
+//! de leur temps à dormir. L'ordonnanceur sert toutes les portes qui lui sont
+//! confiées depuis un seul thread : il retient pour chaque porte l'instant
+//! auquel le véhicule en cours de service aura fini de payer, et ne se réveille
+//! qu'à ces instants ou lorsqu'un véhicule arrive dans une file vide.
+//!
+//! Le comportement observable est celui du thread par porte : mêmes temps de
+//! service, même ordre de passage, blocage de la porte lorsque la zone de
+//! convergence est pleine.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use crate::gate::{DepartedVehicle, Gate, Service};
+use crate::toll_clock::SimpleTime;
+
+/// Manière de faire fonctionner les portes d'un péage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[allow(unused)]
+pub enum ExecutionModel {
+    /// Toutes les portes sont servies par un même thread
+    #[default]
+    Scheduler,
+    /// Chaque porte a son propre thread
+    ThreadPerGate,
+}
+
+/// Evénement réveillant le thread de l'ordonnanceur
+#[derive(Debug, Clone, Copy)]
+pub enum Wake {
+    /// La file de la porte (par numéro d'emplacement) a changé ou sa panne a pris fin
+    Gate(usize),
+    /// Une place s'est libérée dans une zone de convergence
+    Merge,
+}
+
+#[derive(Debug)]
+enum Message {
+    Register(usize, Box<Gate>),
+    Wake(Wake),
+}
+
+/// Permet à une porte ou à une zone de convergence de réveiller l'ordonnanceur
+#[derive(Debug, Clone)]
+pub struct Waker {
+    sender: Sender<Message>,
+    wake: Wake,
+}
+
+impl Waker {
+    pub fn wake(&self) {
+        // l'ordonnanceur ne s'arrête jamais, l'envoi ne peut pas échouer
+        let _ = self.sender.send(Message::Wake(self.wake));
+    }
+}
+
+/// Ordonnanceur servant des portes depuis un seul thread.
+/// Peut être partagé entre plusieurs péages d'un même réseau.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    sender: Sender<Message>,
+    /// Nombre de portes déjà confiées à l'ordonnanceur
+    slots: Arc<AtomicUsize>,
+}
+
+impl Scheduler {
+    /// Crée l'ordonnanceur et lance son thread
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+        launch_scheduler_thread(receiver);
+        Self {
+            sender,
+            slots: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Confie la porte à l'ordonnanceur, à la place de son thread
+    pub fn register(&self, gate: &mut Gate) {
+        let slot = self.slots.fetch_add(1, Ordering::Relaxed);
+        gate.waker = Some(Waker {
+            sender: self.sender.clone(),
+            wake: Wake::Gate(slot),
+        });
+        self.sender.send(Message::Register(slot, Box::new(gate.clone()))).unwrap();
+    }
+
+    /// Waker à donner aux zones de convergence en aval des portes de l'ordonnanceur
+    pub fn merge_waker(&self) -> Waker {
+        Waker {
+            sender: self.sender.clone(),
+            wake: Wake::Merge,
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Etat d'une porte servie par l'ordonnanceur
+enum State {
+    /// Aucun véhicule en train de payer
+    Idle,
+    /// Un véhicule est en train de payer
+    Serving(Service),
+    /// Le véhicule a payé mais attend une place dans la zone de convergence,
+    /// depuis l'heure donnée
+    Blocked(DepartedVehicle, SimpleTime),
+}
+
+struct Slot {
+    gate: Gate,
+    state: State,
+    /// Générateur aléatoire propre à la porte, tiré de sa graine
+    rng: StdRng,
+}
+
+impl Slot {
+    /// Fait avancer le véhicule en tête de file si la porte est libre.
+    /// Renvoie l'instant (réel) auquel il aura fini de payer.
+    fn start(&mut self) -> Option<Instant> {
+        if !matches!(self.state, State::Idle) {
+            return None;
+        }
+        let next_vehicle = self.gate.take_next()?;
+        let service = self.gate.begin_service(&mut self.rng, next_vehicle);
+        let due = Instant::now() + service.arrival.real_duration(service.duration);
+        self.state = State::Serving(service);
+        Some(due)
+    }
+
+    /// Fait entrer le véhicule ayant payé dans la zone de convergence
+    /// ou le fait quitter le péage. La porte reste bloquée si la zone est pleine.
+    fn deliver(&mut self, departed: DepartedVehicle, left_gate: SimpleTime) {
+        match self.gate.merge {
+            Some(ref merge) => match merge.try_enter(departed, left_gate) {
+                Ok(()) => self.free(),
+                Err(rejected) => {
+                    let (departed, left_gate) = *rejected;
+                    self.state = State::Blocked(departed, left_gate);
+                }
+            },
+            None => {
+                self.gate.depart(departed);
+                self.free();
+            }
+        }
+    }
+
+    /// Termine le service du véhicule en train de payer
+    fn finish(&mut self) {
+        match std::mem::replace(&mut self.state, State::Idle) {
+            State::Serving(service) => {
+                let departed = self.gate.end_service(service);
+                let left_gate = departed.departure.clone();
+                self.deliver(departed, left_gate);
+            }
+            state => self.state = state,
+        }
+    }
+
+    /// Retente de faire entrer dans la zone de convergence
+    /// le véhicule bloqué à la porte
+    fn unblock(&mut self) {
+        match std::mem::replace(&mut self.state, State::Idle) {
+            State::Blocked(departed, left_gate) => self.deliver(departed, left_gate),
+            state => self.state = state,
+        }
+    }
+
+    fn free(&mut self) {
+        self.state = State::Idle;
+        self.gate.busy.store(false, Ordering::Relaxed);
+    }
+}
+
+fn launch_scheduler_thread(receiver: Receiver<Message>) {
+    thread::spawn(move || {
+        let mut slots: HashMap<usize, Slot> = HashMap::new();
+        // fins de service à venir, par instant (réel) et numéro d'emplacement
+        let mut dues: BinaryHeap<Reverse<(Instant, usize)>> = BinaryHeap::new();
+        loop {
+            let timeout = match dues.peek() {
+                Some(Reverse((due, _))) => due.saturating_duration_since(Instant::now()),
+                None => Duration::from_secs(3600),
+            };
+            match receiver.recv_timeout(timeout) {
+                Ok(Message::Register(id, gate)) => {
+                    let rng = StdRng::seed_from_u64(gate.seed);
+                    let slot = slots.entry(id).or_insert(Slot { gate: *gate, state: State::Idle, rng });
+                    if let Some(due) = slot.start() {
+                        dues.push(Reverse((due, id)));
+                    }
+                }
+                Ok(Message::Wake(Wake::Gate(id))) => {
+                    if let Some(due) = slots.get_mut(&id).and_then(Slot::start) {
+                        dues.push(Reverse((due, id)));
+                    }
+                }
+                Ok(Message::Wake(Wake::Merge)) => {
+                    let blocked = slots.iter_mut()
+                        .filter(|(_, slot)| matches!(slot.state, State::Blocked(..)));
+                    for (&id, slot) in blocked {
+                        slot.unblock();
+                        if let Some(due) = slot.start() {
+                            dues.push(Reverse((due, id)));
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            while dues.peek().is_some_and(|Reverse((due, _))| *due <= Instant::now()) {
+                let Reverse((_, id)) = dues.pop().unwrap();
+                let slot = slots.get_mut(&id).unwrap();
+                slot.finish();
+                if let Some(due) = slot.start() {
+                    dues.push(Reverse((due, id)));
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+    use rand::Rng;
+    use crate::gate::WaitingVehicle;
+    use crate::sink::MemorySink;
+    use crate::toll::Toll;
+    use crate::vehicle::Vehicle;
+    use super::*;
+
+    /// Passages des mêmes véhicules, par porte et dans l'ordre de passage
+    /// à chaque porte : porte, classe, position dans la file et temps de service
+    fn departures(model: ExecutionModel) -> Vec<(Option<usize>, usize, usize, Duration)> {
+        let memory = MemorySink::new();
+        let toll = Toll::builder()
+            .nb_gates(3)
+            .acceleration_factor(7200)
+            .seed(42)
+            .execution(model)
+            .sink(memory.clone())
+            .build()
+            .unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        for i in 0..30 {
+            toll.gates[i % 3].push(WaitingVehicle::new(rng.gen::<Vehicle>(), toll.clock.clone()));
+        }
+        while toll.gates.iter().any(|gate| !gate.idle()) {
+            sleep(Duration::from_millis(1));
+        }
+        toll.finish();
+        let mut departures: Vec<_> = memory.departures().into_iter()
+            .map(|d| (d.gate, d.vehicle.type_num(), d.queue_position, d.service_time))
+            .collect();
+        // le tri est stable : l'ordre de passage à chaque porte est conservé
+        departures.sort_by_key(|d| d.0);
+        departures
+    }
+
+    #[test]
+    fn scheduler_serves_like_one_thread_per_gate() {
+        let scheduled = departures(ExecutionModel::Scheduler);
+        assert_eq!(scheduled.len(), 30);
+        assert_eq!(scheduled, departures(ExecutionModel::ThreadPerGate));
+    }
+}
@@ -0,0 +1,461 @@
+//! Destinations des enregistrements de la simulation.
+//!
+//! Le thread d'enregistrement transmet chaque message à une destination,
+//! puis lui demande d'écrire les messages reçus à la fin de chaque lot.
+//! Outre la base de données sqlite (`SqliteSink`), les messages peuvent être
+//! écrits dans des fichiers CSV ou JSON Lines, ou conservés en mémoire.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::{create_dir_all, read_dir, read_to_string, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use sqlite::Value;
+use crate::gate::DepartedVehicle;
+use crate::logger::{LogMessage, Record};
+
+/// Erreur survenue lors de l'écriture des enregistrements
+#[derive(Debug)]
+pub enum SinkError {
+    Io(std::io::Error),
+    Sqlite(sqlite::Error),
+}
+
+impl Display for SinkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SinkError::Io(e) => write!(f, "{}", e),
+            SinkError::Sqlite(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SinkError {}
+
+impl From<std::io::Error> for SinkError {
+    fn from(e: std::io::Error) -> Self {
+        SinkError::Io(e)
+    }
+}
+
+impl From<sqlite::Error> for SinkError {
+    fn from(e: sqlite::Error) -> Self {
+        SinkError::Sqlite(e)
+    }
+}
+
+/// Destination des messages du thread d'enregistrement
+pub trait OutputSink: Send {
+    /// Reçoit un message envoyé, au cours du run dont le numéro est donné,
+    /// par le péage dont le numéro est donné.
+    /// Le message peut n'être écrit qu'à l'appel de `flush()`.
+    fn write(&mut self, run: u64, plaza: usize, message: &LogMessage) -> Result<(), SinkError>;
+
+    /// Ecrit les messages reçus depuis le dernier appel
+    fn flush(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Numéro à donner au prochain run enregistré, pour ne pas reprendre
+    /// celui d'un run déjà présent dans la destination
+    fn next_run_id(&self) -> u64 {
+        1
+    }
+}
+
+/// Ecrit les messages dans un fichier CSV par table
+/// (`vehicle.csv`, `staffing.csv`...) dans le répertoire donné.
+/// Les colonnes sont celles des tables de la base de données.
+pub struct CsvSink {
+    dir: PathBuf,
+    files: HashMap<&'static str, BufWriter<File>>,
+    /// Numéro suivant celui du dernier run présent dans les fichiers existants
+    next_run: u64,
+}
+
+impl CsvSink {
+    /// Crée le répertoire s'il n'existe pas.
+    /// Les fichiers existants sont complétés : l'en-tête n'est écrit que dans
+    /// les fichiers vides, et le run reçoit un numéro qui suit ceux déjà présents.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self, SinkError> {
+        create_dir_all(dir.as_ref())?;
+        let mut last_run = 0;
+        for entry in read_dir(dir.as_ref())? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "csv") {
+                last_run = last_run.max(last_run_in_csv(&path)?);
+            }
+        }
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            files: HashMap::new(),
+            next_run: last_run + 1,
+        })
+    }
+}
+
+/// Découpe un texte CSV en lignes et en cellules.
+/// Une cellule entre guillemets peut contenir des virgules et des retours à la ligne.
+fn csv_rows(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => row.push(std::mem::take(&mut cell)),
+            ('\n', false) => {
+                row.push(std::mem::take(&mut cell));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => cell.push(c),
+        }
+    }
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Plus grand numéro de run présent dans un fichier CSV écrit par `CsvSink`,
+/// 0 si le fichier n'en contient aucun
+fn last_run_in_csv(path: &Path) -> Result<u64, SinkError> {
+    let rows = csv_rows(&read_to_string(path)?);
+    let Some(header) = rows.first() else {
+        return Ok(0);
+    };
+    let column = match path.file_stem().is_some_and(|stem| stem == "run") {
+        true => "id",
+        false => "run_id",
+    };
+    let Some(index) = header.iter().position(|c| c == column) else {
+        return Ok(0);
+    };
+    Ok(rows[1..].iter()
+        .filter_map(|row| row.get(index)?.parse::<u64>().ok())
+        .max()
+        .unwrap_or(0))
+}
+
+/// Ecriture d'une valeur dans une cellule CSV
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Integer(n) => n.to_string(),
+        Value::Float(x) => x.to_string(),
+        Value::String(s) if s.contains([',', '"', '\n']) => format!("\"{}\"", s.replace('"', "\"\"")),
+        Value::String(s) => s.clone(),
+        Value::Binary(_) | Value::Null => String::new(),
+    }
+}
+
+impl OutputSink for CsvSink {
+    fn write(&mut self, run: u64, plaza: usize, message: &LogMessage) -> Result<(), SinkError> {
+        let record = message.record(run, plaza);
+        let file = match self.files.get_mut(record.table) {
+            Some(file) => file,
+            None => {
+                let path = self.dir.join(format!("{}.csv", record.table));
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                let empty = file.metadata()?.len() == 0;
+                let mut file = BufWriter::new(file);
+                if empty {
+                    writeln!(file, "{}", record.columns.join(","))?;
+                }
+                self.files.entry(record.table).or_insert(file)
+            }
+        };
+        let cells: Vec<String> = record.values.iter().map(csv_cell).collect();
+        writeln!(file, "{}", cells.join(","))?;
+        Ok(())
+    }
+
+    fn next_run_id(&self) -> u64 {
+        self.next_run
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        for file in self.files.values_mut() {
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Ecrit les messages dans un fichier JSON Lines : un objet par ligne,
+/// dont le champ `table` donne la table de la base de données correspondante
+/// et les autres champs les colonnes de cette table.
+pub struct JsonLinesSink {
+    file: BufWriter<File>,
+}
+
+impl JsonLinesSink {
+    /// Crée le fichier, ou le remplace s'il existe déjà
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, SinkError> {
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+        })
+    }
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push_str(format!("\\u{:04x}", c as u32).as_str()),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::Integer(n) => n.to_string(),
+        Value::Float(x) if x.is_finite() => x.to_string(),
+        Value::String(s) => json_string(s),
+        Value::Float(_) | Value::Binary(_) | Value::Null => "null".to_string(),
+    }
+}
+
+/// Objet JSON représentant la ligne
+pub fn json_object(record: &Record) -> String {
+    let fields: Vec<String> = record.columns.iter()
+        .zip(record.values.iter())
+        .map(|(column, value)| format!("{}:{}", json_string(column), json_value(value)))
+        .collect();
+    format!("{{\"table\":{},{}}}", json_string(record.table), fields.join(","))
+}
+
+impl OutputSink for JsonLinesSink {
+    fn write(&mut self, run: u64, plaza: usize, message: &LogMessage) -> Result<(), SinkError> {
+        writeln!(self.file, "{}", json_object(&message.record(run, plaza)))?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Conserve en mémoire les messages reçus, pour les examiner sans passer
+/// par un fichier. Les clones d'un MemorySink partagent les mêmes messages :
+/// on en garde un clone avant de donner l'original au péage.
+///
+/// ```
+/// # use rsy40::sink::MemorySink;
+/// # use rsy40::toll::Toll;
+/// let memory = MemorySink::new();
+/// let toll = Toll::builder()
+///     .sink(memory.clone())
+///     .build()?;
+/// // ...
+/// let departures = memory.departures();
+/// # Ok::<(), rsy40::error::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    messages: Arc<Mutex<Vec<(usize, LogMessage)>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Messages reçus jusqu'ici, avec le numéro du péage qui les a envoyés
+    pub fn messages(&self) -> Vec<(usize, LogMessage)> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    /// Véhicules ayant quitté le péage jusqu'ici
+    pub fn departures(&self) -> Vec<DepartedVehicle> {
+        self.messages.lock().unwrap()
+            .iter()
+            .filter_map(|(_, message)| match message {
+                LogMessage::Departure(departed) => Some(departed.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Oublie les messages reçus jusqu'ici
+    pub fn clear(&self) {
+        self.messages.lock().unwrap().clear();
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn write(&mut self, _run: u64, plaza: usize, message: &LogMessage) -> Result<(), SinkError> {
+        self.messages.lock().unwrap().push((plaza, message.clone()));
+        Ok(())
+    }
+}
+
+/// Transmet les messages à plusieurs destinations à la fois
+pub struct MultiSink {
+    sinks: Vec<Box<dyn OutputSink>>,
+}
+
+impl MultiSink {
+    pub fn new(sinks: Vec<Box<dyn OutputSink>>) -> Self {
+        Self { sinks }
+    }
+}
+
+impl OutputSink for MultiSink {
+    /// Transmet le message à toutes les destinations, même si l'une d'elles
+    /// échoue. Renvoie la première erreur rencontrée.
+    fn write(&mut self, run: u64, plaza: usize, message: &LogMessage) -> Result<(), SinkError> {
+        self.sinks.iter_mut()
+            .map(|sink| sink.write(run, plaza, message))
+            .fold(Ok(()), Result::and)
+    }
+
+    /// Numéro ne reprenant celui d'aucun run déjà présent dans les destinations
+    fn next_run_id(&self) -> u64 {
+        self.sinks.iter().map(|sink| sink.next_run_id()).max().unwrap_or(1)
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        self.sinks.iter_mut()
+            .map(|sink| sink.flush())
+            .fold(Ok(()), Result::and)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::remove_dir_all;
+    use std::time::{Duration, SystemTime};
+    use rand::prelude::*;
+    use rand::rngs::StdRng;
+    use crate::classification::Classification;
+    use crate::enforcement::honest_assessment;
+    use crate::event::{Event, EventKind};
+    use crate::run::{Run, VERSION};
+    use crate::tariff::Tariff;
+    use crate::toll_clock::SimpleTime;
+    use crate::vehicle::Vehicle;
+
+    fn departure(vehicle: Vehicle) -> LogMessage {
+        let class = vehicle.type_num();
+        LogMessage::Departure(DepartedVehicle {
+            assessment: honest_assessment(&vehicle, &Tariff::default(), class),
+            vehicle,
+            gate: Some(0),
+            queue_position: 0,
+            arrival: SimpleTime::default(),
+            service_start: SimpleTime::default(),
+            service_time: Duration::from_secs(20),
+            departure: SimpleTime::default(),
+            overtaken: 0,
+            imposed_delay: Duration::ZERO,
+            classification: Classification::exact(class),
+            merge_delay: Duration::ZERO,
+            lane_choice_error: false,
+        })
+    }
+
+    fn event(detail: &str) -> LogMessage {
+        LogMessage::Event(Event {
+            time: SimpleTime::default(),
+            kind: EventKind::Arrival,
+            gate: None,
+            vehicle: None,
+            detail: Some(detail.to_string()),
+        })
+    }
+
+    #[test]
+    fn memory_sink_records_departures() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let vehicle: Vehicle = rng.gen();
+        let memory = MemorySink::new();
+        let mut sink = memory.clone();
+        sink.write(1, 2, &departure(vehicle.clone())).unwrap();
+        sink.write(1, 2, &event("arrivée")).unwrap();
+        assert_eq!(memory.messages().len(), 2);
+        assert_eq!(memory.messages()[0].0, 2);
+        let departures = memory.departures();
+        assert_eq!(departures.len(), 1);
+        assert_eq!(departures[0].vehicle.id, vehicle.id);
+        memory.clear();
+        assert!(memory.messages().is_empty());
+    }
+
+    #[test]
+    fn multi_sink_writes_to_every_sink() {
+        let (first, second) = (MemorySink::new(), MemorySink::new());
+        let mut sink = MultiSink::new(vec![Box::new(first.clone()), Box::new(second.clone())]);
+        sink.write(1, 0, &event("a")).unwrap();
+        sink.flush().unwrap();
+        assert_eq!(first.messages().len(), 1);
+        assert_eq!(second.messages().len(), 1);
+    }
+
+    #[test]
+    fn csv_cells_are_escaped() {
+        assert_eq!(csv_cell(&Value::String("abc".to_string())), "abc");
+        assert_eq!(csv_cell(&Value::String("a,b".to_string())), "\"a,b\"");
+        assert_eq!(csv_cell(&Value::String("a \"b\"".to_string())), "\"a \"\"b\"\"\"");
+        assert_eq!(csv_cell(&Value::String("a\nb".to_string())), "\"a\nb\"");
+        assert_eq!(csv_cell(&Value::Null), "");
+        let rows = csv_rows("x,y\n\"a,\"\"b\"\"\nc\",2\n");
+        assert_eq!(rows, vec![vec!["x", "y"], vec!["a,\"b\"\nc", "2"]]);
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+        assert_eq!(json_value(&Value::Float(f64::NAN)), "null");
+        assert_eq!(json_value(&Value::Null), "null");
+    }
+
+    #[test]
+    fn csv_sink_appends_to_existing_files() {
+        let dir = std::env::temp_dir().join(format!("rsy40-csv-{}", std::process::id()));
+        let _ = remove_dir_all(&dir);
+        let run = Run {
+            id: 1,
+            seed: 0,
+            version: VERSION,
+            config: "a, \"b\"\nc".to_string(),
+            started: SystemTime::now(),
+            ended: None,
+            summary: None,
+        };
+        let mut sink = CsvSink::new(&dir).unwrap();
+        assert_eq!(sink.next_run_id(), 1);
+        sink.write(1, 0, &LogMessage::Run(run)).unwrap();
+        sink.write(1, 0, &event("x, y")).unwrap();
+        sink.flush().unwrap();
+        drop(sink);
+
+        let mut sink = CsvSink::new(&dir).unwrap();
+        assert_eq!(sink.next_run_id(), 2);
+        sink.write(2, 0, &event("z")).unwrap();
+        sink.flush().unwrap();
+        drop(sink);
+
+        let rows = csv_rows(&read_to_string(dir.join("event.csv")).unwrap());
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows.iter().filter(|row| row.contains(&"run_id".to_string())).count(), 1);
+        assert_eq!(CsvSink::new(&dir).unwrap().next_run_id(), 3);
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+//! Relevés périodiques de l'état du péage.
+//!
+//! Seuls les véhicules ayant quitté le péage sont enregistrés au fil de l'eau :
+//! les relevés permettent de retracer après coup l'évolution des files d'attente.
+//! A chaque relevé, l'état de chaque porte est enregistré dans la table `snapshot`.
+
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::failure::Outage;
+use crate::gate::Gate;
+use crate::logger::{LogMessage, LogSender};
+use crate::spillback::Upstream;
+use crate::toll_clock::{SimpleTime, TollClock};
+
+/// Etat d'une porte au moment d'un relevé,
+/// destiné à être enregistré en base de données
+#[derive(Debug, Clone)]
+pub struct GateSnapshot {
+    pub time: SimpleTime,
+    pub gate: usize,
+    /// Nombre de véhicules en attente dans la file, sans compter celui en train de payer
+    pub queue_length: usize,
+    pub open: bool,
+    /// Vrai si un véhicule est en train de payer
+    pub busy: bool,
+    /// Panne en cours, None si la porte fonctionne normalement
+    pub outage: Option<Outage>,
+    /// Nombre de véhicules ayant rejoint la file depuis le relevé précédent
+    pub arrivals: u64,
+    /// Nombre de véhicules attendant en amont du péage
+    pub upstream: usize,
+}
+
+/// Lance le thread qui relève l'état des portes à intervalle régulier
+/// (en temps simulé)
+pub fn launch_snapshot_thread(
+    gates: Vec<Gate>,
+    upstream: Option<Arc<Upstream>>,
+    interval: Duration,
+    clock: TollClock,
+    log_sender: Option<LogSender>,
+) {
+    let Some(sender) = log_sender else {
+        return;
+    };
+    if interval.is_zero() {
+        return;
+    }
+    thread::spawn(move || {
+        // les relevés sont calés sur le lancement du thread,
+        // sans accumuler le retard de chaque réveil
+        let start = Instant::now();
+        for n in 1.. {
+            let due = start + clock.real_duration(interval) * n;
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+            let time = clock.now();
+            let upstream = upstream.as_ref().map_or(0, |upstream| upstream.len());
+            for gate in gates.iter() {
+                let snapshot = GateSnapshot {
+                    time: time.clone(),
+                    gate: gate.id,
+                    queue_length: gate.nb_cars(),
+                    open: gate.is_open(),
+                    busy: gate.is_busy(),
+                    outage: gate.outage(),
+                    arrivals: gate.arrivals.swap(0, Ordering::Relaxed),
+                    upstream,
+                };
+                if sender.send(LogMessage::Snapshot(snapshot)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+}
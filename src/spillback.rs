@@ -0,0 +1,187 @@
+//! Capacité de stockage limitée des voies et de la zone d'approche du péage.
+//!
+//! Lorsque le péage est plein, les véhicules qui arrivent patientent dans une
+//! file en amont, c'est-à-dire sur l'autoroute elle-même : c'est la remontée
+//! de file, qui est enregistrée comme indicateur de sécurité.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use crate::gate::{Gate, WaitingVehicle};
+use crate::logger::{LogMessage, LogSender};
+use crate::toll::choose_gate;
+use crate::toll_clock::{SimpleTime, TollClock};
+use crate::vehicle::Vehicle;
+
+/// Capacités de stockage du péage, en mètres
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    /// Longueur de file que peut contenir chaque voie
+    pub lane_capacity: f32,
+    /// Longueur de file que peut contenir la zone d'approche commune,
+    /// une fois les voies pleines
+    pub approach_capacity: f32,
+    /// Longueur occupée dans la file par un véhicule (espacement compris),
+    /// pour chaque classe de véhicule
+    pub vehicle_lengths: [f32; 5],
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            lane_capacity: 60.0,
+            approach_capacity: 300.0,
+            vehicle_lengths: [7.0, 9.0, 14.0, 19.0, 4.0],
+        }
+    }
+}
+
+impl StorageConfig {
+    pub fn vehicle_length(&self, vehicle: &Vehicle) -> f32 {
+        self.vehicle_lengths[vehicle.type_num()]
+    }
+
+    /// Longueur occupée par la file d'une porte
+    fn lane_used(&self, gate: &Gate) -> f32 {
+        gate.queue.lock().unwrap()
+            .iter()
+            .map(|v| self.vehicle_length(&v.vehicle))
+            .sum()
+    }
+
+    /// Renvoie vrai si le véhicule peut rejoindre la file de la porte,
+    /// soit dans la voie elle-même, soit dans la zone d'approche
+    fn fits(&self, gates: &[Gate], gate: &Gate, vehicle: &Vehicle) -> bool {
+        let length = self.vehicle_length(vehicle);
+        if self.lane_used(gate) + length <= self.lane_capacity {
+            return true;
+        }
+        let approach_used: f32 = gates.iter()
+            .map(|g| (self.lane_used(g) - self.lane_capacity).max(0.0))
+            .sum();
+        approach_used + length <= self.approach_capacity
+    }
+}
+
+/// Episode de remontée de file terminé, destiné à être enregistré en base de données
+#[derive(Debug, Clone)]
+pub struct SpillbackRecord {
+    pub start: SimpleTime,
+    pub end: SimpleTime,
+    /// Nombre maximal de véhicules simultanément en attente en amont
+    pub max_vehicles: usize,
+    /// Longueur maximale de la file en amont, en mètres
+    pub max_length: f32,
+}
+
+/// File en amont du péage
+#[derive(Debug)]
+pub struct Upstream {
+    pub config: StorageConfig,
+    pub queue: Mutex<VecDeque<WaitingVehicle>>,
+    /// Condition servant à réveiller le thread de l'amont
+    /// lorsqu'une place se libère dans le péage
+    pub cond: Condvar,
+}
+
+impl Upstream {
+    pub fn new(config: StorageConfig) -> Self {
+        Self {
+            config,
+            queue: Mutex::new(VecDeque::new()),
+            cond: Condvar::new(),
+        }
+    }
+
+    /// Fait entrer le véhicule dans le péage s'il y a de la place
+    /// et si personne n'attend déjà en amont, sinon le place en amont.
+    pub fn admit(&self, gates: &[Gate], vehicle: WaitingVehicle) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.is_empty() {
+            if let Some(gate) = choose_gate(gates, &vehicle.vehicle) {
+                if self.config.fits(gates, gate, &vehicle.vehicle) {
+                    gate.push(vehicle);
+                    return;
+                }
+            }
+        }
+        queue.push_back(vehicle);
+        self.cond.notify_all();
+    }
+
+    /// Fait entrer le véhicule dans la file de la porte qu'il a choisie
+    /// s'il y a de la place et si personne n'attend déjà en amont,
+    /// sinon le place en amont.
+    pub fn admit_to(&self, gates: &[Gate], gate: Option<&Gate>, vehicle: WaitingVehicle) {
+        let mut queue = self.queue.lock().unwrap();
+        if let Some(gate) = gate {
+            if queue.is_empty() && self.config.fits(gates, gate, &vehicle.vehicle) {
+                gate.push(vehicle);
+                return;
+            }
+        }
+        queue.push_back(vehicle);
+        self.cond.notify_all();
+    }
+
+    /// Signale qu'un véhicule a quitté une file du péage
+    pub fn notify_departure(&self) {
+        let _lock = self.queue.lock().unwrap();
+        self.cond.notify_all();
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.lock().unwrap().is_empty()
+    }
+}
+
+/// Lance le thread qui fait entrer dans le péage les véhicules en attente en amont,
+/// dès que de la place se libère
+pub fn launch_upstream_thread(
+    upstream: Arc<Upstream>,
+    gates: Vec<Gate>,
+    clock: TollClock,
+    log_sender: Option<LogSender>,
+) {
+    thread::spawn(move || {
+        let mut episode: Option<SpillbackRecord> = None;
+        let mut queue = upstream.queue.lock().unwrap();
+        loop {
+            while let Some(front) = queue.front() {
+                let gate = match choose_gate(&gates, &front.vehicle) {
+                    Some(gate) if upstream.config.fits(&gates, gate, &front.vehicle) => gate,
+                    _ => break,
+                };
+                gate.push(queue.pop_front().unwrap());
+            }
+            match (queue.is_empty(), episode.as_mut()) {
+                (false, None) => episode = Some(SpillbackRecord {
+                    start: clock.now(),
+                    end: clock.now(),
+                    max_vehicles: 0,
+                    max_length: 0.0,
+                }),
+                (true, Some(_)) => {
+                    let mut record = episode.take().unwrap();
+                    record.end = clock.now();
+                    if let Some(ref sender) = log_sender {
+                        let _ = sender.send(LogMessage::Spillback(record));
+                    }
+                }
+                _ => {}
+            }
+            if let Some(record) = episode.as_mut() {
+                let length: f32 = queue.iter()
+                    .map(|v| upstream.config.vehicle_length(&v.vehicle))
+                    .sum();
+                record.max_vehicles = record.max_vehicles.max(queue.len());
+                record.max_length = record.max_length.max(length);
+            }
+            queue = upstream.cond.wait(queue).unwrap();
+        }
+    });
+}
@@ -0,0 +1,431 @@
+//! Politiques d'ouverture et de fermeture des portes du péage.
+//!
+//! Les voies de covoiturage ne sont jamais concernées :
+//! elles restent ouvertes en permanence.
+//!
+//! Sur un péage desservant les deux sens de circulation, le contrôleur réactif
+//! observe et décide séparément pour chaque sens, et le planning garde
+//! dans chaque sens une porte ouverte acceptant tous les véhicules.
+
+use std::thread;
+use std::time::Duration;
+use crate::direction::Direction;
+use crate::gate::{Gate, LaneType};
+use crate::logger::{LogMessage, LogSender};
+use crate::toll_clock::{SimpleTime, TollClock};
+
+/// Politique de gestion du personnel du péage
+#[derive(Debug, Clone, Default)]
+#[allow(unused)]
+pub enum StaffingPolicy {
+    /// Toutes les portes restent ouvertes en permanence
+    #[default]
+    AllOpen,
+    /// Nombre de portes ouvertes fixé à l'avance pour chaque heure de la journée
+    Schedule([usize; 24]),
+    /// Ouverture et fermeture des portes en fonction de la demande observée
+    Reactive(ReactiveConfig),
+}
+
+/// Paramètres du contrôleur réactif.
+/// Les durées sont exprimées en temps de la simulation.
+#[derive(Debug, Clone)]
+pub struct ReactiveConfig {
+    /// Intervalle entre deux observations des files d'attente
+    pub period: Duration,
+    /// Longueur moyenne de file au-delà de laquelle une porte est ouverte
+    pub open_queue_threshold: f32,
+    /// Longueur moyenne de file en-dessous de laquelle une porte peut être fermée.
+    /// Doit être inférieure à `open_queue_threshold` (hystérésis)
+    pub close_queue_threshold: f32,
+    /// Temps d'attente prévu au-delà duquel une porte est ouverte
+    pub open_wait_threshold: Duration,
+    /// Temps moyen de paiement utilisé pour prévoir le temps d'attente
+    pub mean_service_time: Duration,
+    /// Durée pendant laquelle une porte doit rester vide avant d'être fermée
+    pub idle_before_close: Duration,
+    /// Durée minimale d'ouverture d'une porte
+    pub min_open_time: Duration,
+    /// Nombre minimal de portes ouvertes dans chaque sens desservi, au moins 1
+    pub min_open: usize,
+    /// Nombre maximal de portes ouvertes, tous sens confondus (effectif disponible).
+    /// Il peut être dépassé pour garder `min_open` portes ouvertes dans chaque sens
+    pub max_open: usize,
+}
+
+impl Default for ReactiveConfig {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_secs(60),
+            open_queue_threshold: 3.0,
+            close_queue_threshold: 1.0,
+            open_wait_threshold: Duration::from_secs(180),
+            mean_service_time: Duration::from_secs(45),
+            idle_before_close: Duration::from_secs(600),
+            min_open_time: Duration::from_secs(900),
+            min_open: 1,
+            max_open: usize::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum StaffingAction {
+    Open,
+    Close,
+}
+
+/// Décision d'ouverture ou de fermeture d'une porte,
+/// destinée à être enregistrée en base de données
+#[derive(Debug, Clone)]
+pub struct StaffingDecision {
+    pub time: SimpleTime,
+    pub gate: usize,
+    pub action: StaffingAction,
+    /// Raison de la décision, lisible par un humain
+    pub reason: String,
+    /// Longueur moyenne des files des portes ouvertes au moment de la décision
+    pub avg_queue: f32,
+    /// Temps d'attente prévu au moment de la décision
+    pub predicted_wait: Duration,
+    /// Nombre de portes ouvertes après la décision
+    /// (dans le sens de la porte pour le contrôleur réactif)
+    pub open_gates: usize,
+}
+
+impl StaffingPolicy {
+    /// Nombre de portes à ouvrir au lancement de la simulation
+    pub fn initial_open(&self, hour: u32, nb_gates: usize) -> usize {
+        match self {
+            StaffingPolicy::AllOpen => nb_gates,
+            StaffingPolicy::Schedule(schedule) => schedule[hour as usize % 24],
+            StaffingPolicy::Reactive(config) => config.min_open,
+        }.clamp(1.min(nb_gates), nb_gates)
+    }
+}
+
+/// Etat du contrôleur pour chacune des portes qu'il gère
+#[derive(Clone, Copy)]
+struct GateRecord {
+    /// Instant (en secondes de simulation) de la dernière ouverture
+    opened_at: u64,
+    /// Instant depuis lequel la porte est inoccupée
+    idle_since: Option<u64>,
+}
+
+/// Lance le thread chargé d'appliquer la politique d'ouverture des portes.
+/// `gates` ne doit pas contenir les voies de covoiturage.
+///
+/// Si la politique est `AllOpen`, aucun thread n'est lancé.
+pub fn launch_staffing_thread(
+    policy: StaffingPolicy,
+    gates: Vec<Gate>,
+    clock: TollClock,
+    log_sender: Option<LogSender>,
+) {
+    if gates.is_empty() {
+        return;
+    }
+    let period = match &policy {
+        StaffingPolicy::AllOpen => return,
+        StaffingPolicy::Schedule(_) => Duration::from_secs(60),
+        StaffingPolicy::Reactive(config) => config.period,
+    };
+    thread::spawn(move || {
+        let start = clock.now().as_secs();
+        let mut records: Vec<GateRecord> = gates.iter()
+            .map(|_| GateRecord { opened_at: start, idle_since: None })
+            .collect();
+        loop {
+            thread::sleep(clock.real_duration(period));
+            let now = clock.now();
+            let secs = now.as_secs();
+            for (gate, record) in gates.iter().zip(records.iter_mut()) {
+                record.idle_since = match gate.idle() {
+                    true => record.idle_since.or(Some(secs)),
+                    false => None,
+                };
+            }
+            let decisions = match &policy {
+                StaffingPolicy::AllOpen => Vec::new(),
+                StaffingPolicy::Schedule(schedule) =>
+                    apply_schedule(schedule, &gates, &records, &now).into_iter().collect(),
+                StaffingPolicy::Reactive(config) =>
+                    apply_reactive(config, &gates, &records, &now),
+            };
+            for decision in decisions {
+                let i = gates.iter().position(|g| g.id == decision.gate).unwrap();
+                if let StaffingAction::Open = decision.action {
+                    records[i].opened_at = secs;
+                }
+                gates[i].set_open(matches!(decision.action, StaffingAction::Open));
+                if let Some(ref sender) = log_sender {
+                    let _ = sender.send(LogMessage::Staffing(decision));
+                }
+            }
+        }
+    });
+}
+
+/// Longueur moyenne des files des portes ouvertes
+fn average_queue(gates: &[Gate]) -> f32 {
+    let open: Vec<&Gate> = gates.iter().filter(|g| g.is_open()).collect();
+    if open.is_empty() {
+        return 0.0;
+    }
+    let total: usize = open.iter()
+        .map(|g| g.nb_cars() + g.is_busy() as usize)
+        .sum();
+    total as f32 / open.len() as f32
+}
+
+/// Choisit la porte fermée à ouvrir (la première disponible)
+fn gate_to_open(gates: &[Gate]) -> Option<usize> {
+    gates.iter().position(|g| !g.is_open())
+}
+
+/// Choisit parmi les portes ouvertes et inoccupées depuis au moins `idle`
+/// celle qui est inoccupée depuis le plus longtemps
+fn gate_to_close(
+    gates: &[Gate], records: &[GateRecord], now: u64, idle: u64, min_open: u64,
+) -> Option<usize> {
+    gates.iter()
+        .zip(records)
+        .enumerate()
+        .filter(|(_, (g, r))| g.is_open() && now.saturating_sub(r.opened_at) >= min_open)
+        .filter_map(|(i, (_, r))| r.idle_since.map(|since| (i, since)))
+        .filter(|&(_, since)| now.saturating_sub(since) >= idle)
+        .min_by_key(|&(_, since)| since)
+        .map(|(i, _)| i)
+}
+
+/// Vrai si la porte est la dernière porte ouverte qui permet à tous les véhicules
+/// de son sens de passer : la dernière voie mixte ouverte du sens,
+/// ou la dernière porte ouverte du sens s'il n'est desservi par aucune voie mixte
+fn last_for_direction(gates: &[Gate], gate: &Gate) -> bool {
+    let direction = gate.direction();
+    let served: Vec<&Gate> = gates.iter().filter(|g| g.direction() == direction).collect();
+    let has_mixed = served.iter().any(|g| g.lane_type == LaneType::Mixed);
+    let required = |g: &Gate| !has_mixed || g.lane_type == LaneType::Mixed;
+    required(gate) && served.iter().filter(|g| g.is_open() && required(g)).count() <= 1
+}
+
+/// Sens desservi par des portes dont aucune ne permet à tous ses véhicules
+/// de passer, et porte à ouvrir pour y remédier
+fn stranded_direction(gates: &[Gate]) -> Option<(Direction, usize)> {
+    [Direction::Outbound, Direction::Inbound].into_iter().find_map(|direction| {
+        let served: Vec<usize> = (0..gates.len())
+            .filter(|&i| gates[i].direction() == direction)
+            .collect();
+        let mixed: Vec<usize> = served.iter()
+            .copied()
+            .filter(|&i| gates[i].lane_type == LaneType::Mixed)
+            .collect();
+        let required = if mixed.is_empty() { served } else { mixed };
+        match required.iter().any(|&i| gates[i].is_open()) {
+            true => None,
+            false => required.first().map(|&i| (direction, i)),
+        }
+    })
+}
+
+/// Applique le planning au nombre total de portes ouvertes, en gardant
+/// dans chaque sens desservi une porte ouverte acceptant tous les véhicules
+/// (une voie mixte s'il y en a une)
+fn apply_schedule(
+    schedule: &[usize; 24], gates: &[Gate], records: &[GateRecord], now: &SimpleTime,
+) -> Option<StaffingDecision> {
+    let target = schedule[now.hour as usize].clamp(1, gates.len());
+    let nb_open = gates.iter().filter(|g| g.is_open()).count();
+    let avg_queue = average_queue(gates);
+    let (gate, action, reason) = if let Some((direction, gate)) = stranded_direction(gates) {
+        let reason = format!(
+            "planning : aucune porte ouverte pour tous les véhicules du sens {}",
+            direction as usize + 1,
+        );
+        (gate, StaffingAction::Open, reason)
+    } else if nb_open < target {
+        let reason = format!("planning : {} portes à {}h", target, now.hour);
+        (gate_to_open(gates)?, StaffingAction::Open, reason)
+    } else if nb_open > target {
+        // une porte n'est fermée qu'une fois vidée, et jamais si son sens en dépend
+        let (closable, closable_records): (Vec<Gate>, Vec<GateRecord>) = gates.iter()
+            .zip(records)
+            .filter(|(g, _)| !last_for_direction(gates, g))
+            .map(|(g, r)| (g.clone(), *r))
+            .unzip();
+        let gate = gate_to_close(&closable, &closable_records, now.as_secs(), 0, 0)?;
+        let gate = gates.iter().position(|g| g.id == closable[gate].id).unwrap();
+        let reason = format!("planning : {} portes à {}h", target, now.hour);
+        (gate, StaffingAction::Close, reason)
+    } else {
+        return None;
+    };
+    Some(StaffingDecision {
+        time: now.clone(),
+        gate: gates[gate].id,
+        action,
+        reason,
+        avg_queue,
+        predicted_wait: Duration::ZERO,
+        open_gates: match action {
+            StaffingAction::Open => nb_open + 1,
+            StaffingAction::Close => nb_open - 1,
+        },
+    })
+}
+
+/// Décide séparément pour chaque sens de circulation, d'après les portes
+/// qui le desservent actuellement. Au plus une décision est prise par sens.
+fn apply_reactive(
+    config: &ReactiveConfig, gates: &[Gate], records: &[GateRecord], now: &SimpleTime,
+) -> Vec<StaffingDecision> {
+    let mut total_open = gates.iter().filter(|g| g.is_open()).count();
+    let mut decisions = Vec::new();
+    for direction in [Direction::Outbound, Direction::Inbound] {
+        let (served, served_records): (Vec<Gate>, Vec<GateRecord>) = gates.iter()
+            .zip(records)
+            .filter(|(g, _)| g.direction() == direction)
+            .map(|(g, r)| (g.clone(), *r))
+            .unzip();
+        if served.is_empty() {
+            continue;
+        }
+        let can_open = total_open < config.max_open;
+        if let Some(decision) = apply_reactive_direction(
+            config, direction, can_open, &served, &served_records, now,
+        ) {
+            match decision.action {
+                StaffingAction::Open => total_open += 1,
+                StaffingAction::Close => total_open -= 1,
+            }
+            decisions.push(decision);
+        }
+    }
+    decisions
+}
+
+/// Décision du contrôleur réactif pour les portes desservant un même sens
+fn apply_reactive_direction(
+    config: &ReactiveConfig, direction: Direction, can_open: bool,
+    gates: &[Gate], records: &[GateRecord], now: &SimpleTime,
+) -> Option<StaffingDecision> {
+    let nb_open = gates.iter().filter(|g| g.is_open()).count();
+    let avg_queue = average_queue(gates);
+    let predicted_wait = config.mean_service_time.mul_f32(avg_queue);
+    let min_open = config.min_open.max(1);
+    let sens = direction as usize + 1;
+    let (gate, action, reason) = if nb_open < min_open.min(gates.len()) {
+        // chaque sens desservi garde au moins min_open portes ouvertes
+        let reason = format!("sens {} : {} porte(s) ouverte(s) < {}", sens, nb_open, min_open);
+        (gate_to_open(gates)?, StaffingAction::Open, reason)
+    } else if can_open && nb_open < gates.len()
+        && (avg_queue > config.open_queue_threshold
+        || predicted_wait > config.open_wait_threshold) {
+        let reason = match avg_queue > config.open_queue_threshold {
+            true => format!(
+                "sens {} : file moyenne {:.1} > {:.1}", sens, avg_queue, config.open_queue_threshold,
+            ),
+            false => format!(
+                "sens {} : attente prévue {}s > {}s",
+                sens, predicted_wait.as_secs(), config.open_wait_threshold.as_secs()
+            ),
+        };
+        (gate_to_open(gates)?, StaffingAction::Open, reason)
+    } else if nb_open > min_open && avg_queue < config.close_queue_threshold {
+        let gate = gate_to_close(
+            gates,
+            records,
+            now.as_secs(),
+            config.idle_before_close.as_secs(),
+            config.min_open_time.as_secs(),
+        )?;
+        let reason = format!(
+            "sens {} : file moyenne {:.1} < {:.1}, porte inoccupée",
+            sens, avg_queue, config.close_queue_threshold
+        );
+        (gate, StaffingAction::Close, reason)
+    } else {
+        return None;
+    };
+    Some(StaffingDecision {
+        time: now.clone(),
+        gate: gates[gate].id,
+        action,
+        reason,
+        avg_queue,
+        predicted_wait,
+        open_gates: match action {
+            StaffingAction::Open => nb_open + 1,
+            StaffingAction::Close => nb_open - 1,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use rand::rngs::StdRng;
+    use crate::gate::WaitingVehicle;
+    use crate::toll_clock::TollClock;
+
+    /// Portes 0 et 1 dans le sens 1, portes 2 et 3 dans le sens 2
+    fn two_way_gates(open: [bool; 4]) -> (Vec<Gate>, Vec<GateRecord>) {
+        let gates: Vec<Gate> = (0..4).map(Gate::new).collect();
+        for (gate, open) in gates.iter().zip(open) {
+            gate.set_open(open);
+            if gate.id >= 2 {
+                gate.set_direction(Direction::Inbound);
+            }
+        }
+        let records = vec![GateRecord { opened_at: 0, idle_since: Some(0) }; 4];
+        (gates, records)
+    }
+
+    fn opened(decisions: &[StaffingDecision]) -> Vec<usize> {
+        decisions.iter()
+            .filter(|d| matches!(d.action, StaffingAction::Open))
+            .map(|d| d.gate)
+            .collect()
+    }
+
+    #[test]
+    fn each_direction_keeps_an_open_gate() {
+        let (gates, records) = two_way_gates([true, false, false, false]);
+        let decisions = apply_reactive(&ReactiveConfig::default(), &gates, &records, &SimpleTime::default());
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(opened(&decisions), vec![2]);
+    }
+
+    #[test]
+    fn schedule_keeps_an_open_gate_in_each_direction() {
+        let mut schedule = [4; 24];
+        schedule[7] = 1;
+        // planning à une seule porte : la porte du sens 2 reste ouverte
+        let (gates, records) = two_way_gates([true, true, true, false]);
+        let now = SimpleTime::default();
+        let decision = apply_schedule(&schedule, &gates, &records, &now).unwrap();
+        assert!(matches!(decision.action, StaffingAction::Close));
+        assert!(decision.gate < 2);
+        gates[decision.gate].set_open(false);
+        assert!(apply_schedule(&schedule, &gates, &records, &now).is_none());
+        // un sens sans porte ouverte en récupère une, même au-delà du planning
+        let (gates, records) = two_way_gates([true, false, false, false]);
+        let decision = apply_schedule(&schedule, &gates, &records, &now).unwrap();
+        assert!(matches!(decision.action, StaffingAction::Open));
+        assert_eq!(decision.gate, 2);
+    }
+
+    #[test]
+    fn queues_of_one_direction_do_not_open_gates_in_the_other() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (gates, records) = two_way_gates([true, false, true, false]);
+        for _ in 0..5 {
+            gates[0].push(WaitingVehicle::new(rng.gen(), TollClock::default()));
+        }
+        let decisions = apply_reactive(&ReactiveConfig::default(), &gates, &records, &SimpleTime::default());
+        assert_eq!(opened(&decisions), vec![1]);
+        assert_eq!(decisions.len(), 1);
+    }
+}
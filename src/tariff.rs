@@ -0,0 +1,32 @@
+//! Grille tarifaire du péage
+
+use crate::vehicle::Vehicle;
+
+/// Prix au kilomètre pour chaque classe de véhicule, en euros
+#[derive(Debug, Clone)]
+pub struct Tariff {
+    pub per_km: [f32; 5],
+}
+
+impl Default for Tariff {
+    fn default() -> Self {
+        Self { per_km: [0.10, 0.15, 0.22, 0.30, 0.06] }
+    }
+}
+
+impl Tariff {
+    /// Prix dû par le véhicule pour le trajet parcouru.
+    /// Les véhicules prioritaires ne paient pas.
+    pub fn price(&self, vehicle: &Vehicle) -> f32 {
+        self.price_for_class(vehicle, vehicle.type_num())
+    }
+
+    /// Prix dû par le véhicule pour le trajet parcouru
+    /// s'il appartenait à la classe donnée
+    pub fn price_for_class(&self, vehicle: &Vehicle, class: usize) -> f32 {
+        match vehicle.category.is_priority() {
+            true => 0.0,
+            false => self.per_km[class] * vehicle.nb_kilometres.max(0.0),
+        }
+    }
+}
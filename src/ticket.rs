@@ -0,0 +1,104 @@
+//! Gare d'entrée d'une autoroute fermée : les véhicules y prennent un ticket
+//! au lieu de payer.
+//!
+//! La délivrance du ticket est brève mais variable. Les véhicules équipés
+//! du télépéage passent sans s'arrêter : leur badge tient lieu de ticket.
+
+use std::time::Duration;
+use rand::Rng;
+use crate::distribution::ServiceDistribution;
+use crate::vehicle::{PaymentMean, ServicePhases, Vehicle};
+
+/// Paramètres de la délivrance des tickets à une gare d'entrée
+#[derive(Debug, Clone)]
+pub struct TicketConfig {
+    /// Loi du temps de délivrance d'un ticket, entre l'arrêt du véhicule
+    /// et la prise du ticket par le conducteur
+    pub issue_time: ServiceDistribution,
+}
+
+impl Default for TicketConfig {
+    fn default() -> Self {
+        Self {
+            // environ 4 s, rarement plus de 8 s
+            issue_time: ServiceDistribution::LogNormal { mu: 1.4, sigma: 0.35 },
+        }
+    }
+}
+
+impl TicketConfig {
+    /// Temps passé par le véhicule à la porte d'entrée.
+    /// Un véhicule équipé du télépéage ou prioritaire passe sans s'arrêter :
+    /// seul reste le temps de franchissement de la barrière.
+    pub fn service_phases<R: Rng + ?Sized>(&self, rng: &mut R, vehicle: &Vehicle) -> ServicePhases {
+        let mut phases = vehicle.service_phases(rng);
+        if vehicle.payment_mean == PaymentMean::Toll || vehicle.category.is_priority() {
+            phases.approach = Duration::ZERO;
+            phases.payment = Duration::ZERO;
+        } else {
+            phases.payment = self.issue_time.sample(rng);
+        }
+        phases
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use crate::gate::{Gate, WaitingVehicle};
+    use crate::toll_clock::TollClock;
+    use crate::vehicle::VehicleCategory;
+    use super::*;
+
+    fn config() -> TicketConfig {
+        TicketConfig { issue_time: ServiceDistribution::Deterministic(Duration::from_secs(4)) }
+    }
+
+    fn vehicle(rng: &mut StdRng, payment_mean: PaymentMean, category: VehicleCategory) -> Vehicle {
+        let mut vehicle: Vehicle = rng.gen();
+        vehicle.payment_mean = payment_mean;
+        vehicle.category = category;
+        vehicle
+    }
+
+    #[test]
+    fn only_vehicles_without_badge_stop_for_a_ticket() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let cash = vehicle(&mut rng, PaymentMean::Cash, VehicleCategory::Regular);
+        let phases = config().service_phases(&mut rng, &cash);
+        assert_eq!(phases.payment, Duration::from_secs(4));
+        assert!(phases.approach > Duration::ZERO);
+
+        for vehicle in [
+            vehicle(&mut rng, PaymentMean::Toll, VehicleCategory::Regular),
+            vehicle(&mut rng, PaymentMean::Cash, VehicleCategory::Police),
+        ] {
+            let phases = config().service_phases(&mut rng, &vehicle);
+            assert_eq!(phases.approach + phases.payment, Duration::ZERO);
+            assert!(phases.clearance > Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn entry_gate_issues_a_ticket_without_charging() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut gate = Gate::new(0);
+        gate.ticket = Some(config());
+        // le temps de paiement de la porte ne s'applique pas à une gare d'entrée
+        gate.payment_times[PaymentMean::Cash as usize] =
+            Some(ServiceDistribution::Deterministic(Duration::from_secs(100)));
+        let mut clock = TollClock::default();
+        clock.acceleration_factor = 1;
+        let cash = vehicle(&mut rng, PaymentMean::Cash, VehicleCategory::Regular);
+        gate.push(WaitingVehicle::new(cash, clock));
+        let next = gate.take_next().unwrap();
+        let service = gate.begin_service(&mut rng, next);
+        assert!(service.duration < Duration::from_secs(100));
+        let departed = gate.end_service(service);
+        assert_eq!((departed.assessment.paid, departed.assessment.unpaid), (0.0, 0.0));
+        let issued = departed.vehicle.ticket.expect("the vehicle should hold a ticket");
+        assert!(issued.as_secs() > departed.service_start.as_secs());
+        assert!(issued.as_secs() < departed.service_start.as_secs() + departed.service_time.as_secs());
+    }
+}
@@ -1,11 +1,34 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
 use std::time::Duration;
 use rand::Rng;
 use rand::prelude::*;
-use crate::gate::{Gate, WaitingVehicle};
-use crate::logger::TollDatabase;
-use crate::toll_clock::TollClock;
-use crate::vehicle::Vehicle;
+use crate::approach::{Approach, ApproachConfig};
+use crate::enforcement::{honest_assessment, Assessment, EnforcementConfig};
+use crate::error::{ConfigError, Error};
+use crate::event::{EventKind, EventLog};
+use crate::gate::{depart, DepartedVehicle, Gate, LaneType, PriorityPolicy, WaitingVehicle};
+use crate::logger::{
+    chosen_mode, LogMessage, LoggerConfig, LoggerReport, OpenMode, SqliteSink, TollDatabase,
+};
+use crate::classification::{Classification, ClassificationConfig};
+use crate::direction::{launch_reversal_thread, Direction};
+use crate::distribution::ServiceDistribution;
+use crate::failure::{launch_failure_thread, FailureModel};
+use crate::free_flow::{FreeFlowConfig, FreeFlowPlaza, ModeComparison, RevenueStats};
+use crate::merge::{launch_merge_thread, Merge, MergeConfig};
+use crate::run::{derive_seed, VERSION};
+use crate::scheduler::{ExecutionModel, Scheduler};
+use crate::sink::{MultiSink, OutputSink};
+use crate::snapshot::launch_snapshot_thread;
+use crate::spillback::{launch_upstream_thread, StorageConfig, Upstream};
+use crate::staffing::{launch_staffing_thread, StaffingPolicy};
+use crate::tariff::Tariff;
+use crate::ticket::TicketConfig;
+use crate::toll_clock::{SimpleTime, TollClock};
+use crate::vehicle::{PaymentMean, Vehicle};
 
 /// Péage
 pub struct Toll {
@@ -17,38 +40,193 @@ pub struct Toll {
     /// des flux de voitures de ce péage.
     /// Si logger vaut None, aucun enregistrement n'a lieu
     pub logger: Option<TollDatabase>,
+    /// File en amont du péage.
+    /// Si upstream vaut None, la capacité de stockage du péage n'est pas limitée
+    pub upstream: Option<Arc<Upstream>>,
+    /// Zone d'approche du péage.
+    /// Si approach vaut None, les véhicules choisissent leur porte en connaissant
+    /// exactement toutes les files et y entrent immédiatement
+    pub approach: Option<Approach>,
+    /// Zone de convergence en aval du péage.
+    /// Si merge vaut None, les véhicules quittent le péage dès la barrière franchie
+    pub merge: Option<Arc<Merge>>,
+    /// Traitement des véhicules prioritaires
+    pub priority_policy: PriorityPolicy,
+    /// Mode de fonctionnement du péage
+    pub mode: PlazaMode,
+    /// Portique en flux libre fictif, qui voit passer le même trafic
+    /// que le péage à barrières afin de comparer les deux modes.
+    /// Vaut None si aucune comparaison n'est demandée.
+    pub shadow_free_flow: Option<FreeFlowPlaza>,
+    /// Nombre de véhicules arrivés au péage à barrières
+    barrier_arrivals: u64,
+    /// Passages et recettes des véhicules ayant franchi les barrières,
+    /// partagés avec les portes
+    barrier_revenue: Arc<Mutex<RevenueStats>>,
+    /// Grille tarifaire du péage à barrières
+    tariff: Tariff,
+    /// Heure de début de la simulation
+    start: SimpleTime,
+    /// Paramètres de la fraude et des contrôles, None s'il n'y a pas de fraude
+    enforcement: Option<EnforcementConfig>,
+    /// Sender servant à transmettre au réseau autoroutier les véhicules
+    /// qui poursuivent leur trajet, None si le péage est isolé
+    onward: Option<Sender<Vehicle>>,
+    /// Taux d'arrivée des véhicules (par seconde) pour chaque heure de la journée,
+    /// pour chaque sens de circulation (indexé par `Direction as usize`)
+    arrival_rates: [[f64; 24]; 2],
+    /// Journal des événements de la simulation, None si les événements
+    /// ne sont pas enregistrés
+    events: Option<EventLog>,
+    /// Graine du générateur aléatoire de la simulation
+    pub seed: u64,
+    /// Générateur aléatoire du péage (flux libre, fraude, choix de la file)
+    rng: StdRng,
+}
+
+/// Taux d'arrivée des véhicules par défaut (par seconde), pour chaque heure
+/// de la journée
+pub static DEFAULT_ARRIVAL_RATES: [f64; 24] = [
+    0.0125, 0.01, 0.00909, 0.0125, 0.0125, 0.014285,
+    0.033333, 0.066666, 0.232558, 0.2, 0.05, 0.04,
+    0.04, 0.033333, 0.028571, 0.04, 0.1, 0.25,
+    0.1, 0.033333, 0.025, 0.02, 0.016666, 0.014285
+];
+
+/// Mode de fonctionnement du péage
+#[derive(Debug, Clone, Default)]
+#[allow(clippy::large_enum_variant)]
+pub enum PlazaMode {
+    /// Péage classique : les véhicules s'arrêtent à une porte pour payer
+    #[default]
+    Barrier,
+    /// Péage en flux libre : les véhicules passent sans s'arrêter
+    /// sous un portique, aucune porte n'est utilisée
+    FreeFlow(FreeFlowPlaza),
+    /// Gare d'entrée : les véhicules prennent un ticket à une porte sans payer
+    /// (la délivrance des tickets est paramétrée au niveau des portes)
+    Entry,
 }
 
 impl Toll {
     pub fn builder() -> TollBuilder {
-        TollBuilder::default()
+        TollBuilder::default().nb_gates(10)
     }
 
-    pub fn add_vehicle(&mut self, vehicle: Vehicle) {
-        let gates = match vehicle.carpooling() {
-            true => self.gates.as_slice(),
-            false => self.gates.split_last().unwrap().1
-        };
-        let less_crowded_gate = gates.iter()
-            .filter(|&gate| !gate.empty())
-            .min_by(|a, b| a.nb_cars().cmp(&b.nb_cars()));
-        let gate = match less_crowded_gate {
-            None => gates.get(0).unwrap(),
-            Some(gate) => match gate.nb_cars() < 5 {
-                true => gate,
-                false => gates.iter()
-                    .find(|gate| gate.empty())
-                    .unwrap_or(gate)
+    /// Fait arriver un véhicule au péage.
+    ///
+    /// Renvoie une erreur si le thread d'enregistrement a rencontré une erreur
+    /// depuis l'appel précédent ou s'est arrêté : le véhicule n'est alors pas ajouté.
+    /// Renvoie également une erreur si aucune porte ouverte n'accepte le véhicule
+    /// (et que la capacité de stockage du péage n'est pas limitée).
+    pub fn add_vehicle(&mut self, vehicle: Vehicle) -> Result<(), Error> {
+        if let Some(ref logger) = self.logger {
+            logger.check()?;
+        }
+        self.admit(vehicle)
+    }
+
+    /// Fait arriver un véhicule au péage, sans vérifier l'état
+    /// du thread d'enregistrement
+    pub(crate) fn admit(&mut self, mut vehicle: Vehicle) -> Result<(), Error> {
+        vehicle.identify();
+        if let Some(ref events) = self.events {
+            events.emit_at(
+                self.clock.clock.clone(),
+                EventKind::Arrival,
+                None,
+                Some(vehicle.id),
+                Some(vehicle.direction.name().to_string()),
+            );
+        }
+        if let PlazaMode::FreeFlow(ref mut plaza) = self.mode {
+            let passage = plaza.pass(&mut self.rng, &vehicle, self.clock.clock.clone());
+            let crossing = plaza.config.crossing_time;
+            let assessment = Assessment {
+                paid: passage.collected,
+                unpaid: passage.due - passage.collected,
+                ..honest_assessment(&vehicle, &plaza.config.tariff, vehicle.type_num())
+            };
+            self.log_passage(vehicle, crossing, assessment);
+            if let Some(ref logger) = self.logger {
+                let _ = logger.sender.send(LogMessage::FreeFlow(passage));
             }
+            return Ok(());
+        }
+        if let Some(ref mut plaza) = self.shadow_free_flow {
+            plaza.pass(&mut self.rng, &vehicle, self.clock.clock.clone());
+        }
+        self.barrier_arrivals += 1;
+        if let PriorityPolicy::ServicePassage(crossing) = self.priority_policy {
+            if vehicle.category.is_priority() {
+                let assessment = honest_assessment(&vehicle, &self.tariff, vehicle.type_num());
+                self.barrier_revenue.lock().unwrap().add_passage(&assessment);
+                self.log_passage(vehicle, crossing, assessment);
+                return Ok(());
+            }
+        }
+        if let Some(ref config) = self.enforcement {
+            vehicle.evasion = config.badge_fraud(&mut self.rng, &vehicle);
+        }
+        let vehicle = WaitingVehicle::new(vehicle, self.clock.clone());
+        match (&self.approach, &self.upstream) {
+            (Some(approach), _) => approach.arrive(&mut self.rng, &self.gates, vehicle)?,
+            (None, Some(upstream)) => upstream.admit(&self.gates, vehicle),
+            (None, None) => choose_gate(&self.gates, &vehicle.vehicle)
+                .ok_or(Error::NoGate(vehicle.vehicle.id))?
+                .push(vehicle),
+        }
+        Ok(())
+    }
+
+    /// Fait traverser le péage au véhicule sans passer par une porte
+    /// (passage de service ou flux libre) ni par la zone de convergence
+    fn log_passage(&self, vehicle: Vehicle, crossing: Duration, assessment: Assessment) {
+        let arrival = self.clock.clock.clone();
+        let departed = DepartedVehicle {
+            vehicle,
+            gate: None,
+            queue_position: 0,
+            departure: arrival.clone() + crossing,
+            service_start: arrival.clone(),
+            service_time: crossing,
+            arrival,
+            overtaken: 0,
+            imposed_delay: Duration::ZERO,
+            classification: Classification::exact(assessment.detected_class),
+            assessment,
+            merge_delay: Duration::ZERO,
+            lane_choice_error: false,
         };
-        gate.queue
-            .lock()
-            .unwrap()
-            .push_back(WaitingVehicle {
-                vehicle,
-                arrival: self.clock.clone(),
-            });
-        gate.cond.notify_all();
+        depart(
+            departed,
+            self.onward.as_ref(),
+            self.logger.as_ref().map(|db| &db.sender),
+            self.events.as_ref(),
+        );
+    }
+
+    /// Compare le péage à barrières au portique en flux libre fictif
+    /// sur le trafic écoulé depuis le début de la simulation.
+    /// Renvoie None si la comparaison n'a pas été demandée à la construction du péage.
+    ///
+    /// Côté barrières, seuls les véhicules ayant franchi la barrière sont comptés
+    /// comme passés, avec ce qu'ils ont réellement payé (fraude comprise).
+    /// Les véhicules d'une gare d'entrée ne doivent rien.
+    #[allow(unused)]
+    pub fn comparison(&self) -> Option<ModeComparison> {
+        let free_flow = self.shadow_free_flow.as_ref()?.stats.clone();
+        let barrier = RevenueStats {
+            arrived: self.barrier_arrivals,
+            ..self.barrier_revenue.lock().unwrap().clone()
+        };
+        Some(ModeComparison {
+            elapsed: Duration::from_secs(
+                self.clock.clock.as_secs().saturating_sub(self.start.as_secs())
+            ),
+            barrier,
+            free_flow,
+        })
     }
 
     /// Renvoie le temps qui s'écoulera avant l'arrivée de la porchaine voiture.
@@ -59,45 +237,187 @@ impl Toll {
     /// Cette fonction doit être appelée le plus tôt possible après la création
     /// de la dernière voiture.
     ///
-    /// ```
-    /// let toll = Toll::builder().build(); // Péage par défaut
+    /// ```no_run
+    /// # use std::thread;
+    /// # use rand::Rng;
+    /// # use rsy40::toll::Toll;
+    /// # use rsy40::vehicle::Vehicle;
+    /// let mut toll = Toll::builder().build()?; // Péage par défaut
     /// let mut rng = rand::thread_rng();
     ///
     /// loop {
-    ///     let v = Vehicle::new();
+    ///     let v: Vehicle = rng.gen();
     ///     let waiting_time = toll.time_until_next_vehicle(&mut rng);
-    ///     toll.add_vehicle(v);
+    ///     toll.add_vehicle(v)?;
     ///     thread::sleep(waiting_time);
     /// }
+    /// # Ok::<(), rsy40::error::Error>(())
     /// ```
     pub fn time_until_next_vehicle<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
-        static LAMBDAS: [f64; 24] = [
-            0.0125, 0.01, 0.00909, 0.0125, 0.0125, 0.014285,
-            0.033333, 0.066666, 0.232558, 0.2, 0.05, 0.04,
-            0.04, 0.033333, 0.028571, 0.04, 0.1, 0.25,
-            0.1, 0.033333, 0.025, 0.02, 0.016666, 0.014285
-        ];
-        let current_lambda = LAMBDAS[self.clock.clock.hour as usize];
+        let current_lambda = self.arrival_rates[Direction::Outbound as usize][self.clock.clock.hour as usize];
         let generator = rand_distr::Exp::new(current_lambda).unwrap();
         Duration::from_secs((generator.sample(rng)) as u64)
     }
+
+    /// Renvoie le temps qui s'écoulera avant l'arrivée du prochain véhicule,
+    /// tous sens de circulation confondus, et le sens dans lequel il circule.
+    /// Les arrivées dans chaque sens suivent leur propre profil horaire
+    /// (voir `TollBuilder::arrival_rates()`).
+    ///
+    /// ```no_run
+    /// # use std::thread;
+    /// # use rand::Rng;
+    /// # use rsy40::toll::Toll;
+    /// # use rsy40::vehicle::Vehicle;
+    /// # let mut toll = Toll::builder().build()?;
+    /// # let mut rng = rand::thread_rng();
+    /// loop {
+    ///     let (waiting_time, direction) = toll.next_arrival(&mut rng);
+    ///     let mut v = rng.gen::<Vehicle>();
+    ///     v.direction = direction;
+    ///     toll.add_vehicle(v)?;
+    ///     thread::sleep(waiting_time);
+    /// }
+    /// # Ok::<(), rsy40::error::Error>(())
+    /// ```
+    #[allow(unused)]
+    pub fn next_arrival<R: Rng + ?Sized>(&self, rng: &mut R) -> (Duration, Direction) {
+        let hour = self.clock.clock.hour as usize;
+        [Direction::Outbound, Direction::Inbound].into_iter()
+            .filter(|&direction| self.arrival_rates[direction as usize][hour] > 0.0)
+            .map(|direction| {
+                let lambda = self.arrival_rates[direction as usize][hour];
+                (rand_distr::Exp::new(lambda).unwrap().sample(rng), direction)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(secs, direction)| (Duration::from_secs(secs as u64), direction))
+            .unwrap_or((Duration::from_secs(3600), Direction::Outbound))
+    }
+
+    /// Termine la simulation : enregistre l'heure de fin du run et ses indicateurs
+    /// dans la table `run`, puis attend que les enregistrements soient écrits.
+    /// Les opérations suivantes ne sont plus enregistrées.
+    /// Sans effet si aucun enregistrement n'a lieu.
+    pub fn finish(&self) {
+        if let Some(ref logger) = self.logger {
+            logger.finish_run();
+        }
+    }
+
+    /// Débit et retard de l'enregistrement en base de données,
+    /// None si le péage n'enregistre rien
+    pub fn logger_report(&self) -> Option<LoggerReport> {
+        self.logger.as_ref().map(TollDatabase::report)
+    }
+
+    /// Renvoie vrai si le péage dessert les deux sens de circulation
+    pub fn bidirectional(&self) -> bool {
+        self.gates.iter().any(|gate| gate.direction() == Direction::Inbound)
+            || self.arrival_rates[Direction::Inbound as usize].iter().any(|&rate| rate > 0.0)
+    }
+}
+
+/// Choisit la porte dans laquelle le véhicule va faire la queue,
+/// parmi les portes ouvertes qui desservent son sens de circulation
+/// et dont le type de voie convient au véhicule.
+///
+/// Les portes dont la panne en cours empêche le passage du véhicule
+/// ne sont choisies qu'en dernier recours, puis les portes fermées
+/// ou en train de changer de sens.
+/// Renvoie None si aucune porte ne convient.
+pub fn choose_gate<'a>(gates: &'a [Gate], vehicle: &Vehicle) -> Option<&'a Gate> {
+    choose_gate_by(gates, vehicle, Gate::nb_cars)
+}
+
+/// Choisit la porte comme `choose_gate()`, la longueur de la file de chaque
+/// porte étant donnée par `queue_len` (par exemple telle que la perçoit le conducteur)
+pub fn choose_gate_by<'a, F>(gates: &'a [Gate], vehicle: &Vehicle, queue_len: F) -> Option<&'a Gate>
+where
+    F: Fn(&Gate) -> usize,
+{
+    let mut candidates: Vec<&Gate> = gates.iter()
+        .filter(|gate| gate.accepts(vehicle))
+        .collect();
+    let fits = |gate: &&Gate| gate.direction() == vehicle.direction && gate.lane_type.accepts(vehicle);
+    if candidates.is_empty() {
+        candidates = gates.iter()
+            .filter(|gate| gate.is_open() && !gate.is_switching())
+            .filter(fits)
+            .collect();
+    }
+    if candidates.is_empty() {
+        candidates = gates.iter().filter(fits).collect();
+    }
+    let less_crowded_gate = candidates.iter()
+        .filter(|&gate| queue_len(gate) > 0)
+        .min_by_key(|gate| queue_len(gate));
+    match less_crowded_gate {
+        None => candidates.first().copied(),
+        Some(gate) => match queue_len(gate) < 5 {
+            true => Some(gate),
+            false => candidates.iter()
+                .find(|gate| queue_len(gate) == 0)
+                .or(Some(gate))
+                .copied()
+        }
+    }
 }
 
 impl Display for Toll {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut buffer = format!("\x1b[{}A\x1b[J", self.gates.len() + 2);
+        let bidirectional = self.bidirectional();
+        let nb_lines = self.gates.len() + 2 + self.upstream.is_some() as usize
+            + self.approach.is_some() as usize + self.merge.is_some() as usize
+            + bidirectional as usize + self.logger.is_some() as usize;
+        let mut buffer = format!("\x1b[{}A\x1b[J", nb_lines);
         buffer.push_str(self.clock.clock.to_string().as_str());
+        // véhicules en attente dans chaque sens
+        let mut waiting = [0; 2];
         for (i, gate) in self.gates.iter().enumerate() {
             buffer.push_str(i.to_string().as_str());
-            buffer.push_str(" | ");
-            if gate.empty() {
+            if bidirectional {
+                buffer.push(match (gate.is_switching(), gate.direction()) {
+                    (true, _) => '~',
+                    (false, Direction::Outbound) => '>',
+                    (false, Direction::Inbound) => '<',
+                });
+            }
+            buffer.push_str(match gate.lane_type {
+                LaneType::Mixed => "  | ",
+                LaneType::Electronic => " t| ",
+                LaneType::Carpool => " c| ",
+            });
+            // la file n'est verrouillée qu'une fois par porte
+            let queue = gate.queue.lock().unwrap();
+            if gate.outage().is_some() {
+                buffer.push('!');
+            } else if !gate.is_open() {
+                buffer.push('#');
+            } else if queue.is_empty() {
                 buffer.push('X');
             }
-            for v in gate.queue.lock().unwrap().iter() {
+            for v in queue.iter() {
                 buffer.push_str(v.vehicle.type_num().to_string().as_str());
+                waiting[v.vehicle.direction as usize] += 1;
             }
+            drop(queue);
             buffer.push('\n');
         }
+        if bidirectional {
+            buffer.push_str(format!("sens 1 : {} | sens 2 : {}\n", waiting[0], waiting[1]).as_str());
+        }
+        if let Some(ref upstream) = self.upstream {
+            buffer.push_str(format!("amont : {}\n", upstream.len()).as_str());
+        }
+        if let Some(ref approach) = self.approach {
+            buffer.push_str(format!("approche : {}\n", approach.len()).as_str());
+        }
+        if let Some(ref merge) = self.merge {
+            buffer.push_str(format!("aval : {}\n", merge.len()).as_str());
+        }
+        if let Some(report) = self.logger_report() {
+            buffer.push_str(format!("{}\n", report).as_str());
+        }
         f.write_str(buffer.as_str())
     }
 }
@@ -111,32 +431,515 @@ pub struct TollBuilder {
     /// `.build()`, l'objet `Toll` ainsi construit n'effectuera aucun
     /// enregistrement en base de données
     logger_name: Option<String>,
+    /// Politique d'ouverture des portes (hors voie de covoiturage)
+    staffing: StaffingPolicy,
+    /// Modèle de panne appliqué aux portes sans modèle spécifique
+    default_failure_model: Option<FailureModel>,
+    /// Modèles de panne spécifiques à certaines portes, par numéro de porte
+    failure_models: HashMap<usize, FailureModel>,
+    /// Capacité de stockage du péage, None si elle n'est pas limitée
+    storage: Option<StorageConfig>,
+    /// Lois du temps de paiement appliquées aux portes sans loi spécifique,
+    /// par moyen de paiement
+    default_payment_times: [Option<ServiceDistribution>; 2],
+    /// Lois du temps de paiement spécifiques à certaines portes,
+    /// par numéro de porte et moyen de paiement
+    payment_times: HashMap<(usize, PaymentMean), ServiceDistribution>,
+    /// Traitement des véhicules prioritaires
+    priority_policy: PriorityPolicy,
+    /// Configuration du flux libre, si le péage fonctionne dans ce mode
+    free_flow: Option<FreeFlowConfig>,
+    /// Délivrance des tickets, si le péage est une gare d'entrée
+    ticket: Option<TicketConfig>,
+    /// Configuration du portique fictif servant à comparer le péage à barrières
+    /// au flux libre
+    shadow_free_flow: Option<FreeFlowConfig>,
+    /// Grille tarifaire du péage à barrières
+    tariff: Tariff,
+    /// Types de voie spécifiques à certaines portes, par numéro de porte
+    lane_types: HashMap<usize, LaneType>,
+    /// Paramètres de la fraude et des contrôles
+    enforcement: Option<EnforcementConfig>,
+    /// Capteurs de classification des portes
+    classification: Option<ClassificationConfig>,
+    /// Base de données partagée avec d'autres péages d'un réseau.
+    /// Si elle est renseignée, `logger_name` est ignoré
+    database: Option<TollDatabase>,
+    /// Sender vers le réseau autoroutier auquel appartient le péage
+    onward: Option<Sender<Vehicle>>,
+    /// Zone de convergence en aval du péage, None s'il n'y en a pas
+    merge: Option<MergeConfig>,
+    /// Zone d'approche du péage, None si les véhicules entrent immédiatement
+    /// dans la meilleure file
+    approach: Option<ApproachConfig>,
+    /// Sens de circulation spécifiques à certaines portes, par numéro de porte
+    directions: HashMap<usize, Direction>,
+    /// Numéros des portes réversibles
+    reversible: Vec<usize>,
+    /// Sens des voies réversibles pour chaque heure de la journée
+    reversal_schedule: Option<[Direction; 24]>,
+    /// Intervalle (en temps simulé) entre deux relevés de l'état des portes,
+    /// None si aucun relevé n'est fait
+    snapshot_interval: Option<Duration>,
+    /// Taux d'arrivée des véhicules pour chaque sens de circulation,
+    /// None pour utiliser le profil par défaut (sens 1) ou aucune arrivée (sens 2)
+    arrival_rates: [Option<[f64; 24]>; 2],
+    /// Manière d'ouvrir la base de données si elle existe déjà,
+    /// None si elle n'a pas été choisie
+    open_mode: Option<OpenMode>,
+    /// Destinations des enregistrements, en plus de la base de données
+    sinks: Vec<Box<dyn OutputSink>>,
+    /// Regroupement des enregistrements en transactions
+    logger_config: LoggerConfig,
+    /// Manière de faire fonctionner les portes
+    execution: ExecutionModel,
+    /// Ordonnanceur partagé avec d'autres péages, None pour en créer un
+    scheduler: Option<Scheduler>,
+    /// Vrai si le journal complet des événements est enregistré
+    event_log: bool,
+    /// Graine du générateur aléatoire, None pour en tirer une au hasard
+    seed: Option<u64>,
+}
+
+/// Vue triée par clé d'une table de hachage, pour écrire la configuration
+/// toujours dans le même ordre
+pub(crate) fn sorted<K: Ord, V>(map: &HashMap<K, V>) -> BTreeMap<&K, &V> {
+    map.iter().collect()
+}
+
+impl std::fmt::Debug for TollBuilder {
+    /// Configuration du péage, enregistrée avec chaque run.
+    /// Les destinations des enregistrements et les liens avec un réseau
+    /// n'en font pas partie.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TollBuilder")
+            .field("nb_gates", &self.gates.len())
+            .field("start", &self.clock.clock)
+            .field("acceleration_factor", &self.clock.acceleration_factor)
+            .field("staffing", &self.staffing)
+            .field("default_failure_model", &self.default_failure_model)
+            .field("failure_models", &sorted(&self.failure_models))
+            .field("storage", &self.storage)
+            .field("default_payment_times", &self.default_payment_times)
+            .field("payment_times", &sorted(&self.payment_times))
+            .field("priority_policy", &self.priority_policy)
+            .field("free_flow", &self.free_flow)
+            .field("ticket", &self.ticket)
+            .field("shadow_free_flow", &self.shadow_free_flow)
+            .field("tariff", &self.tariff)
+            .field("lane_types", &sorted(&self.lane_types))
+            .field("enforcement", &self.enforcement)
+            .field("classification", &self.classification)
+            .field("merge", &self.merge)
+            .field("approach", &self.approach)
+            .field("directions", &sorted(&self.directions))
+            .field("reversible", &self.reversible)
+            .field("reversal_schedule", &self.reversal_schedule)
+            .field("snapshot_interval", &self.snapshot_interval)
+            .field("arrival_rates", &self.arrival_rates)
+            .field("execution", &self.execution)
+            .field("event_log", &self.event_log)
+            .finish_non_exhaustive()
+    }
 }
 
 impl TollBuilder {
+    /// Configuration du péage enregistrée avec chaque run : version du simulateur
+    /// et paramètres du péage, toujours écrits dans le même ordre afin que deux
+    /// runs de même configuration aient la même chaîne
+    pub fn config_string(&self) -> String {
+        format!("rsy40 {} {:?}", VERSION, self)
+    }
+
     /// Construit et retourne l'objet Toll correspondant à ce constructeur.
     /// Lance également les threads en arrière-plan liés à ce péage
-    /// (threads des portes et thread d'enregistrement en db).
+    /// (ordonnanceur ou threads des portes et thread d'enregistrement en db).
     ///
     /// Si la méthode `.set_logger()` n'a pas été appelée,
     /// le thread d'enregistrement n'est pas lancé.
-    pub fn build(mut self) -> Toll {
-        let logger = match self.logger_name {
-            None => None,
-            Some(name) => {
-                let db = TollDatabase::new(name.as_str())
-                    .unwrap_or_else(|_| panic!("Failed to open database {}", name));
-                self.gates.iter_mut()
-                    .for_each(|gate| gate.log_sender = Some(db.sender.clone()));
-                Some(db)
+    ///
+    /// Renvoie une erreur, sans lancer aucun thread, si la configuration
+    /// est invalide (voir `.validate()`) ou si la base de données
+    /// ne peut pas être ouverte.
+    pub fn build(mut self) -> Result<Toll, Error> {
+        let problems = self.validate();
+        if !problems.is_empty() {
+            return Err(Error::Config(problems));
+        }
+        let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let config = self.config_string();
+        let mut sinks = std::mem::take(&mut self.sinks);
+        if let Some(ref name) = self.logger_name {
+            let mode = chosen_mode(name.as_str(), self.open_mode)?;
+            sinks.insert(0, Box::new(SqliteSink::open(name.as_str(), mode)?));
+        }
+        let upstream = self.storage.map(|config| Arc::new(Upstream::new(config)));
+        let scheduler = match self.execution {
+            ExecutionModel::Scheduler => Some(self.scheduler.take().unwrap_or_default()),
+            ExecutionModel::ThreadPerGate => None,
+        };
+        let merge = self.merge.map(|config| Arc::new(
+            Merge::new(config, scheduler.as_ref().map(Scheduler::merge_waker))
+        ));
+        let last_gate = self.gates.len().saturating_sub(1);
+        for gate in self.gates.iter_mut() {
+            // par défaut, la dernière porte est la voie de covoiturage
+            gate.lane_type = match self.lane_types.get(&gate.id) {
+                Some(&lane_type) => lane_type,
+                None if gate.id == last_gate => LaneType::Carpool,
+                None => LaneType::Mixed,
+            };
+            let direction = match (self.reversible.contains(&gate.id), self.reversal_schedule) {
+                (true, Some(schedule)) => schedule[self.clock.clock.hour as usize],
+                _ => self.directions.get(&gate.id).copied().unwrap_or_default(),
+            };
+            gate.set_direction(direction);
+            gate.tariff = self.tariff.clone();
+            gate.enforcement = self.enforcement.clone();
+            gate.classification = self.classification.clone();
+            gate.ticket = self.ticket.clone();
+            gate.upstream = upstream.clone();
+            gate.merge = merge.clone();
+            gate.priority_first = matches!(self.priority_policy, PriorityPolicy::FrontOfQueue);
+            // flux 0 : péage, flux 1 : pannes, flux suivants : portes
+            gate.seed = derive_seed(seed, gate.id as u64 + 2);
+            for mean in [PaymentMean::Cash, PaymentMean::Toll] {
+                gate.payment_times[mean as usize] = self.payment_times
+                    .get(&(gate.id, mean))
+                    .or(self.default_payment_times[mean as usize].as_ref())
+                    .cloned();
             }
+        }
+        // une base partagée avec un réseau enregistre le run du réseau
+        let shared = self.database.is_some();
+        let logger = match (self.database, sinks.len()) {
+            (Some(db), _) => Some(db),
+            (None, 0) => None,
+            (None, 1) => Some(TollDatabase::with_sink(sinks.pop().unwrap(), self.logger_config)),
+            (None, _) => Some(TollDatabase::with_sink(
+                Box::new(MultiSink::new(sinks)), self.logger_config,
+            )),
+        };
+        if let (Some(db), false) = (&logger, shared) {
+            db.start_run(seed, config);
+        }
+        let events = match (self.event_log, &logger) {
+            (true, Some(db)) => Some(EventLog::new(db.sender.clone(), self.clock.clone())),
+            _ => None,
         };
-        self.gates.iter_mut().for_each(|gate| gate.launch_thread());
-        Toll {
+        let barrier_revenue = Arc::new(Mutex::new(RevenueStats::default()));
+        for gate in self.gates.iter_mut() {
+            gate.revenue = barrier_revenue.clone();
+            gate.log_sender = logger.as_ref().map(|db| db.sender.clone());
+            gate.events = events.clone();
+            gate.onward = self.onward.clone();
+        }
+        if let Some(ref merge) = merge {
+            launch_merge_thread(
+                merge.clone(),
+                self.clock.clone(),
+                logger.as_ref().map(|db| db.sender.clone()),
+                self.onward.clone(),
+                events.clone(),
+            );
+        }
+        match scheduler {
+            Some(ref scheduler) => self.gates.iter_mut().for_each(|gate| scheduler.register(gate)),
+            None => self.gates.iter().for_each(Gate::launch_thread),
+        }
+        // les voies de covoiturage restent toujours ouvertes
+        let staffed: Vec<Gate> = self.gates.iter()
+            .filter(|gate| gate.lane_type != LaneType::Carpool)
+            .cloned()
+            .collect();
+        let nb_open = self.staffing.initial_open(self.clock.clock.hour, staffed.len());
+        staffed.iter()
+            .enumerate()
+            .for_each(|(i, gate)| gate.set_open(i < nb_open));
+        launch_staffing_thread(
+            self.staffing,
+            staffed,
+            self.clock.clone(),
+            logger.as_ref().map(|db| db.sender.clone()),
+        );
+        let failure_models = self.gates.iter()
+            .map(|gate| self.failure_models.get(&gate.id)
+                .or(self.default_failure_model.as_ref())
+                .cloned())
+            .collect();
+        launch_failure_thread(
+            self.gates.clone(),
+            failure_models,
+            self.clock.clone(),
+            logger.as_ref().map(|db| db.sender.clone()),
+            derive_seed(seed, 1),
+        );
+        if let Some(schedule) = self.reversal_schedule {
+            launch_reversal_thread(
+                self.gates.iter()
+                    .filter(|gate| self.reversible.contains(&gate.id))
+                    .cloned()
+                    .collect(),
+                schedule,
+                self.clock.clone(),
+                logger.as_ref().map(|db| db.sender.clone()),
+            );
+        }
+        let approach = self.approach.map(|config| Approach::new(
+            config,
+            self.gates.clone(),
+            upstream.clone(),
+            self.clock.clone(),
+        ));
+        if let Some(ref upstream) = upstream {
+            launch_upstream_thread(
+                upstream.clone(),
+                self.gates.clone(),
+                self.clock.clone(),
+                logger.as_ref().map(|db| db.sender.clone()),
+            );
+        }
+        if let Some(interval) = self.snapshot_interval {
+            launch_snapshot_thread(
+                self.gates.clone(),
+                upstream.clone(),
+                interval,
+                self.clock.clone(),
+                logger.as_ref().map(|db| db.sender.clone()),
+            );
+        }
+        Ok(Toll {
             gates: self.gates,
-            clock: self.clock,
             logger,
+            upstream,
+            approach,
+            merge,
+            priority_policy: self.priority_policy,
+            mode: match (self.free_flow, self.ticket.is_some()) {
+                (Some(config), _) => PlazaMode::FreeFlow(FreeFlowPlaza::new(config)),
+                (None, true) => PlazaMode::Entry,
+                (None, false) => PlazaMode::Barrier,
+            },
+            shadow_free_flow: self.shadow_free_flow.map(FreeFlowPlaza::new),
+            barrier_arrivals: 0,
+            barrier_revenue,
+            tariff: self.tariff,
+            start: self.clock.clock.clone(),
+            enforcement: self.enforcement,
+            onward: self.onward,
+            arrival_rates: [
+                self.arrival_rates[0].unwrap_or(DEFAULT_ARRIVAL_RATES),
+                self.arrival_rates[1].unwrap_or([0.0; 24]),
+            ],
+            clock: self.clock,
+            events,
+            seed,
+            rng: StdRng::seed_from_u64(derive_seed(seed, 0)),
+        })
+    }
+
+    /// Vérifie la configuration du péage et renvoie tous les problèmes relevés :
+    /// portes inexistantes, sens de circulation ou heures sans voie mixte
+    /// alors que des véhicules arrivent, plannings et politique d'ouverture
+    /// incohérents, taux d'arrivée, lois du temps de paiement et modèles de panne
+    /// invalides, modes de fonctionnement incompatibles, relevés ou journal
+    /// des événements demandés sans aucun enregistrement...
+    /// La liste est vide si la configuration est valide.
+    ///
+    /// Appelée par `.build()` avant de lancer le moindre thread.
+    ///
+    /// ```
+    /// # use rsy40::toll::Toll;
+    /// let builder = Toll::builder().nb_gates(1);
+    /// for problem in builder.validate() {
+    ///     eprintln!("{}", problem);
+    /// }
+    /// ```
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut problems = Vec::new();
+        let nb_gates = self.gates.len();
+        if self.clock.acceleration_factor == 0 {
+            problems.push(ConfigError::ZeroAcceleration);
+        }
+        let start = &self.clock.clock;
+        if start.hour >= 24 || start.minute >= 60 || start.second >= 60 {
+            problems.push(ConfigError::InvalidStartTime);
+        }
+        if self.free_flow.is_some() && self.ticket.is_some() {
+            problems.push(ConfigError::ConflictingModes);
+        }
+        // un péage en flux libre n'utilise pas ses portes
+        if nb_gates == 0 && self.free_flow.is_none() {
+            problems.push(ConfigError::NoGates);
+        }
+        let unknown = |setting, gate| ConfigError::UnknownGate { setting, gate, nb_gates };
+        let mut gates: Vec<(&'static str, usize)> = Vec::new();
+        gates.extend(self.lane_types.keys().map(|&gate| ("lane_type", gate)));
+        gates.extend(self.directions.keys().map(|&gate| ("lane_direction", gate)));
+        gates.extend(self.reversible.iter().map(|&gate| ("reversible_lanes", gate)));
+        gates.extend(self.failure_models.keys().map(|&gate| ("gate_failure_model", gate)));
+        gates.extend(self.payment_times.keys().map(|&(gate, _)| ("gate_payment_time", gate)));
+        gates.sort();
+        gates.dedup();
+        problems.extend(gates.into_iter()
+            .filter(|&(_, gate)| gate >= nb_gates)
+            .map(|(setting, gate)| unknown(setting, gate)));
+        if self.reversal_schedule.is_some() && self.reversible.is_empty() {
+            problems.push(ConfigError::NoReversibleLane);
+        }
+
+        // types de voie et sens effectifs, comme dans `.build()`
+        let lane_type = |gate: usize| match self.lane_types.get(&gate) {
+            Some(&lane_type) => lane_type,
+            None if gate + 1 == nb_gates => LaneType::Carpool,
+            None => LaneType::Mixed,
+        };
+        let direction = |gate: usize, hour: usize| {
+            match (self.reversible.contains(&gate), self.reversal_schedule) {
+                (true, Some(schedule)) => schedule[hour],
+                _ => self.directions.get(&gate).copied().unwrap_or_default(),
+            }
+        };
+        let rates = [
+            self.arrival_rates[0].unwrap_or(DEFAULT_ARRIVAL_RATES),
+            self.arrival_rates[1].unwrap_or([0.0; 24]),
+        ];
+        for (direction_rates, dir) in rates.iter().zip([Direction::Outbound, Direction::Inbound]) {
+            problems.extend(direction_rates.iter()
+                .enumerate()
+                .filter(|(_, rate)| !rate.is_finite() || **rate < 0.0)
+                .map(|(hour, &rate)| ConfigError::InvalidRate { direction: dir, hour, rate }));
+            // les véhicules payant en espèces ne peuvent passer que par une voie mixte
+            if self.free_flow.is_some() || nb_gates == 0 {
+                continue;
+            }
+            let hours: Vec<usize> = (0..24)
+                .filter(|&hour| direction_rates[hour] > 0.0)
+                .filter(|&hour| !(0..nb_gates).any(|gate| {
+                    lane_type(gate) == LaneType::Mixed && direction(gate, hour) == dir
+                }))
+                .collect();
+            if !hours.is_empty() {
+                problems.push(ConfigError::NoMixedLane { direction: dir, hours });
+            }
+        }
+
+        // les voies de covoiturage ne sont pas concernées par la politique d'ouverture
+        let nb_staffed = (0..nb_gates).filter(|&gate| lane_type(gate) != LaneType::Carpool).count();
+        match self.staffing {
+            StaffingPolicy::AllOpen => {}
+            StaffingPolicy::Schedule(schedule) => problems.extend(schedule.iter()
+                .enumerate()
+                .filter(|(_, &open)| open > nb_staffed)
+                .map(|(hour, &open)| ConfigError::ScheduleTooLarge { hour, open, nb_staffed })),
+            StaffingPolicy::Reactive(ref config) => {
+                if config.period.is_zero() {
+                    problems.push(ConfigError::Staffing("the observation period must not be 0"));
+                }
+                if config.close_queue_threshold >= config.open_queue_threshold
+                    || config.close_queue_threshold.is_nan() || config.open_queue_threshold.is_nan() {
+                    problems.push(ConfigError::Staffing(
+                        "close_queue_threshold must be lower than open_queue_threshold",
+                    ));
+                }
+                if config.min_open == 0 {
+                    problems.push(ConfigError::Staffing("min_open must be at least 1"));
+                }
+                if config.min_open > config.max_open {
+                    problems.push(ConfigError::Staffing("min_open must not exceed max_open"));
+                }
+                if config.max_open == 0 {
+                    problems.push(ConfigError::Staffing("max_open must be at least 1"));
+                }
+                if config.min_open > nb_staffed {
+                    problems.push(ConfigError::Staffing(
+                        "min_open exceeds the number of gates that can be staffed",
+                    ));
+                }
+            }
+        }
+
+        for mean in [PaymentMean::Cash, PaymentMean::Toll] {
+            if let Some(ref dist) = self.default_payment_times[mean as usize] {
+                if !dist.is_valid() {
+                    problems.push(ConfigError::InvalidDistribution { gate: None, mean });
+                }
+            }
+        }
+        let mut payment_times: Vec<_> = self.payment_times.iter()
+            .filter(|(_, dist)| !dist.is_valid())
+            .map(|(&(gate, mean), _)| (gate, mean as usize, mean))
+            .collect();
+        payment_times.sort_by_key(|&(gate, index, _)| (gate, index));
+        problems.extend(payment_times.into_iter()
+            .map(|(gate, _, mean)| ConfigError::InvalidDistribution { gate: Some(gate), mean }));
+        if let Some(ref model) = self.default_failure_model {
+            if !model.is_valid() {
+                problems.push(ConfigError::InvalidFailureModel(None));
+            }
         }
+        let mut failure_models: Vec<usize> = self.failure_models.iter()
+            .filter(|(_, model)| !model.is_valid())
+            .map(|(&gate, _)| gate)
+            .collect();
+        failure_models.sort();
+        problems.extend(failure_models.into_iter().map(|gate| ConfigError::InvalidFailureModel(Some(gate))));
+
+        if self.enforcement.as_ref().is_some_and(|config| !config.is_valid()) {
+            problems.push(ConfigError::InvalidEnforcement);
+        }
+        if [&self.free_flow, &self.shadow_free_flow].iter()
+            .any(|config| config.as_ref().is_some_and(|config| !config.is_valid())) {
+            problems.push(ConfigError::InvalidFreeFlow);
+        }
+        if self.ticket.as_ref().is_some_and(|config| !config.issue_time.is_valid()) {
+            problems.push(ConfigError::InvalidIssueTime);
+        }
+
+        if let Some(ref storage) = self.storage {
+            if storage.lane_capacity <= 0.0 || storage.lane_capacity.is_nan() {
+                problems.push(ConfigError::NotPositive("the storage lane capacity"));
+            }
+        }
+        if let Some(ref approach) = self.approach {
+            if approach.vehicle_length <= 0.0 || approach.vehicle_length.is_nan() {
+                problems.push(ConfigError::NotPositive("the approach vehicle length"));
+            }
+            if approach.speed <= 0.0 || !approach.speed.is_finite() {
+                problems.push(ConfigError::NotPositive("the approach speed"));
+            }
+            if approach.visibility <= 0.0 || approach.visibility.is_nan() {
+                problems.push(ConfigError::NotPositive("the approach visibility"));
+            }
+        }
+        if let Some(ref merge) = self.merge {
+            if merge.nb_lanes == 0 {
+                problems.push(ConfigError::NotPositive("the number of merge lanes"));
+            }
+            if merge.capacity == 0 {
+                problems.push(ConfigError::NotPositive("the merge capacity"));
+            }
+            if merge.headway.is_zero() {
+                problems.push(ConfigError::NotPositive("the merge headway"));
+            }
+        }
+
+        let logged = self.database.is_some() || self.logger_name.is_some() || !self.sinks.is_empty();
+        if self.snapshot_interval.is_some_and(|interval| interval.is_zero()) {
+            problems.push(ConfigError::NotPositive("the snapshot interval"));
+        }
+        if self.snapshot_interval.is_some() && !logged {
+            problems.push(ConfigError::Logging("snapshots are requested but nothing is logged"));
+        }
+        if self.event_log && !logged {
+            problems.push(ConfigError::Logging("the event log is requested but nothing is logged"));
+        }
+        if self.database.is_some() && (self.logger_name.is_some() || !self.sinks.is_empty()) {
+            problems.push(ConfigError::Logging(
+                "the shared logger replaces the database and sinks given to the plaza",
+            ));
+        }
+        problems
     }
 
     /// Nombre de portes du péage.
@@ -146,7 +949,7 @@ impl TollBuilder {
         if !self.gates.is_empty() {
             self.gates.clear();
         }
-        self.gates.extend((0..nb_gates).map(|_| Gate::new()));
+        self.gates.extend((0..nb_gates).map(Gate::new));
         self
     }
 
@@ -165,21 +968,430 @@ impl TollBuilder {
         self
     }
 
+    /// Politique d'ouverture et de fermeture des portes.
+    /// Si cette méthode n'est pas appelée, toutes les portes restent ouvertes.
+    ///
+    /// ```
+    /// # use rsy40::toll::Toll;
+    /// # use rsy40::staffing::{ReactiveConfig, StaffingPolicy};
+    /// let toll = Toll::builder()
+    ///     .nb_gates(8)
+    ///     .staffing(StaffingPolicy::Reactive(ReactiveConfig {
+    ///         max_open: 5,
+    ///         ..Default::default()
+    ///     }))
+    ///     .build()?;
+    /// # Ok::<(), rsy40::error::Error>(())
+    /// ```
+    #[allow(unused)]
+    pub fn staffing(mut self, policy: StaffingPolicy) -> Self {
+        self.staffing = policy;
+        self
+    }
+
+    /// Modèle de panne de toutes les portes du péage,
+    /// sauf celles ayant reçu un modèle spécifique avec `.gate_failure_model()`.
+    /// Si cette méthode n'est pas appelée, les portes ne tombent jamais en panne.
+    #[allow(unused)]
+    pub fn failure_model(mut self, model: FailureModel) -> Self {
+        self.default_failure_model = Some(model);
+        self
+    }
+
+    /// Modèle de panne spécifique à la porte dont le numéro est donné
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use rsy40::toll::Toll;
+    /// # use rsy40::failure::FailureModel;
+    /// let toll = Toll::builder()
+    ///     .nb_gates(6)
+    ///     .failure_model(FailureModel::default())
+    ///     .gate_failure_model(5, FailureModel {
+    ///         mean_time_to_failure: Duration::from_secs(2 * 3600),
+    ///         ..Default::default()
+    ///     })
+    ///     .build()?;
+    /// # Ok::<(), rsy40::error::Error>(())
+    /// ```
+    #[allow(unused)]
+    pub fn gate_failure_model(mut self, gate: usize, model: FailureModel) -> Self {
+        self.failure_models.insert(gate, model);
+        self
+    }
+
+    /// Limite la capacité de stockage des voies et de la zone d'approche du péage.
+    /// Les véhicules qui trouvent le péage plein attendent en amont.
+    /// Si cette méthode n'est pas appelée, les files d'attente sont illimitées.
+    #[allow(unused)]
+    pub fn storage(mut self, config: StorageConfig) -> Self {
+        self.storage = Some(config);
+        self
+    }
+
+    /// Fait arriver les véhicules par une zone d'approche : chacun choisit sa porte
+    /// à un point de décision, d'après les seules files qu'il voit,
+    /// puis met un certain temps à atteindre la fin de la file choisie.
+    /// Si cette méthode n'est pas appelée, les véhicules entrent immédiatement
+    /// dans la meilleure file.
+    #[allow(unused)]
+    pub fn approach(mut self, config: ApproachConfig) -> Self {
+        self.approach = Some(config);
+        self
+    }
+
+    /// Limite le débit de sortie du péage par une zone de convergence
+    /// où les voies du péage se rabattent sur celles de l'autoroute.
+    /// Lorsque la zone est pleine, les véhicules restent bloqués à leur porte.
+    /// Si cette méthode n'est pas appelée, les véhicules quittent le péage
+    /// dès la barrière franchie.
+    ///
+    /// ```
+    /// # use rsy40::toll::Toll;
+    /// # use rsy40::merge::MergeConfig;
+    /// let toll = Toll::builder()
+    ///     .nb_gates(12)
+    ///     .merge(MergeConfig {
+    ///         nb_lanes: 2,
+    ///         ..Default::default()
+    ///     })
+    ///     .build()?;
+    /// # Ok::<(), rsy40::error::Error>(())
+    /// ```
+    #[allow(unused)]
+    pub fn merge(mut self, config: MergeConfig) -> Self {
+        self.merge = Some(config);
+        self
+    }
+
+    /// Loi du temps de paiement de toutes les portes pour le moyen de paiement donné,
+    /// sauf pour les portes ayant reçu une loi spécifique avec `.gate_payment_time()`.
+    /// Les temps d'approche et de dégagement restent ceux de la classe du véhicule.
+    ///
+    /// Si cette méthode n'est pas appelée, le temps de paiement dépend
+    /// uniquement de la classe du véhicule et de son moyen de paiement.
+    #[allow(unused)]
+    pub fn payment_time(mut self, mean: PaymentMean, dist: ServiceDistribution) -> Self {
+        self.default_payment_times[mean as usize] = Some(dist);
+        self
+    }
+
+    /// Loi du temps de paiement spécifique à une porte et à un moyen de paiement.
+    /// Permet par exemple de rendre une voie automatique plus rapide
+    /// qu'une voie tenue par un agent.
+    ///
+    /// ```no_run
+    /// # use rsy40::toll::Toll;
+    /// # use rsy40::distribution::ServiceDistribution;
+    /// # use rsy40::vehicle::PaymentMean;
+    /// let toll = Toll::builder()
+    ///     .nb_gates(6)
+    ///     .gate_payment_time(0, PaymentMean::Cash, ServiceDistribution::LogNormal {
+    ///         mu: 3.4,
+    ///         sigma: 0.3,
+    ///     })
+    ///     .gate_payment_time(1, PaymentMean::Cash,
+    ///         ServiceDistribution::from_csv("cabine_1.csv", 0).unwrap())
+    ///     .build()?;
+    /// # Ok::<(), rsy40::error::Error>(())
+    /// ```
+    #[allow(unused)]
+    pub fn gate_payment_time(
+        mut self, gate: usize, mean: PaymentMean, dist: ServiceDistribution,
+    ) -> Self {
+        self.payment_times.insert((gate, mean), dist);
+        self
+    }
+
+    /// Traitement des véhicules prioritaires (urgences, forces de l'ordre,
+    /// patrouilles). Si cette méthode n'est pas appelée, ils passent
+    /// en tête de file.
+    #[allow(unused)]
+    pub fn priority_policy(mut self, policy: PriorityPolicy) -> Self {
+        self.priority_policy = policy;
+        self
+    }
+
+    /// Fait fonctionner le péage en flux libre : les véhicules passent sous
+    /// un portique sans s'arrêter et aucune file d'attente ne se forme.
+    #[allow(unused)]
+    pub fn free_flow(mut self, config: FreeFlowConfig) -> Self {
+        self.free_flow = Some(config);
+        self
+    }
+
+    /// Fait fonctionner le péage comme une gare d'entrée d'autoroute fermée :
+    /// les véhicules prennent un ticket à une porte sans rien payer,
+    /// les véhicules équipés du télépéage passent sans s'arrêter.
+    /// L'heure de délivrance du ticket est conservée par le véhicule.
+    #[allow(unused)]
+    pub fn entry(mut self, config: TicketConfig) -> Self {
+        self.ticket = Some(config);
+        self
+    }
+
+    /// Fait passer le trafic du péage à barrières sous un portique en flux libre
+    /// fictif, afin de comparer les deux modes avec `Toll::comparison()`.
+    /// Le tarif utilisé par le péage à barrières est celui du portique.
+    ///
+    /// ```
+    /// # use rsy40::toll::Toll;
+    /// # use rsy40::free_flow::FreeFlowConfig;
+    /// let mut toll = Toll::builder()
+    ///     .compare_free_flow(FreeFlowConfig::default())
+    ///     .build()?;
+    /// // ...
+    /// println!("{}", toll.comparison().unwrap());
+    /// # Ok::<(), rsy40::error::Error>(())
+    /// ```
+    #[allow(unused)]
+    pub fn compare_free_flow(mut self, config: FreeFlowConfig) -> Self {
+        self.tariff = config.tariff.clone();
+        self.shadow_free_flow = Some(config);
+        self
+    }
+
+    /// Type de voie de la porte dont le numéro est donné.
+    /// Si cette méthode n'est pas appelée pour une porte, la dernière porte
+    /// est une voie de covoiturage et les autres acceptent tous les véhicules.
+    ///
+    /// ```
+    /// # use rsy40::toll::Toll;
+    /// # use rsy40::gate::LaneType;
+    /// let toll = Toll::builder()
+    ///     .nb_gates(8)
+    ///     .lane_type(0, LaneType::Electronic)
+    ///     .lane_type(1, LaneType::Electronic)
+    ///     .build()?;
+    /// # Ok::<(), rsy40::error::Error>(())
+    /// ```
+    #[allow(unused)]
+    pub fn lane_type(mut self, gate: usize, lane_type: LaneType) -> Self {
+        self.lane_types.insert(gate, lane_type);
+        self
+    }
+
+    /// Sens de circulation desservi par la porte dont le numéro est donné.
+    /// Si cette méthode n'est pas appelée pour une porte, elle dessert le sens 1.
+    #[allow(unused)]
+    pub fn lane_direction(mut self, gate: usize, direction: Direction) -> Self {
+        self.directions.insert(gate, direction);
+        self
+    }
+
+    /// Rend réversibles les portes dont les numéros sont donnés : elles desservent
+    /// à chaque heure le sens donné par l'horaire, et changent de sens
+    /// une fois vidées des véhicules de l'autre sens.
+    ///
+    /// ```
+    /// # use rsy40::direction::Direction;
+    /// # use rsy40::toll::{Toll, DEFAULT_ARRIVAL_RATES};
+    /// use Direction::*;
+    /// let mut schedule = [Outbound; 24];
+    /// schedule[16..20].fill(Inbound); // pointe du soir dans le sens 2
+    /// let toll = Toll::builder()
+    ///     .nb_gates(10)
+    ///     .lane_direction(6, Inbound)
+    ///     .lane_direction(7, Inbound)
+    ///     .lane_direction(8, Inbound)
+    ///     .lane_direction(9, Inbound)
+    ///     .reversible_lanes(&[4, 5], schedule)
+    ///     .arrival_rates(Inbound, DEFAULT_ARRIVAL_RATES)
+    ///     .build()?;
+    /// # Ok::<(), rsy40::error::Error>(())
+    /// ```
+    #[allow(unused)]
+    pub fn reversible_lanes(mut self, gates: &[usize], schedule: [Direction; 24]) -> Self {
+        self.reversible = gates.to_vec();
+        self.reversal_schedule = Some(schedule);
+        self
+    }
+
+    /// Relève l'état de chaque porte (longueur de la file, ouverture, occupation,
+    /// panne, arrivées depuis le relevé précédent) à l'intervalle donné,
+    /// en temps simulé, et l'enregistre dans la table `snapshot`.
+    /// L'intervalle doit être strictement positif et un enregistrement doit avoir lieu.
+    /// Si cette méthode n'est pas appelée, aucun relevé n'est fait.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use rsy40::toll::Toll;
+    /// let toll = Toll::builder()
+    ///     .set_logger("toll")
+    ///     .snapshots(Duration::from_secs(5 * 60))
+    ///     .build()?;
+    /// # Ok::<(), rsy40::error::Error>(())
+    /// ```
+    #[allow(unused)]
+    pub fn snapshots(mut self, interval: Duration) -> Self {
+        self.snapshot_interval = Some(interval);
+        self
+    }
+
+    /// Enregistre, en plus des véhicules ayant quitté le péage, chaque événement
+    /// de la simulation avec son heure simulée dans la table `event` :
+    /// arrivée d'un véhicule, choix de sa file, arrivée à la porte, paiement,
+    /// départ, ainsi que l'ouverture, la fermeture, le changement de sens,
+    /// la panne et la réparation des portes.
+    /// Nécessite un enregistrement (`.set_logger()`, `.sink()` ou `.shared_logger()`).
+    /// Si cette méthode n'est pas appelée, les événements ne sont pas enregistrés.
+    ///
+    /// ```no_run
+    /// # use rsy40::toll::Toll;
+    /// let toll = Toll::builder()
+    ///     .set_logger("toll")
+    ///     .event_log()
+    ///     .build()?;
+    /// # Ok::<(), rsy40::error::Error>(())
+    /// ```
+    #[allow(unused)]
+    pub fn event_log(mut self) -> Self {
+        self.event_log = true;
+        self
+    }
+
+    /// Graine du générateur aléatoire de la simulation, enregistrée avec le run.
+    /// Les tirages du péage, de ses portes et de leurs pannes en découlent ;
+    /// l'ordre des véhicules entre les threads peut néanmoins varier d'une
+    /// exécution à l'autre.
+    /// Si cette méthode n'est pas appelée, une graine est tirée au hasard.
+    #[allow(unused)]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Taux d'arrivée des véhicules (par seconde) circulant dans le sens donné,
+    /// pour chaque heure de la journée (voir `Toll::next_arrival()`).
+    /// Si cette méthode n'est pas appelée, le sens 1 suit le profil par défaut
+    /// et aucun véhicule n'arrive dans le sens 2.
+    #[allow(unused)]
+    pub fn arrival_rates(mut self, direction: Direction, rates: [f64; 24]) -> Self {
+        self.arrival_rates[direction as usize] = Some(rates);
+        self
+    }
+
+    /// Manière de faire fonctionner les portes : toutes servies par un même thread,
+    /// ou chacune par son propre thread.
+    /// Si cette méthode n'est pas appelée, les portes sont servies par un ordonnanceur.
+    #[allow(unused)]
+    pub fn execution(mut self, model: ExecutionModel) -> Self {
+        self.execution = model;
+        self
+    }
+
+    /// Fait servir les portes par l'ordonnanceur donné, partagé avec d'autres péages,
+    /// plutôt que par un ordonnanceur propre au péage.
+    /// Sans effet si les portes ont chacune leur thread.
+    #[allow(unused)]
+    pub fn scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Grille tarifaire du péage.
+    /// Si cette méthode n'est pas appelée, le tarif par défaut est utilisé.
+    #[allow(unused)]
+    pub fn tariff(mut self, tariff: Tariff) -> Self {
+        self.tariff = tariff;
+        self
+    }
+
+    /// Simule la fraude au péage (passages collés, fraude au badge,
+    /// fausse déclaration de classe) et sa détection.
+    /// Si cette méthode n'est pas appelée, tous les véhicules paient ce qu'ils doivent.
+    #[allow(unused)]
+    pub fn enforcement(mut self, config: EnforcementConfig) -> Self {
+        self.enforcement = Some(config);
+        self
+    }
+
+    /// Classe automatiquement les véhicules aux portes à l'aide de capteurs,
+    /// dont les erreurs font facturer une classe différente de la classe réelle.
+    /// Si cette méthode n'est pas appelée, la classe facturée est toujours
+    /// la classe réelle du véhicule.
+    #[allow(unused)]
+    pub fn classification(mut self, config: ClassificationConfig) -> Self {
+        self.classification = Some(config);
+        self
+    }
+
+    /// Manière d'ouvrir la base de données donnée par `.set_logger()`
+    /// si elle existe déjà : compléter ses enregistrements ou la remplacer.
+    /// Si cette méthode n'est pas appelée et que la base existe déjà,
+    /// la construction échoue plutôt que de choisir à la place de l'utilisateur.
+    #[allow(unused)]
+    pub fn open_mode(mut self, mode: OpenMode) -> Self {
+        self.open_mode = Some(mode);
+        self
+    }
+
+    /// Regroupement des enregistrements en base de données en transactions.
+    /// Sans effet si la base de données est partagée avec `.shared_logger()`.
+    /// Si cette méthode n'est pas appelée, la configuration par défaut est utilisée.
+    #[allow(unused)]
+    pub fn logger_config(mut self, config: LoggerConfig) -> Self {
+        self.logger_config = config;
+        self
+    }
+
+    /// Enregistre également les opérations du péage vers la destination donnée
+    /// (fichiers CSV ou JSON Lines, mémoire...).
+    /// Peut être appelée plusieurs fois pour enregistrer vers plusieurs destinations,
+    /// en plus de la base de données donnée par `.set_logger()`.
+    ///
+    /// ```no_run
+    /// # use rsy40::toll::Toll;
+    /// # use rsy40::sink::{CsvSink, MemorySink};
+    /// let memory = MemorySink::new();
+    /// let toll = Toll::builder()
+    ///     .set_logger("toll")
+    ///     .sink(CsvSink::new("resultats").unwrap())
+    ///     .sink(memory.clone())
+    ///     .build()?;
+    /// # Ok::<(), rsy40::error::Error>(())
+    /// ```
+    #[allow(unused)]
+    pub fn sink<S: OutputSink + 'static>(mut self, sink: S) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Enregistre les opérations du péage dans une base de données
+    /// partagée avec d'autres péages (voir `TollDatabase::for_plaza()`).
+    /// Remplace la base de données donnée par `.set_logger()`
+    /// et les destinations données par `.sink()`.
+    #[allow(unused)]
+    pub fn shared_logger(mut self, database: TollDatabase) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Transmet au réseau autoroutier, par le Sender donné, les véhicules
+    /// qui quittent le péage et poursuivent leur trajet vers un autre péage
+    #[allow(unused)]
+    pub fn onward(mut self, sender: Sender<Vehicle>) -> Self {
+        self.onward = Some(sender);
+        self
+    }
+
     /// Spécifie que les opérations au péage seront enregistrées dans la
     /// base de données dont le nom est spécifié en argument
     /// Il n'est pas obligé de renseigner l'extension de la base de données.
     ///
-    /// Si cette méthode n'est pas appelée, aucun enregistrement n'aura lieu
-    /// lors de la simulation.
+    /// Si ni cette méthode ni `.sink()` ne sont appelées,
+    /// aucun enregistrement n'aura lieu lors de la simulation.
     ///
     /// Si cette méthode est appelée plusieurs fois, une seule base de données
     /// est crée, avec le nom donné lors du dernier appel
     ///
-    /// ```
+    /// ```no_run
+    /// # use rsy40::toll::Toll;
     /// let toll = Toll::builder()
     ///     .set_logger("db") // cette db ne sera jamais ouverte
     ///     .set_logger("other_db")
-    ///     .build();
+    ///     .build()?;
+    /// # Ok::<(), rsy40::error::Error>(())
     /// ```
     #[allow(unused)]
     pub fn set_logger(mut self, s: &str) -> Self {
@@ -197,4 +1409,131 @@ impl TollBuilder {
         self.logger_name = Some(name);
         self
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::MemorySink;
+
+    #[test]
+    fn default_configuration_is_valid() {
+        assert_eq!(Toll::builder().validate(), Vec::new());
+    }
+
+    #[test]
+    fn plaza_without_gate_is_rejected() {
+        assert_eq!(Toll::builder().nb_gates(0).validate(), vec![ConfigError::NoGates]);
+    }
+
+    #[test]
+    fn single_carpool_lane_cannot_serve_cash_vehicles() {
+        let hours: Vec<usize> = (0..24).filter(|&hour| DEFAULT_ARRIVAL_RATES[hour] > 0.0).collect();
+        assert_eq!(
+            Toll::builder().nb_gates(1).validate(),
+            vec![ConfigError::NoMixedLane { direction: Direction::Outbound, hours }],
+        );
+        let mixed = Toll::builder().nb_gates(1).lane_type(0, LaneType::Mixed);
+        assert_eq!(mixed.validate(), Vec::new());
+    }
+
+    #[test]
+    fn zero_acceleration_is_rejected() {
+        assert_eq!(
+            Toll::builder().acceleration_factor(0).validate(),
+            vec![ConfigError::ZeroAcceleration],
+        );
+    }
+
+    #[test]
+    fn snapshots_need_a_positive_interval_and_a_destination() {
+        assert_eq!(
+            Toll::builder().snapshots(Duration::ZERO).validate(),
+            vec![
+                ConfigError::NotPositive("the snapshot interval"),
+                ConfigError::Logging("snapshots are requested but nothing is logged"),
+            ],
+        );
+        let logged = Toll::builder()
+            .sink(MemorySink::new())
+            .snapshots(Duration::from_secs(60));
+        assert_eq!(logged.validate(), Vec::new());
+    }
+
+    #[test]
+    fn event_log_needs_a_destination() {
+        assert_eq!(
+            Toll::builder().event_log().validate(),
+            vec![ConfigError::Logging("the event log is requested but nothing is logged")],
+        );
+    }
+
+    #[test]
+    fn shared_logger_cannot_be_combined_with_sinks() {
+        let database = TollDatabase::with_sink(Box::new(MemorySink::new()), LoggerConfig::default());
+        let builder = Toll::builder()
+            .sink(MemorySink::new())
+            .shared_logger(database);
+        assert_eq!(
+            builder.validate(),
+            vec![ConfigError::Logging("the shared logger replaces the database and sinks given to the plaza")],
+        );
+    }
+
+    #[test]
+    fn nan_probabilities_are_rejected() {
+        let failure = FailureModel { reroute_probability: f64::NAN, ..Default::default() };
+        assert_eq!(
+            Toll::builder().failure_model(failure).validate(),
+            vec![ConfigError::InvalidFailureModel(None)],
+        );
+        let mut enforcement = EnforcementConfig::default();
+        enforcement.badge_fraud[2] = f64::NAN;
+        assert_eq!(Toll::builder().enforcement(enforcement).validate(), vec![ConfigError::InvalidEnforcement]);
+        let free_flow = FreeFlowConfig { plate_read_rate: f64::NAN, ..Default::default() };
+        assert_eq!(Toll::builder().free_flow(free_flow).validate(), vec![ConfigError::InvalidFreeFlow]);
+    }
+
+    #[test]
+    fn huge_service_times_are_rejected() {
+        let huge = ServiceDistribution::Normal { mean: 1e300, std_dev: 1.0, min: 0.0 };
+        assert_eq!(
+            Toll::builder().payment_time(PaymentMean::Cash, huge).validate(),
+            vec![ConfigError::InvalidDistribution { gate: None, mean: PaymentMean::Cash }],
+        );
+        let ticket = TicketConfig { issue_time: ServiceDistribution::Gamma { shape: 1e200, scale: 1e200 } };
+        assert_eq!(Toll::builder().entry(ticket).validate(), vec![ConfigError::InvalidIssueTime]);
+    }
+
+    #[test]
+    fn approach_and_merge_parameters_must_be_positive() {
+        let approach = ApproachConfig { speed: 0.0, visibility: f32::NAN, ..Default::default() };
+        assert_eq!(
+            Toll::builder().approach(approach).validate(),
+            vec![
+                ConfigError::NotPositive("the approach speed"),
+                ConfigError::NotPositive("the approach visibility"),
+            ],
+        );
+        let merge = MergeConfig { capacity: 0, headway: Duration::ZERO, ..Default::default() };
+        assert_eq!(
+            Toll::builder().merge(merge).validate(),
+            vec![
+                ConfigError::NotPositive("the merge capacity"),
+                ConfigError::NotPositive("the merge headway"),
+            ],
+        );
+    }
+
+    #[test]
+    fn config_string_does_not_depend_on_insertion_order() {
+        let payment_time = ServiceDistribution::Deterministic(Duration::from_secs(3));
+        let builder = |gates: Vec<usize>| gates.into_iter()
+            .fold(Toll::builder().nb_gates(20), |builder, gate| builder
+                .lane_type(gate, LaneType::Mixed)
+                .lane_direction(gate, Direction::Inbound)
+                .gate_payment_time(gate, PaymentMean::Toll, payment_time.clone()));
+        let config = builder((0..20).collect()).config_string();
+        assert!(config.starts_with(&format!("rsy40 {} TollBuilder {{", VERSION)));
+        assert_eq!(config, builder((0..20).rev().collect()).config_string());
+    }
+}
@@ -18,13 +18,14 @@ pub struct SimpleTime {
 impl Default for SimpleTime {
     /// Par défaut, SimpleTime vaut 07h 00m 00s au jour 0
     /// ```
+    /// # use rsy40::toll_clock::SimpleTime;
     /// let t = SimpleTime {
     ///     day: 0,
     ///     hour: 7,
     ///     minute: 0,
     ///     second: 0,
     /// };
-    /// assert_eq!(t, SimpleTime::default())
+    /// assert_eq!(t.to_timestamp(), SimpleTime::default().to_timestamp())
     /// ```
     fn default() -> Self {
         Self {
@@ -95,6 +96,24 @@ impl SimpleTime {
             self.day, self.hour, self.minute, self.second
         )
     }
+
+    /// Lit un temps écrit par `.to_timestamp()`, None s'il n'est pas à ce format
+    pub fn from_timestamp(timestamp: &str) -> Option<Self> {
+        let (day, time) = timestamp.split_once('T')?;
+        let mut fields = time.splitn(3, ':').map(str::parse::<u32>);
+        Some(Self {
+            day: day.parse().ok()?,
+            hour: fields.next()?.ok()?,
+            minute: fields.next()?.ok()?,
+            second: fields.next()?.ok()?,
+        })
+    }
+
+    /// Nombre total de secondes écoulées depuis le jour 0 à 00h00m00s
+    pub fn as_secs(&self) -> u64 {
+        ((self.day as u64 * 24 + self.hour as u64) * 60 + self.minute as u64) * 60
+            + self.second as u64
+    }
 }
 
 /// Sert à conserver le temps qui s'écoule depuis la création
@@ -122,19 +141,25 @@ impl TollClock {
     /// Renvoie un objet SimpleTime représentant l'heure qu'il est
     /// dans la simulation par rapport à cet objet
     /// ```
-    /// let clock = TollClock {
-    ///     acceleration_factor: 2,
-    ///     clock: SimpleTime::default(),
-    ///     last_tick: Instant::now()
-    /// }
+    /// # use std::thread;
+    /// # use std::time::Duration;
+    /// # use rsy40::toll_clock::{SimpleTime, TollClock};
+    /// let mut clock = TollClock::default();
+    /// clock.acceleration_factor = 2;
     /// thread::sleep(Duration::from_secs(2));
     /// let now = clock.now();
-    /// assert_eq!(now, SimpleTime::default() + Duration::from_secs(4));
+    /// assert!(now.as_secs() >= (SimpleTime::default() + Duration::from_secs(4)).as_secs());
     /// ```
     pub fn now(&self) -> SimpleTime {
         let elapsed = self.last_tick.elapsed();
         self.clock.clone() + elapsed * self.acceleration_factor
     }
+
+    /// Convertit une durée de la simulation en durée réelle
+    /// (c'est-à-dire divisée par le facteur d'accélération)
+    pub fn real_duration(&self, simulated: Duration) -> Duration {
+        simulated / self.acceleration_factor
+    }
 }
 
 impl Default for TollClock {
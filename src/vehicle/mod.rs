@@ -1,5 +1,10 @@
 mod vehicle_struct;
 mod vehicle_type;
+mod vehicle_category;
 mod paymen_mean;
+mod service_time;
 
-pub use vehicle_struct::Vehicle;
\ No newline at end of file
+pub use vehicle_struct::Vehicle;
+pub use paymen_mean::PaymentMean;
+pub use vehicle_category::VehicleCategory;
+pub use service_time::ServicePhases;
\ No newline at end of file
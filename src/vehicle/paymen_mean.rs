@@ -5,7 +5,7 @@ use crate::vehicle::paymen_mean::PaymentMean::{Cash, Toll};
 use crate::vehicle::vehicle_type::VehicleType;
 use crate::vehicle::vehicle_type::VehicleType::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum PaymentMean {
     Cash,
     Toll, // télépéage
@@ -0,0 +1,169 @@
+use std::time::Duration;
+use lazy_static::lazy_static;
+use rand::prelude::*;
+use rand_distr::Normal;
+use crate::vehicle::paymen_mean::PaymentMean;
+use crate::vehicle::vehicle_type::VehicleType;
+
+/// Temps passé par un véhicule à la porte du péage, découpé en trois phases.
+/// La porte ne peut pas accueillir le véhicule suivant avant la fin
+/// de la dernière phase.
+#[derive(Debug, Clone)]
+pub struct ServicePhases {
+    /// Temps pour s'avancer jusqu'à la borne de paiement
+    pub approach: Duration,
+    /// Temps de paiement
+    pub payment: Duration,
+    /// Temps pour franchir la barrière et attendre qu'elle se referme
+    pub clearance: Duration,
+}
+
+impl ServicePhases {
+    pub fn total(&self) -> Duration {
+        self.approach + self.payment + self.clearance
+    }
+}
+
+/// Loi normale tronquée par une valeur minimale (en secondes)
+struct PhaseDistribution {
+    normal: Normal<f32>,
+    min: f32,
+}
+
+impl PhaseDistribution {
+    fn new(mean: f32, std_dev: f32, min: f32) -> Self {
+        Self { normal: Normal::new(mean, std_dev).unwrap(), min }
+    }
+
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
+        Duration::from_secs_f32(self.normal.sample(rng).max(self.min))
+    }
+}
+
+/// Lois des trois phases pour une classe de véhicule et un moyen de paiement
+struct PhaseDistributions {
+    approach: PhaseDistribution,
+    payment: PhaseDistribution,
+    clearance: PhaseDistribution,
+}
+
+/// Crée les lois (espérance, écart-type, minimum) des phases
+/// d'approche, de paiement et de dégagement
+fn phases(
+    approach: (f32, f32, f32), payment: (f32, f32, f32), clearance: (f32, f32, f32),
+) -> PhaseDistributions {
+    PhaseDistributions {
+        approach: PhaseDistribution::new(approach.0, approach.1, approach.2),
+        payment: PhaseDistribution::new(payment.0, payment.1, payment.2),
+        clearance: PhaseDistribution::new(clearance.0, clearance.1, clearance.2),
+    }
+}
+
+lazy_static!(
+    /// Lois des phases indexées par classe de véhicule,
+    /// puis par moyen de paiement (espèces/carte, télépéage)
+    static ref PHASES: [[PhaseDistributions; 2]; 5] = [
+        // léger
+        [
+            phases((6.0, 2.0, 2.0), (45.0, 10.0, 20.0), (5.0, 1.0, 3.0)),
+            phases((4.0, 1.0, 2.0), (20.0, 5.0, 8.0), (5.0, 1.0, 3.0)),
+        ],
+        // intermédiaire
+        [
+            phases((8.0, 2.0, 3.0), (48.0, 10.0, 20.0), (6.0, 1.5, 4.0)),
+            phases((5.0, 1.5, 2.0), (21.0, 5.0, 8.0), (6.0, 1.5, 4.0)),
+        ],
+        // poids lourd 2 essieux
+        [
+            phases((12.0, 3.0, 5.0), (55.0, 12.0, 25.0), (10.0, 2.0, 6.0)),
+            phases((8.0, 2.0, 4.0), (22.0, 5.0, 10.0), (10.0, 2.0, 6.0)),
+        ],
+        // poids lourd 4 essieux
+        [
+            phases((15.0, 4.0, 6.0), (58.0, 12.0, 25.0), (13.0, 3.0, 8.0)),
+            phases((10.0, 2.5, 5.0), (23.0, 5.0, 10.0), (13.0, 3.0, 8.0)),
+        ],
+        // moto
+        [
+            phases((5.0, 2.0, 2.0), (50.0, 12.0, 20.0), (3.0, 1.0, 2.0)),
+            phases((3.0, 1.0, 1.0), (20.0, 5.0, 8.0), (3.0, 1.0, 2.0)),
+        ],
+    ];
+);
+
+impl ServicePhases {
+    /// Tire aléatoirement les durées des phases pour un véhicule de la classe
+    /// et du moyen de paiement donnés.
+    /// Chaque phase suit une loi normale tronquée propre à la classe
+    /// et au moyen de paiement.
+    pub fn sample<R: Rng + ?Sized>(
+        rng: &mut R, vtype: VehicleType, mean: PaymentMean,
+    ) -> Self {
+        let dists = &PHASES[vtype as usize][mean as usize];
+        Self {
+            approach: dists.approach.sample(rng),
+            payment: dists.payment.sample(rng),
+            clearance: dists.clearance.sample(rng),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use crate::vehicle::{Vehicle, VehicleCategory};
+    use crate::vehicle::vehicle_type::VehicleType::*;
+    use super::*;
+
+    /// Durées moyennes, en secondes, des phases d'approche, de paiement
+    /// et de dégagement sur un grand nombre de tirages
+    fn mean_phases(rng: &mut StdRng, vtype: VehicleType, mean: PaymentMean) -> [f32; 3] {
+        let n = 2000;
+        let mut sums = [0.0; 3];
+        for _ in 0..n {
+            let phases = ServicePhases::sample(rng, vtype, mean);
+            assert_eq!(phases.total(), phases.approach + phases.payment + phases.clearance);
+            sums[0] += phases.approach.as_secs_f32();
+            sums[1] += phases.payment.as_secs_f32();
+            sums[2] += phases.clearance.as_secs_f32();
+        }
+        sums.map(|sum| sum / n as f32)
+    }
+
+    #[test]
+    fn phases_depend_on_class_and_payment_mean() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let light = mean_phases(&mut rng, Light, PaymentMean::Cash);
+        let heavy = mean_phases(&mut rng, HeavyTruck, PaymentMean::Cash);
+        let badge = mean_phases(&mut rng, Light, PaymentMean::Toll);
+        // un poids lourd met plus de temps à s'avancer et à franchir la barrière
+        assert!((light[0] - 6.0).abs() < 0.5 && (heavy[0] - 15.0).abs() < 0.5);
+        assert!((light[2] - 5.0).abs() < 0.5 && (heavy[2] - 13.0).abs() < 0.5);
+        // le télépéage réduit le temps de paiement, pas celui de dégagement
+        assert!((light[1] - 45.0).abs() < 1.0 && (badge[1] - 20.0).abs() < 1.0);
+        assert!((badge[2] - light[2]).abs() < 0.5);
+    }
+
+    #[test]
+    fn phases_are_truncated_at_their_minimum() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..2000 {
+            let phases = ServicePhases::sample(&mut rng, Motorcycle, PaymentMean::Toll);
+            assert!(phases.approach >= Duration::from_secs(1));
+            assert!(phases.payment >= Duration::from_secs(8));
+            assert!(phases.clearance >= Duration::from_secs(2));
+        }
+    }
+
+    #[test]
+    fn priority_vehicles_do_not_pay() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut vehicle: Vehicle = rng.gen();
+        vehicle.category = VehicleCategory::Police;
+        let phases = vehicle.service_phases(&mut rng);
+        assert_eq!(phases.payment, Duration::ZERO);
+        assert!(phases.approach > Duration::ZERO && phases.clearance > Duration::ZERO);
+        vehicle.category = VehicleCategory::Regular;
+        assert!(vehicle.service_phases(&mut rng).payment > Duration::ZERO);
+    }
+}
@@ -0,0 +1,42 @@
+use lazy_static::lazy_static;
+use rand::distributions::{Standard, WeightedIndex};
+use rand::prelude::*;
+use crate::vehicle::vehicle_category::VehicleCategory::*;
+
+/// Catégorie particulière d'un véhicule.
+/// Les véhicules d'urgence et de service sont prioritaires au péage
+/// et ne paient pas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VehicleCategory {
+    Regular = 0,
+    Ambulance,
+    Police,
+    Gendarmerie,
+    /// Véhicule de patrouille de la société d'autoroute
+    Patrol,
+}
+
+lazy_static!(
+    static ref DIST: WeightedIndex<i32> = WeightedIndex::new([9950, 15, 10, 10, 15]).unwrap();
+);
+
+impl VehicleCategory {
+    /// Renvoie vrai si le véhicule est prioritaire au péage
+    pub fn is_priority(&self) -> bool {
+        *self != Regular
+    }
+}
+
+impl Distribution<VehicleCategory> for Standard {
+    /// Renvoie une catégorie de véhicule aléatoire avec les pondérations suivantes :
+    /// - 99.5% de véhicules ordinaires
+    /// - 0.15% d'ambulances
+    /// - 0.1% de véhicules de police
+    /// - 0.1% de véhicules de gendarmerie
+    /// - 0.15% de véhicules de patrouille
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> VehicleCategory {
+        static CATEGORIES: [VehicleCategory; 5] =
+            [Regular, Ambulance, Police, Gendarmerie, Patrol];
+        CATEGORIES[DIST.sample(rng)]
+    }
+}
@@ -2,15 +2,25 @@ use lazy_static::lazy_static;
 use rand::distributions::{Bernoulli, Standard};
 use rand::prelude::*;
 use rand_distr::{Geometric, Normal};
-use std::cmp::{max, min};
+use std::cmp::min;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
+use crate::direction::Direction;
+use crate::enforcement::Evasion;
+use crate::network::Trip;
+use crate::toll_clock::SimpleTime;
 use crate::vehicle::paymen_mean::PaymentMean;
+use crate::vehicle::service_time::ServicePhases;
+use crate::vehicle::vehicle_category::VehicleCategory;
 use crate::vehicle::vehicle_type::VehicleType;
 use crate::vehicle::vehicle_type::VehicleType::*;
 
 /// Représente un véhicule
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Vehicle {
+    /// Numéro du véhicule, attribué à son arrivée au premier péage qu'il traverse.
+    /// Vaut 0 tant que le véhicule n'est arrivé à aucun péage
+    pub id: u64,
     /// Nombre de personnes à bord du véhicule
     pub nb_passengers: u8,
     pub taxi: bool,
@@ -23,6 +33,18 @@ pub struct Vehicle {
     /// nombre de kilomètres parcourus par le véhicule entre son entrée
     /// sur l'autoroute et son arrivée au péage
     pub nb_kilometres: f32,
+    /// Catégorie particulière du véhicule (urgence, forces de l'ordre...)
+    pub category: VehicleCategory,
+    /// Fraude décidée par le conducteur avant d'arriver au péage
+    pub evasion: Option<Evasion>,
+    /// Sens de circulation du véhicule
+    pub direction: Direction,
+    /// Heure de délivrance du ticket pris à la gare d'entrée,
+    /// None si le véhicule n'est passé par aucune gare d'entrée
+    pub ticket: Option<SimpleTime>,
+    /// Trajet du véhicule dans un réseau de péages,
+    /// None si le véhicule ne traverse qu'un péage isolé
+    pub trip: Option<Trip>,
 }
 
 lazy_static!(
@@ -32,9 +54,11 @@ lazy_static!(
     static ref TAXI_RNG: Bernoulli = Bernoulli::new(0.05).unwrap();
     static ref NB_KM_RNG_LIGHT: Normal<f32> = Normal::new(60.0, 10.0).unwrap();
     static ref NB_KM_RNG_HEAVY: Normal<f32> = Normal::new(76.0, 10.0).unwrap();
-    static ref PAYMENT_TIME_RNG: Normal<f32> = Normal::new(60.0, 10.0).unwrap();
 );
 
+/// Dernier numéro attribué à un véhicule, commun à tous les péages
+static LAST_ID: AtomicU64 = AtomicU64::new(0);
+
 impl Distribution<Vehicle> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Vehicle {
         let vtype = rng.gen::<VehicleType>();
@@ -54,37 +78,56 @@ impl Distribution<Vehicle> for Standard {
             Light | Motorcycle => NB_KM_RNG_LIGHT.sample(rng),
             _ => NB_KM_RNG_HEAVY.sample(rng),
         };
+        let category = match vtype {
+            Light | Medium => rng.gen::<VehicleCategory>(),
+            _ => VehicleCategory::Regular,
+        };
         Vehicle {
+            id: 0,
             nb_passengers,
             taxi,
             low_carbon,
             payment_mean: PaymentMean::rand_from_vehicle_type(rng, &vtype),
             type_: vtype,
             nb_kilometres,
+            category,
+            evasion: None,
+            direction: Direction::default(),
+            ticket: None,
+            trip: None,
         }
     }
 }
 
 impl Vehicle {
+    /// Attribue un numéro au véhicule s'il n'en a pas encore.
+    /// Un véhicule traversant plusieurs péages d'un réseau garde le même numéro.
+    pub fn identify(&mut self) {
+        if self.id == 0 {
+            self.id = LAST_ID.fetch_add(1, Ordering::Relaxed) + 1;
+        }
+    }
+
     /// Renvoie true si le véhicule peut prendre la voie de covoiturage, sinon false
     #[inline(always)]
     pub fn carpooling(&self) -> bool {
         self.nb_passengers > 1 || self.taxi || self.low_carbon
     }
 
-    /// Renvoie le temps pris par le conducteur pour payer le péage.
-    /// Le temps est généré aléatoirement suivant une loi normale d'espérance
-    /// 60 (secondes) et de variance 10.
-    /// Pour les véhicules dotés du télépéage, le temps est divisé par deux.
+    /// Renvoie le temps passé par le véhicule à la porte du péage,
+    /// découpé en phases d'approche, de paiement et de dégagement.
+    /// Chaque phase suit une loi normale tronquée dont les paramètres dépendent
+    /// de la classe du véhicule et de son moyen de paiement :
+    /// un poids lourd met plus de temps à s'avancer et à franchir la barrière,
+    /// le télépéage réduit le temps de paiement.
     ///
-    /// Le temps de paiement n'est jamais inférieur à 30 secondes (15 avec le télépéage)
-    pub fn payment_duration<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
-        let seconds = max(30, PAYMENT_TIME_RNG.sample(rng) as u64);
-        let duration = Duration::from_secs(seconds);
-        match self.payment_mean {
-            PaymentMean::Cash => duration,
-            PaymentMean::Toll => duration / 2,
+    /// Les véhicules prioritaires ne paient pas : leur temps de paiement est nul.
+    pub fn service_phases<R: Rng + ?Sized>(&self, rng: &mut R) -> ServicePhases {
+        let mut phases = ServicePhases::sample(rng, self.type_, self.payment_mean);
+        if self.category.is_priority() {
+            phases.payment = Duration::ZERO;
         }
+        phases
     }
 
     /// Renvoie l'entier de numérotation de la classe du véhicule
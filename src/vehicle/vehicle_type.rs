@@ -13,7 +13,7 @@ pub enum VehicleType {
 }
 
 lazy_static!(
-    static ref DIST: WeightedIndex<i32> = WeightedIndex::new(&[80, 10, 5, 4, 1]).unwrap();
+    static ref DIST: WeightedIndex<i32> = WeightedIndex::new([80, 10, 5, 4, 1]).unwrap();
 );
 
 impl Distribution<VehicleType> for Standard {